@@ -0,0 +1,205 @@
+#!/usr/bin/env python3
+# Checks that an engine follows docs/engine-protocol.md. The engine is the command given on
+# the command line, by default this program's own:
+#
+#     scripts/engine_conformance.py target/debug/tic_tac_toe --engine
+#
+# Every check starts a new engine. The script prints one line per check and exits with 1 if
+# any of them failed.
+
+import queue
+import subprocess
+import sys
+import threading
+import time
+
+TIMEOUT = 10
+
+
+class Engine:
+    def __init__(self, command):
+        self.process = subprocess.Popen(command, stdin=subprocess.PIPE, stdout=subprocess.PIPE,
+                                        text=True, bufsize=1)
+        self.lines = queue.Queue()
+        threading.Thread(target=self.read, daemon=True).start()
+
+    def read(self):
+        for line in self.process.stdout:
+            self.lines.put(line.rstrip('\n'))
+        self.lines.put(None)
+
+    def send(self, command):
+        self.process.stdin.write(command + '\n')
+        self.process.stdin.flush()
+
+    # The next answer as (success, text)
+    def answer(self, timeout=TIMEOUT):
+        lines = []
+        while True:
+            line = self.lines.get(timeout=timeout)
+            if line is None:
+                raise AssertionError('the engine ended without an answer')
+            if line == '' and lines:
+                break
+            lines.append(line)
+        first = lines[0]
+        if first[:1] not in ('=', '?') or (len(first) > 1 and first[1] != ' '):
+            raise AssertionError("'%s' doesn't start with '= ' or '? '" % first)
+        text = '\n'.join([first[2:]] + lines[1:]).strip('\n')
+        return first[0] == '=', text
+
+    def ask(self, command):
+        self.send(command)
+        return self.answer()
+
+    def ok(self, command):
+        success, text = self.ask(command)
+        if not success:
+            raise AssertionError("'%s' failed: %s" % (command, text))
+        return text
+
+    def fails(self, command):
+        success, text = self.ask(command)
+        if success:
+            raise AssertionError("'%s' should fail, but answered '%s'" % (command, text))
+
+    def close(self):
+        try:
+            self.send('quit')
+            self.process.wait(timeout=TIMEOUT)
+        except (OSError, subprocess.TimeoutExpired):
+            self.process.kill()
+
+
+def expect(actual, expected):
+    if actual != expected:
+        raise AssertionError("expected '%s', got '%s'" % (expected, actual))
+
+
+def check_identification(engine):
+    expect(engine.ok('protocol_version'), '1')
+    engine.ok('name')
+    engine.ok('version')
+    commands = engine.ok('list_commands').split('\n')
+    for command in ('boardsize', 'to_win', 'rules', 'position', 'play', 'genmove', 'stop', 'quit'):
+        if command not in commands:
+            raise AssertionError("list_commands lacks '%s'" % command)
+    expect(engine.ok('known_command genmove'), 'true')
+    expect(engine.ok('known_command fly'), 'false')
+    engine.fails('fly')
+
+
+def check_comments_and_empty_lines(engine):
+    engine.send('')
+    engine.send('# nothing')
+    expect(engine.ok('protocol_version # the version'), '1')
+
+
+def check_play_and_result(engine):
+    engine.ok('boardsize 3x3')
+    engine.ok('to_win 3')
+    for cell in ('b2', 'a1', 'b1', 'a3'):
+        engine.ok('play ' + cell)
+    expect(engine.ok('result'), '*')
+    engine.ok('play b3')
+    expect(engine.ok('result'), '1-0')
+    engine.fails('play c1')
+
+
+def check_illegal_moves(engine):
+    engine.ok('boardsize 3x3')
+    engine.ok('play b2')
+    engine.fails('play b2')
+    engine.fails('play d1')
+    engine.fails('play a1 a2')
+    engine.fails('play 0,0=X')
+    expect(engine.ok('notation'), '3x3 3 standard 3/1x1/3 2')
+
+
+def check_numeric_cells(engine):
+    engine.ok('boardsize 3x3')
+    engine.ok('play 0,2')
+    expect(engine.ok('notation'), '3x3 3 standard 2x/3/3 2')
+
+
+def check_rules_and_undo(engine):
+    engine.ok('boardsize 6x6')
+    engine.ok('to_win 4')
+    engine.ok('rules standard stones=1/2')
+    engine.ok('play c3')
+    engine.fails('play d4')
+    engine.ok('play d4 d5')
+    engine.ok('undo')
+    expect(engine.ok('notation'), '6x6 4 standard 6/6/2x3/6/6/6 2 stones=1/2')
+    engine.ok('clear_board')
+    expect(engine.ok('notation'), '6x6 4 standard 6/6/6/6/6/6 1 stones=1/2')
+    engine.fails('rules chess')
+
+
+def check_position(engine):
+    engine.ok('position 3x3 3 wild x1o/3/3 1')
+    engine.ok('play c3=O')
+    expect(engine.ok('notation'), '3x3 3 wild x1o/3/2o 2')
+    engine.fails('position 3x3 3 standard x1o/3 2')
+
+
+def check_genmove(engine):
+    engine.ok('position 3x3 3 standard xx1/oo1/3 1')
+    expect(engine.ok('genmove movetime 1000'), 'c1')
+    expect(engine.ok('result'), '1-0')
+    engine.fails('genmove')
+    engine.ok('position 5x5 4 standard 5/5/5/5/5 1')
+    cells = engine.ok('genmove depth 2').split()
+    expect(len(cells), 1)
+    engine.fails('play ' + cells[0])
+
+
+def check_movetime(engine):
+    engine.ok('boardsize 15x15')
+    engine.ok('to_win 5')
+    engine.ok('play h8')
+    started = time.time()
+    engine.ok('genmove movetime 500')
+    if time.time() - started > 3:
+        raise AssertionError('genmove movetime 500 took %.1f seconds' % (time.time() - started))
+
+
+def check_stop(engine):
+    engine.ok('boardsize 9x9')
+    engine.ok('to_win 5')
+    engine.send('genmove depth 40')
+    time.sleep(0.2)
+    engine.send('stop')
+    success, cells = engine.answer(timeout=3)
+    if not success or not cells:
+        raise AssertionError('genmove answered %s' % cells)
+    # commands after a search are answered in order
+    expect(engine.ok('stop'), '')
+    if not engine.ok('notation').endswith(' 2'):
+        raise AssertionError('the turn of genmove was not played')
+
+
+CHECKS = [check_identification, check_comments_and_empty_lines, check_play_and_result,
+          check_illegal_moves, check_numeric_cells, check_rules_and_undo, check_position,
+          check_genmove, check_movetime, check_stop]
+
+
+def main():
+    command = sys.argv[1:] or ['cargo', 'run', '--quiet', '--', '--engine']
+    failed = 0
+    for check in CHECKS:
+        engine = Engine(command)
+        try:
+            check(engine)
+            print('ok    ' + check.__name__)
+        except (AssertionError, queue.Empty, OSError) as error:
+            failed += 1
+            print('FAIL  %s: %s' % (check.__name__, error or 'no answer in time'))
+        finally:
+            engine.close()
+    print('%d of %d checks passed' % (len(CHECKS) - failed, len(CHECKS)))
+    sys.exit(1 if failed else 0)
+
+
+if __name__ == '__main__':
+    main()
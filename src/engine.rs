@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use ::game::{CellState, GameState, Move};
+use ::game::grid::{checked_cell_count, Grid};
+use ::game::grid_observer::check_winner;
+use ::game::notation::{parse_notation, parse_variant, to_notation, variant_name};
+use ::game::record::{parse_stone, result_text, write_stone};
+use ::player::Player;
+use ::player::ki::{KiPlayer, Limits};
+
+// The version of the protocol described in docs/engine-protocol.md
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const COMMANDS: [&str; 19] = [
+    "protocol_version", "name", "version", "known_command", "list_commands", "quit",
+    "boardsize", "to_win", "rules", "position", "clear_board", "play", "undo", "genmove",
+    "stop", "showboard", "notation", "result", "evaluation",
+];
+
+// The ki as an engine that other programs drive through stdin and stdout, one command per
+// line, in the way of GTP for Go. See docs/engine-protocol.md.
+struct Engine {
+    // the rules of new boards: the size, the cells for a line, the variant and the options
+    // of the notation
+    size: Vec<usize>,
+    to_win: u32,
+    variant: String,
+    options: String,
+    start: Grid,
+    grid: Grid,
+    // the positions before every turn, for undo
+    history: Vec<Grid>,
+    evaluation: Option<i32>,
+}
+
+// What the engine waits for: a line of input, the end of it or the result of a search
+enum Event {
+    Line(String),
+    End,
+    Found(Option<Vec<Move>>, Option<i32>),
+}
+
+impl Engine {
+    fn new() -> Engine {
+        let grid = Grid::new(3, 3, 3);
+        Engine {
+            size: vec![3, 3],
+            to_win: 3,
+            variant: "standard".to_string(),
+            options: String::new(),
+            start: grid.clone(),
+            grid,
+            history: Vec::new(),
+            evaluation: None,
+        }
+    }
+
+    // Starts an empty board with the rules, or keeps the old one if they don't work
+    fn new_board(&mut self, size: Vec<usize>, to_win: u32, variant: &str,
+                 options: &str) -> Result<String, String> {
+        let grid = empty_board(&size, to_win, variant, options)?;
+        self.size = size;
+        self.to_win = to_win;
+        self.variant = variant.to_string();
+        self.options = options.to_string();
+        self.set_position(grid);
+        Ok(String::new())
+    }
+
+    fn set_position(&mut self, grid: Grid) {
+        self.start = grid.clone();
+        self.grid = grid;
+        self.history.clear();
+        self.evaluation = None;
+    }
+
+    // Runs every command but genmove and stop, which need the search thread
+    fn execute(&mut self, command: &str, arguments: &[&str]) -> Result<String, String> {
+        match (command, arguments.len()) {
+            ("protocol_version", 0) => Ok(PROTOCOL_VERSION.to_string()),
+            ("name", 0) => Ok("tic_tac_toe".to_string()),
+            ("version", 0) => Ok(env!("CARGO_PKG_VERSION").to_string()),
+            ("known_command", 1) => Ok(COMMANDS.contains(&arguments[0]).to_string()),
+            ("list_commands", 0) => Ok(COMMANDS.join("\n")),
+            ("stop", 0) => Ok(String::new()),
+            ("boardsize", 1) => {
+                let size: Vec<usize> = arguments[0].split('x')
+                    .map(|size| size.parse().ok().filter(|&size| size > 0))
+                    .collect::<Option<_>>()
+                    .filter(|size: &Vec<usize>| size.len() >= 2)
+                    .ok_or_else(|| format!("'{}' is not a board size like 15x15.", arguments[0]))?;
+                let (variant, options) = (self.variant.clone(), self.options.clone());
+                self.new_board(size, self.to_win, &variant, &options)
+            },
+            ("to_win", 1) => {
+                let to_win = arguments[0].parse().ok().filter(|&to_win| to_win > 0)
+                    .ok_or_else(|| format!("'{}' is not a number of cells.", arguments[0]))?;
+                let (variant, options) = (self.variant.clone(), self.options.clone());
+                self.new_board(self.size.clone(), to_win, &variant, &options)
+            },
+            ("rules", 1) | ("rules", 2) => {
+                parse_variant(arguments[0])?;
+                let options = arguments.get(1).cloned().unwrap_or("");
+                if options.split(',').any(|option| option.starts_with("handicap")) {
+                    return Err("A handicap is part of a position, not of the rules.".to_string());
+                }
+                let (size, to_win) = (self.size.clone(), self.to_win);
+                self.new_board(size, to_win, arguments[0], options)
+            },
+            ("position", _) if arguments.len() >= 5 => {
+                let grid = parse_notation(&arguments.join(" "))?;
+                self.size = grid.dimensions().to_vec();
+                self.to_win = grid.to_win;
+                self.variant = variant_name(grid.variant).to_string();
+                self.options = arguments.get(5).map_or(String::new(), |options| {
+                    let rules: Vec<&str> = options.split(',')
+                        .filter(|option| !option.starts_with("handicap"))
+                        .collect();
+                    rules.join(",")
+                });
+                self.set_position(grid);
+                Ok(String::new())
+            },
+            ("clear_board", 0) => {
+                let start = self.start.clone();
+                self.set_position(start);
+                Ok(String::new())
+            },
+            ("play", _) if !arguments.is_empty() => self.play(arguments),
+            ("undo", 0) => {
+                self.grid = self.history.pop().ok_or("There is no turn to take back.")?;
+                Ok(String::new())
+            },
+            ("showboard", 0) => Ok(format!("\n{}", self.grid.render_labelled(&[]).trim_end())),
+            ("notation", 0) => Ok(to_notation(&self.grid)),
+            ("result", 0) => Ok(result_text(&check_winner(&self.grid))),
+            ("evaluation", 0) => self.evaluation.map(|value| value.to_string())
+                .ok_or_else(|| "There was no search yet.".to_string()),
+            _ if COMMANDS.contains(&command) => Err(format!("Wrong arguments for {}.", command)),
+            _ => Err(format!("Unknown command '{}'.", command)),
+        }
+    }
+
+    // Plays all stones of a turn for the side to move
+    fn play(&mut self, stones: &[&str]) -> Result<String, String> {
+        if check_winner(&self.grid) != GameState::Mid {
+            return Err("The game is over.".to_string());
+        }
+        let grid = parse_turn(&self.grid, stones)?;
+        self.history.push(::std::mem::replace(&mut self.grid, grid));
+        Ok(String::new())
+    }
+
+    // Starts to search for the turn of the side to move in another thread. Gives back the
+    // flag that stops the search.
+    fn start_search(&self, arguments: &[&str],
+                    events: Sender<Event>) -> Result<Arc<AtomicBool>, String> {
+        if check_winner(&self.grid) != GameState::Mid {
+            return Err("The game is over.".to_string());
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut limits = Limits { stop: Some(stop.clone()), ..Limits::default() };
+        let mut arguments = arguments.iter();
+        while let Some(&name) = arguments.next() {
+            let value: u64 = arguments.next().and_then(|value| value.parse().ok())
+                .ok_or_else(|| format!("{} needs a number.", name))?;
+            match name {
+                "movetime" => limits.deadline = Some(Instant::now() + Duration::from_millis(value)),
+                "depth" if value > 0 => limits.depth = Some(value as u32),
+                _ => return Err(format!("'{}' is not a limit, use movetime or depth.", name)),
+            }
+        }
+        let grid = self.grid.clone();
+        thread::spawn(move || {
+            let side = grid.to_move();
+            let ki = KiPlayer::new(side.0);
+            let turn = ki.find_turn_within(&grid, side, &limits);
+            let _ = events.send(Event::Found(turn, ki.evaluation()));
+        });
+        Ok(stop)
+    }
+
+    // Plays the turn a search found
+    fn finish_search(&mut self, turn: Option<Vec<Move>>,
+                     evaluation: Option<i32>) -> Result<String, String> {
+        let turn = turn.ok_or("There is no turn to make.")?;
+        let mut grid = self.grid.clone();
+        for mov in &turn {
+            grid.make_move(mov.clone());
+        }
+        self.history.push(::std::mem::replace(&mut self.grid, grid));
+        self.evaluation = evaluation;
+        let stones: Vec<String> = turn.iter().map(write_stone).collect();
+        Ok(stones.join(" "))
+    }
+}
+
+// An empty board of the size, with the rules of the variant and the options of the notation
+pub(crate) fn empty_board(size: &[usize], to_win: u32, variant: &str,
+                          options: &str) -> Result<Grid, String> {
+    if size.len() < 2 || size.contains(&0) {
+        return Err("A board needs at least two dimensions.".to_string());
+    }
+    // the notation of a huge board would be huge itself
+    checked_cell_count(size)?;
+    let sizes: Vec<String> = size.iter().map(|size| size.to_string()).collect();
+    let row = size[size.len() - 1].to_string();
+    let rows = vec![row; size[size.len() - 2]].join("/");
+    let layers: usize = size[.. size.len() - 2].iter().product();
+    let notation = format!("{} {} {} {} 1 {}", sizes.join("x"), to_win, variant,
+                           vec![rows; layers].join("|"), options);
+    parse_notation(&notation)
+}
+
+// The position after the side to move places the stones, if they are a whole legal turn
+pub(crate) fn parse_turn(grid: &Grid, stones: &[&str]) -> Result<Grid, String> {
+    let side = grid.to_move();
+    if stones.len() as u32 != grid.stones_left() {
+        return Err(format!("This turn has {} stones.", grid.stones_left()));
+    }
+    let mut grid = grid.clone();
+    for &stone in stones {
+        let mov = parse_stone(stone, side)?;
+        if !grid.is_on_board(&mov.coord) {
+            return Err(format!("{} is not on the board.", stone));
+        }
+        if mov.symbol != side && !grid.variant.has_symbol_choice() {
+            return Err("The symbol can only be chosen in the wild and order-chaos variants."
+                       .to_string());
+        }
+        if *grid.get_cell_at(&mov.coord) != CellState::Unset || !grid.make_move(mov) {
+            return Err(format!("{} is not free.", stone));
+        }
+    }
+    Ok(grid)
+}
+
+// Answers the commands from `input` on `output` until 'quit' or the end of the input
+pub fn run<R, W>(input: R, mut output: W) -> io::Result<()>
+    where R: BufRead + Send + 'static, W: Write {
+    let (events, receiver) = mpsc::channel();
+    let lines = events.clone();
+    thread::spawn(move || {
+        for line in input.lines() {
+            match line {
+                Ok(line) => if lines.send(Event::Line(line)).is_err() {
+                    return;
+                },
+                Err(_) => break,
+            }
+        }
+        let _ = lines.send(Event::End);
+    });
+
+    let mut engine = Engine::new();
+    // the flag of the running search
+    let mut search: Option<Arc<AtomicBool>> = None;
+    // commands that came in during a search, they are answered after it
+    let mut waiting = VecDeque::new();
+    let mut ended = false;
+    loop {
+        let event = match waiting.pop_front() {
+            Some(line) if search.is_none() => Event::Line(line),
+            line => {
+                if let Some(line) = line {
+                    waiting.push_front(line);
+                }
+                if ended && search.is_none() {
+                    return Ok(());
+                }
+                match receiver.recv() {
+                    Ok(event) => event,
+                    Err(_) => return Ok(()),
+                }
+            },
+        };
+        let line = match event {
+            Event::Line(line) => line,
+            Event::End => {
+                ended = true;
+                continue;
+            },
+            Event::Found(turn, evaluation) => {
+                search = None;
+                respond(&mut output, engine.finish_search(turn, evaluation))?;
+                continue;
+            },
+        };
+        // anything after a '#' is a comment
+        let line = line.split('#').next().unwrap_or("").trim().to_string();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => continue,
+        };
+        if let Some(ref stop) = search {
+            match command {
+                "stop" => stop.store(true, Ordering::Relaxed),
+                "quit" => {
+                    stop.store(true, Ordering::Relaxed);
+                    waiting.push_back(line.clone());
+                },
+                _ => waiting.push_back(line.clone()),
+            }
+            continue;
+        }
+        match command {
+            "quit" => {
+                respond(&mut output, Ok(String::new()))?;
+                return Ok(());
+            },
+            "genmove" => match engine.start_search(arguments, events.clone()) {
+                Ok(stop) => search = Some(stop),
+                Err(error) => respond(&mut output, Err(error))?,
+            },
+            _ => {
+                let result = engine.execute(command, arguments);
+                respond(&mut output, result)?;
+            },
+        }
+    }
+}
+
+// '= result' or '? error', followed by an empty line
+fn respond<W: Write>(output: &mut W, result: Result<String, String>) -> io::Result<()> {
+    let (mark, text) = match result {
+        Ok(text) => ('=', text),
+        Err(error) => ('?', error),
+    };
+    if text.is_empty() || text.starts_with('\n') {
+        writeln!(output, "{}{}\n", mark, text)?;
+    } else {
+        writeln!(output, "{} {}\n", mark, text)?;
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn talk(commands: &str) -> String {
+        let mut output = Vec::new();
+        run(Cursor::new(commands.to_string().into_bytes()), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_protocol_commands() {
+        assert_eq!("= 1\n\n= tic_tac_toe\n\n= true\n\n= false\n\n? Unknown command 'fly'.\n\n",
+                   talk("protocol_version\nname\n# a comment\n\nknown_command genmove\n\
+                         known_command fly\nfly\n"));
+    }
+
+    #[test]
+    fn test_play_to_the_end() {
+        let output = talk("boardsize 3x3\nplay b2\nplay a1\nplay b1\nplay a3\nplay b3\nresult\n\
+                           play c1\n");
+        assert_eq!("=\n\n=\n\n=\n\n=\n\n=\n\n=\n\n= 1-0\n\n? The game is over.\n\n", output);
+    }
+
+    #[test]
+    fn test_play_rejects_illegal_stones() {
+        let output = talk("play b2\nplay b2\nplay d1\nplay 0,0=X\nplay a1 a2\nnotation\n");
+        assert_eq!("=\n\n? b2 is not free.\n\n? d1 is not on the board.\n\n\
+                    ? The symbol can only be chosen in the wild and order-chaos variants.\n\n\
+                    ? This turn has 1 stones.\n\n\
+                    = 3x3 3 standard 3/1x1/3 2\n\n", output);
+    }
+
+    #[test]
+    fn test_rules_and_undo() {
+        let output = talk("boardsize 6x6\nto_win 4\nrules standard stones=1/2\nplay c3\n\
+                           play d4 d5\nundo\nnotation\nclear_board\nnotation\n");
+        assert!(output.ends_with("= 6x6 4 standard 6/6/2x3/6/6/6 2 stones=1/2\n\n=\n\n\
+                                  = 6x6 4 standard 6/6/6/6/6/6 1 stones=1/2\n\n"), "{}", output);
+    }
+
+    #[test]
+    fn test_genmove_plays_a_turn() {
+        let output = talk("position 3x3 3 standard xx1/oo1/3 1\ngenmove movetime 1000\nresult\n");
+        assert_eq!("=\n\n= c1\n\n= 1-0\n\n", output);
+    }
+
+    #[test]
+    fn test_stop_ends_the_search() {
+        // a search this deep would take very long, stop makes it answer at once
+        let output = talk("boardsize 9x9\nto_win 5\ngenmove depth 40\nstop\nnotation\n");
+        let answers: Vec<&str> = output.split("\n\n").collect();
+        assert!(answers[2].starts_with("= "), "{}", output);
+        assert!(answers[3].ends_with(" 2"), "{}", output);
+    }
+}
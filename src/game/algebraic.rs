@@ -0,0 +1,84 @@
+use super::Coord;
+
+// Coordinates like board games write them: a letter for the column and the row counted from
+// 1 at the top, so 'c3' is the third cell of the third row. After 'z' the columns go on with
+// 'aa', 'ab' and so on. On boards with more dimensions the layers come first, counted from 1
+// and separated by ':', like '2:c3'.
+pub fn format(coord: &Coord) -> String {
+    let mut output = String::new();
+    for value in coord.layer() {
+        output += &format!("{}:", value + 1);
+    }
+    output + &column_name(coord.column()) + &(coord.row() + 1).to_string()
+}
+
+// 0 is 'a', 25 is 'z', 26 is 'aa'
+pub fn column_name(column: usize) -> String {
+    let mut name = Vec::new();
+    let mut rest = column + 1;
+    while rest > 0 {
+        rest -= 1;
+        name.push(b'a' + (rest % 26) as u8);
+        rest /= 26;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+pub fn parse(text: &str) -> Result<Coord, String> {
+    let invalid = || format!("'{}' is not a cell like c3.", text);
+    let mut parts: Vec<&str> = text.split(':').collect();
+    let cell = parts.pop().ok_or_else(invalid)?.to_lowercase();
+    let mut values = Vec::new();
+    for layer in parts {
+        match layer.parse::<usize>() {
+            Ok(value) if value > 0 => values.push(value - 1),
+            _ => return Err(invalid()),
+        }
+    }
+    let letters: String = cell.chars().take_while(|c| c.is_ascii_lowercase()).collect();
+    let row = match cell[letters.len() ..].parse::<usize>() {
+        Ok(row) if row > 0 && !letters.is_empty() => row - 1,
+        _ => return Err(invalid()),
+    };
+    let mut column = 0usize;
+    for letter in letters.bytes() {
+        column = column.checked_mul(26)
+            .and_then(|column| column.checked_add((letter - b'a') as usize + 1))
+            .ok_or_else(invalid)?;
+    }
+    values.push(row);
+    values.push(column - 1);
+    Ok(Coord(values))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_column_name() {
+        assert_eq!("a", column_name(0));
+        assert_eq!("z", column_name(25));
+        assert_eq!("aa", column_name(26));
+        assert_eq!("ab", column_name(27));
+        assert_eq!("ba", column_name(52));
+    }
+
+    #[test]
+    fn test_format_and_parse() {
+        assert_eq!("c3", format(&Coord::new(2, 2)));
+        assert_eq!("2:a1", format(&Coord(vec![1, 0, 0])));
+        for coord in &[Coord::new(0, 0), Coord::new(18, 30), Coord(vec![3, 1, 27])] {
+            assert_eq!(Ok(coord.clone()), parse(&format(coord)));
+        }
+        assert_eq!(Ok(Coord::new(9, 1)), parse("B10"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in &["", "c", "3", "c0", "3c", "0:c3", "c3x"] {
+            assert!(parse(text).is_err(), "{} was accepted", text);
+        }
+    }
+}
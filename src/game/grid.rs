@@ -1,88 +1,666 @@
-use super::{CellState, PlayerId};
+use super::{CellState, PlayerId, GameState, Variant, Move, Coord, X, O};
+use super::topology::Topology;
+use super::pattern::Pattern;
+use super::grid_observer;
+use super::algebraic;
+#[cfg(feature = "serde")]
+use super::notation::{parse_notation, to_notation};
 
+// The most cells a board read from a position or a request may have, as many as 1024x1024.
+// Bigger boards would only use up the memory of whoever reads them.
+pub const MAX_CELLS: usize = 1 << 20;
+
+// The number of cells of a board with these dimensions, if it isn't too big to be made
+pub fn checked_cell_count(dimensions: &[usize]) -> Result<usize, String> {
+    dimensions.iter()
+        .try_fold(1usize, |count, &size| count.checked_mul(size))
+        .filter(|&count| count <= MAX_CELLS)
+        .ok_or_else(|| format!("A board can have {} cells at most.", MAX_CELLS))
+}
+
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "StoredGrid"))]
 pub struct Grid {
-    // inner [0, 2] would be the 3rd column of the 1st row
+    // inner [0, 2] would be the 3rd column of the 1st row. With more than two dimensions
+    // the layers are stored one after another.
     inner: Box<[CellState]>,
+    // The size of every dimension, the last two are the rows and the columns
+    dimensions: Vec<usize>,
     pub column_count: usize,
     pub row_count: usize,
     pub to_win: u32,
+    pub variant: Variant,
+    topology: Topology,
+    // Lines continue across the edges: the right edge joins the left, the bottom the top
+    pub wrap: bool,
+    // How many stones a player places in the very first turn and in every turn after that
+    pub first_turn_stones: u32,
+    pub stones_per_turn: u32,
+    // Play goes on until the board is full, every line is a point instead of a win
+    pub scoring: bool,
+    // Shapes that win besides the lines of `to_win` cells
+    pub patterns: Vec<Pattern>,
+    pub last_move: Option<Move>,
+    turns_played: u32,
+    // stones placed so far in the current turn
+    turn_stones: u32,
 }
 
 impl Clone for Grid {
     fn clone(&self) -> Self {
         Grid {
             inner: self.inner.clone(),
+            dimensions: self.dimensions.clone(),
             column_count: self.column_count,
             row_count: self.row_count,
             to_win: self.to_win,
+            variant: self.variant,
+            topology: self.topology,
+            wrap: self.wrap,
+            first_turn_stones: self.first_turn_stones,
+            stones_per_turn: self.stones_per_turn,
+            scoring: self.scoring,
+            patterns: self.patterns.clone(),
+            last_move: self.last_move.clone(),
+            turns_played: self.turns_played,
+            turn_stones: self.turn_stones,
+        }
+    }
+}
+
+// How serde stores a grid: the position in the notation, with the patterns and the last move
+// the notation leaves out. A stored grid is read like any position, so one that doesn't add up
+// is refused. The notation can't tell a turn that is partly made, so such a grid can't be
+// stored.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredGrid {
+    position: String,
+    #[serde(default)]
+    patterns: Vec<Pattern>,
+    #[serde(default)]
+    last_move: Option<Move>,
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Grid {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.turn_stones > 0 {
+            let error = "a grid can only be stored between turns";
+            return Err(<S::Error as ::serde::ser::Error>::custom(error));
+        }
+        let stored = StoredGrid {
+            position: to_notation(self),
+            patterns: self.patterns.clone(),
+            last_move: self.last_move.clone(),
+        };
+        stored.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::std::convert::TryFrom<StoredGrid> for Grid {
+    type Error = String;
+
+    fn try_from(stored: StoredGrid) -> Result<Grid, String> {
+        let mut grid = parse_notation(&stored.position)?;
+        for pattern in &stored.patterns {
+            let fits = pattern.cells.iter().all(|cell| cell.len() == grid.dimensions.len());
+            if pattern.cells.is_empty() || !fits {
+                return Err(format!("The pattern {} doesn't fit the board.", pattern.name));
+            }
+        }
+        if let Some(ref mov) = stored.last_move {
+            let placed = grid.is_on_board(&mov.coord)
+                && *grid.get_cell_at(&mov.coord) == CellState::Set(mov.symbol)
+                && (mov.player == X || mov.player == O);
+            if !placed {
+                return Err("The last move isn't on the board.".to_string());
+            }
         }
+        grid.patterns = stored.patterns;
+        grid.last_move = stored.last_move;
+        Ok(grid)
     }
 }
 
 impl Grid {
     pub fn new(row_count: usize, column_count: usize, streak_to_win: u32) -> Grid {
+        Grid::with_variant(row_count, column_count, streak_to_win, Variant::Standard)
+    }
+
+    pub fn with_variant(row_count: usize, column_count: usize, streak_to_win: u32,
+                        variant: Variant) -> Grid {
+        let mut grid = Grid::with_dimensions(vec![row_count, column_count], streak_to_win);
+        grid.variant = variant;
+        grid
+    }
+
+    // A board with any number of dimensions, at least two. [4, 4, 4] would be four layers
+    // of 4x4.
+    pub fn with_dimensions(dimensions: Vec<usize>, streak_to_win: u32) -> Grid {
+        if dimensions.len() < 2 {
+            panic!("a grid needs at least 2 dimensions, but {} were given", dimensions.len())
+        }
+        let cell_count = dimensions.iter().product();
         Grid {
-            inner: vec![CellState::Unset; column_count * row_count].into_boxed_slice(),
-            column_count: column_count,
-            row_count: row_count,
+            inner: vec![CellState::Unset; cell_count].into_boxed_slice(),
+            column_count: dimensions[dimensions.len() - 1],
+            row_count: dimensions[dimensions.len() - 2],
+            dimensions,
             to_win: streak_to_win,
+            variant: Variant::Standard,
+            topology: Topology::Square,
+            wrap: false,
+            first_turn_stones: 1,
+            stones_per_turn: 1,
+            scoring: false,
+            patterns: Vec::new(),
+            last_move: None,
+            turns_played: 0,
+            turn_stones: 0,
+        }
+    }
+
+    // A board of hexagons, with `row_count` rows of `column_count` cells. In axial coordinates
+    // that's a rhombus.
+    pub fn hex(row_count: usize, column_count: usize, streak_to_win: u32) -> Grid {
+        let mut grid = Grid::new(row_count, column_count, streak_to_win);
+        grid.topology = Topology::Hex;
+        grid
+    }
+
+    // A hexagon shaped board of hexagons with `side` cells on each side. The corners of the
+    // rhombus that don't belong to the hexagon are blocked.
+    pub fn hexagon(side: usize, streak_to_win: u32) -> Grid {
+        let size = 2 * side - 1;
+        let mut grid = Grid::hex(size, size, streak_to_win);
+        for row in 0 .. size {
+            for column in 0 .. size {
+                if row + column < side - 1 || row + column > 3 * (side - 1) {
+                    grid.block_cell(row, column);
+                }
+            }
         }
+        grid
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn dimensions(&self) -> &[usize] {
+        &self.dimensions
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn turns_played(&self) -> u32 {
+        self.turns_played
+    }
+
+    // Players take turns in order of their ids, starting with player 1
+    pub fn to_move(&self) -> PlayerId {
+        PlayerId(self.turns_played % 2 + 1)
+    }
+
+    fn stones_in_turn(&self, turn: u32) -> u32 {
+        if turn == 0 { self.first_turn_stones } else { self.stones_per_turn }
+    }
+
+    // The stones that still have to be placed before the turn passes to the next player
+    pub fn stones_left(&self) -> u32 {
+        self.stones_in_turn(self.turns_played) - self.turn_stones
     }
 
-    fn calc_index(&self, row: usize, column: usize) -> usize {
-        if row >= self.row_count {
+    fn calc_index(&self, coord: &Coord) -> usize {
+        if coord.0.len() != self.dimensions.len() {
+            panic!("the grid has {} dimensions but the coordinate {:?} has {}",
+                   self.dimensions.len(), coord.0, coord.0.len())
+        }
+        if coord.row() >= self.row_count {
             panic!("index out of bounds: the row_count is {} but the row accessed is {}",
-                   self.row_count, row)
+                   self.row_count, coord.row())
         }
-        if column >= self.column_count {
+        if coord.column() >= self.column_count {
             panic!("index out of bounds: the column_count is {} but the column accessed is {}",
-                   self.column_count, column)
+                   self.column_count, coord.column())
+        }
+
+        let mut index = 0;
+        for (dimension, (&size, &value)) in self.dimensions.iter().zip(&coord.0).enumerate() {
+            if value >= size {
+                panic!("index out of bounds: dimension {} has size {} but {} was accessed",
+                       dimension, size, value)
+            }
+            index = index * size + value;
+        }
+        index
+    }
+
+    pub fn coord_of(&self, index: usize) -> Coord {
+        let mut values = vec![0; self.dimensions.len()];
+        let mut rest = index;
+        for (value, &size) in values.iter_mut().zip(&self.dimensions).rev() {
+            *value = rest % size;
+            rest /= size;
         }
+        Coord(values)
+    }
+
+    // Every coordinate of the grid, in the order the cells are stored
+    pub fn coords(&self) -> Vec<Coord> {
+        (0 .. self.cell_count()).map(|index| self.coord_of(index)).collect()
+    }
+
+    // The index of the cell one step in the given direction, if there is one.
+    // This works on indices, so the line scans don't need to build coordinates.
+    pub(crate) fn offset_index(&self, index: usize, step: &[isize]) -> Option<usize> {
+        let mut result = 0;
+        let mut stride = 1;
+        let mut rest = index;
+        for (&size, &step) in self.dimensions.iter().zip(step).rev() {
+            let value = (rest % size) as isize + step;
+            rest /= size;
+            let value = if self.wrap {
+                value.rem_euclid(size as isize)
+            } else if value < 0 || value >= size as isize {
+                return None;
+            } else {
+                value
+            };
+            result += value as usize * stride;
+            stride *= size;
+        }
+        Some(result)
+    }
 
-        column + row * self.column_count
+    pub(crate) fn cell_at_index(&self, index: usize) -> &CellState {
+        &self.inner[index]
     }
 
-    fn get_mut_cell(&mut self, row: usize, column: usize) -> &mut CellState {
-        &mut self.inner[self.calc_index(row, column)]
+    pub(crate) fn index_of(&self, coord: &Coord) -> usize {
+        self.calc_index(coord)
+    }
+
+    // The cell one step in the given direction, if there is one
+    pub fn offset(&self, coord: &Coord, step: &[isize]) -> Option<Coord> {
+        self.offset_index(self.calc_index(coord), step)
+            .map(|index| self.coord_of(index))
+    }
+
+    // The directions a line can be made in, see Topology::directions
+    pub fn directions(&self) -> Vec<Vec<isize>> {
+        self.topology.directions(self.dimensions.len())
+    }
+
+    // Every step to a cell at most `distance` cells away
+    pub fn neighbourhood(&self, distance: isize) -> Vec<Vec<isize>> {
+        self.topology.neighbourhood(self.dimensions.len(), distance)
+    }
+
+    fn get_mut_cell(&mut self, coord: &Coord) -> &mut CellState {
+        let index = self.calc_index(coord);
+        &mut self.inner[index]
     }
 
     pub fn get_cell(&self, row: usize, column: usize) -> &CellState {
-        &self.inner[self.calc_index(row, column)]
+        self.get_cell_at(&Coord::new(row, column))
+    }
+
+    pub fn get_cell_at(&self, coord: &Coord) -> &CellState {
+        &self.inner[self.calc_index(coord)]
     }
 
+    // Whether the coordinate names a cell of this board
+    pub fn is_on_board(&self, coord: &Coord) -> bool {
+        coord.0.len() == self.dimensions.len()
+            && coord.0.iter().zip(self.dimensions.iter()).all(|(&value, &size)| value < size)
+    }
+
+    // Places the player's own mark
     pub fn set_cell(&mut self, row: usize, column: usize, player: PlayerId) -> bool {
-        let cell = self.get_mut_cell(row, column);
-        match cell {
-            &mut CellState::Unset => {
-                *cell = CellState::Set(player);
-                return true;
+        self.make_move(Move::new(row, column, player))
+    }
+
+    pub fn make_move(&mut self, mov: Move) -> bool {
+        let cell = self.get_mut_cell(&mov.coord);
+        match *cell {
+            CellState::Unset => {
+                *cell = CellState::Set(mov.symbol);
+                self.last_move = Some(mov);
+                self.turn_stones += 1;
+                if self.turn_stones >= self.stones_in_turn(self.turns_played) {
+                    self.turns_played += 1;
+                    self.turn_stones = 0;
+                }
+                true
             },
-            &mut CellState::Set(_) => return false,
+            CellState::Set(_) | CellState::Blocked => false,
         }
     }
 
-    pub fn get_cells_with_state(&self, state: CellState) -> Vec<(usize, usize)> {
-        let mut result = Vec::new();
-        for row_nr in 0 .. self.row_count {
-            for col_nr in 0 .. self.column_count {
-                if *self.get_cell(row_nr, col_nr) == state {
-                    result.push((row_nr, col_nr));
+    // Takes a free cell out of the game
+    pub fn block_cell(&mut self, row: usize, column: usize) -> bool {
+        self.block_cell_at(&Coord::new(row, column))
+    }
+
+    pub fn block_cell_at(&mut self, coord: &Coord) -> bool {
+        let cell = self.get_mut_cell(coord);
+        match *cell {
+            CellState::Unset => {
+                *cell = CellState::Blocked;
+                true
+            },
+            CellState::Set(_) | CellState::Blocked => false,
+        }
+    }
+    // Reads the shape of a board, one line per row: '#' is a blocked cell, '_' or '.' a free one
+    pub fn from_shape(shape: &str, streak_to_win: u32) -> Result<Grid, String> {
+        let (grid, stones) = Grid::position_from_shape(shape, streak_to_win)?;
+        if let Some(stone) = stones.first() {
+            return Err(format!("Row {} has a stone, a board can't have any.", stone.coord.row()));
+        }
+        Ok(grid)
+    }
+
+    // Like from_shape, but the drawing may also hold stones: '1' or 'X' for the first symbol,
+    // '2' or 'O' for the second. The stones are returned to be placed with set_position.
+    pub fn position_from_shape(shape: &str, streak_to_win: u32)
+                               -> Result<(Grid, Vec<Move>), String> {
+        let rows: Vec<&str> = shape.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let column_count = rows.first().map_or(0, |row| row.chars().count());
+        if column_count == 0 {
+            return Err("The board is empty.".to_string());
+        }
+        let mut grid = Grid::new(rows.len(), column_count, streak_to_win);
+        let mut stones = Vec::new();
+        for (row_nr, row) in rows.iter().enumerate() {
+            if row.chars().count() != column_count {
+                return Err(format!("Row {} has {} cells instead of {}.",
+                                   row_nr, row.chars().count(), column_count));
+            }
+            for (col_nr, cell) in row.chars().enumerate() {
+                match cell {
+                    '#' => { grid.block_cell(row_nr, col_nr); },
+                    '_' | '.' => {},
+                    '1' | 'X' => stones.push(Move::new(row_nr, col_nr, X)),
+                    '2' | 'O' => stones.push(Move::new(row_nr, col_nr, O)),
+                    other => return Err(format!("Unknown cell '{}' in row {}.", other, row_nr)),
                 }
             }
         }
-        result
+        Ok((grid, stones))
+    }
+
+    // Starts the game from a position instead of an empty board: places the stones and hands
+    // the turn to `to_move`. The number of stones has to fit the rules, where one of the
+    // sides may have `handicap` stones more, and the game must not be decided already.
+    pub fn set_position(&mut self, stones: &[Move], to_move: PlayerId, handicap: u32)
+                        -> Result<(), String> {
+        let mut grid = self.clone();
+        grid.place_position(stones, to_move, handicap)?;
+        if grid_observer::check_winner(&grid) != GameState::Mid {
+            return Err("The game is already decided in this position.".to_string());
+        }
+        *self = grid;
+        Ok(())
+    }
+
+    // Like set_position, but the game may already be decided
+    pub(crate) fn place_position(&mut self, stones: &[Move], to_move: PlayerId, handicap: u32)
+                                 -> Result<(), String> {
+        if self.inner.iter().any(|cell| matches!(*cell, CellState::Set(_))) {
+            return Err("The board already has stones.".to_string());
+        }
+        if to_move != X && to_move != O {
+            return Err(format!("There is no player {}.", to_move.0));
+        }
+        let mut grid = self.clone();
+        let mut counts = [0, 0];
+        for stone in stones {
+            let on_board = stone.coord.0.len() == self.dimensions.len()
+                && stone.coord.0.iter().zip(&self.dimensions).all(|(&value, &size)| value < size);
+            if !on_board {
+                return Err(format!("The stone at {:?} is not on the board.", stone.coord.0));
+            }
+            if stone.symbol != X && stone.symbol != O {
+                return Err(format!("There is no symbol {}.", stone.symbol.0));
+            }
+            let cell = grid.get_mut_cell(&stone.coord);
+            if *cell != CellState::Unset {
+                return Err(format!("The cell {:?} is blocked or already has a stone.",
+                                   stone.coord.0));
+            }
+            *cell = CellState::Set(stone.symbol);
+            counts[stone.symbol.0 as usize - 1] += 1;
+        }
+        grid.turns_played = self.turns_to_reach(counts, to_move, handicap).ok_or_else(|| {
+            format!("A game can't have {} stones of 1 and {} of 2 when {} is to move.",
+                    counts[0], counts[1], to_move.0)
+        })?;
+        grid.turn_stones = 0;
+        grid.last_move = None;
+        *self = grid;
+        Ok(())
+    }
+
+    // The stones on the board beyond the ones of the turns played so far, like the stones of
+    // a handicap
+    pub fn extra_stones(&self) -> u32 {
+        let stones = self.inner.iter().filter(|cell| matches!(**cell, CellState::Set(_))).count();
+        let played: u32 = (0 .. self.turns_played).map(|turn| self.stones_in_turn(turn)).sum();
+        (stones as u32).saturating_sub(played + self.turn_stones)
+    }
+
+    // The number of turns after which `to_move` has the next turn and the stones of each
+    // symbol are the given ones. When players choose their symbols, only the sum counts.
+    fn turns_to_reach(&self, counts: [u32; 2], to_move: PlayerId, handicap: u32) -> Option<u32> {
+        let total = counts[0] + counts[1];
+        let mut regular = [0, 0];
+        for turns in 0 ..= total + 1 {
+            let fits = if self.variant.has_symbol_choice() {
+                total == regular[0] + regular[1] + handicap
+            } else {
+                (counts[0] == regular[0] + handicap && counts[1] == regular[1])
+                    || (counts[0] == regular[0] && counts[1] == regular[1] + handicap)
+            };
+            if fits && PlayerId(turns % 2 + 1) == to_move {
+                return Some(turns);
+            }
+            regular[(turns % 2) as usize] += self.stones_in_turn(turns);
+        }
+        None
+    }
+
+    pub fn get_cells_with_state(&self, state: CellState) -> Vec<Coord> {
+        (0 .. self.cell_count())
+            .filter(|&index| self.inner[index] == state)
+            .map(|index| self.coord_of(index))
+            .collect()
     }
 
+    pub fn contains(&self, state: CellState) -> bool {
+        self.inner.contains(&state)
+    }
+
+    // Prints the board with the cells of every completed line highlighted
     pub fn pretty_print(&self) {
-        for row_nr in 0 .. self.row_count {
-            for cell_nr in 0 .. self.column_count {
-                let string = match self.get_cell(row_nr, cell_nr) {
-                    &CellState::Unset => format!("_"),
-                    &CellState::Set(PlayerId(id)) => format!("{}", id),
-                };
-                print!("|{}", string);
+        let highlight: Vec<Coord> = grid_observer::winning_lines(self).into_iter()
+            .flat_map(|line| line.cells)
+            .collect();
+        print!("{}", self.render_labelled(&highlight));
+    }
+
+    // Boards with more than two dimensions are shown one layer after another.
+    // On a torus the board is surrounded by the cells from the opposite edges, set apart
+    // by ':' and a dotted line.
+    pub fn render(&self) -> String {
+        self.render_highlighted(&[])
+    }
+
+    // Like render, with the given cells shown in reverse video
+    pub fn render_highlighted(&self, highlight: &[Coord]) -> String {
+        let mut output = String::new();
+        for layer in self.layers() {
+            if !layer.is_empty() {
+                output += &format!("Layer {}:\n", Grid::layer_name(&layer));
             }
-            println!("|");
+            output += &self.render_layer(&layer, highlight);
+        }
+        output
+    }
+
+    // Like render_highlighted, with the names of the columns above the board and the numbers
+    // of the rows in front of it, the way moves are written in algebraic notation. Columns
+    // with longer names have their letters written below each other.
+    pub fn render_labelled(&self, highlight: &[Coord]) -> String {
+        let width = self.row_count.to_string().len();
+        let names: Vec<_> = (0 .. self.column_count).map(algebraic::column_name).collect();
+        let first_cell = match (self.topology, self.wrap) {
+            (Topology::Hex, _) => 0,
+            (_, true) => 2,
+            (_, false) => 1,
+        };
+        let mut header = String::new();
+        for letter in 0 .. names.iter().map(|name| name.len()).max().unwrap_or(0) {
+            let mut line = " ".repeat(width + 1 + first_cell);
+            for name in &names {
+                line.push(name.chars().nth(letter).unwrap_or(' '));
+                line.push(' ');
+            }
+            header += line.trim_end();
+            header += "\n";
+        }
+        // the rows of the board itself come after the wrapped row and the dotted line
+        let first_row = if self.wrap && self.topology == Topology::Square { 2 } else { 0 };
+        let mut output = String::new();
+        for layer in self.layers() {
+            if !layer.is_empty() {
+                output += &format!("Layer {}:\n", Grid::layer_name(&layer));
+            }
+            output += &header;
+            for (line_nr, line) in self.render_layer(&layer, highlight).lines().enumerate() {
+                if line_nr >= first_row && line_nr < first_row + self.row_count {
+                    output += &format!("{:>1$} ", line_nr - first_row + 1, width);
+                } else {
+                    output += &" ".repeat(width + 1);
+                }
+                output += line;
+                output += "\n";
+            }
+        }
+        output
+    }
+
+    // The coordinates before the row and the column of every layer
+    pub fn layers(&self) -> Vec<Vec<usize>> {
+        let layer_dimensions = &self.dimensions[.. self.dimensions.len() - 2];
+        let mut layers = vec![Vec::new()];
+        for &size in layer_dimensions {
+            layers = layers.into_iter()
+                .flat_map(|layer: Vec<usize>| (0 .. size).map(move |value| {
+                    let mut layer = layer.clone();
+                    layer.push(value);
+                    layer
+                }))
+                .collect();
+        }
+        layers
+    }
+
+    // The name a layer is shown with, its coordinates counted from 1 and set apart by ':'
+    pub fn layer_name(layer: &[usize]) -> String {
+        let numbers: Vec<_> = layer.iter().map(|value| (value + 1).to_string()).collect();
+        numbers.join(":")
+    }
+
+    fn render_layer(&self, layer: &[usize], highlight: &[Coord]) -> String {
+        if self.topology == Topology::Hex {
+            return self.render_hex(highlight);
+        }
+        let mut output = String::new();
+        if self.wrap && self.row_count > 0 {
+            output += &self.render_row(layer, self.row_count - 1, highlight);
+            output += &self.render_separator();
+        }
+        for row_nr in 0 .. self.row_count {
+            output += &self.render_row(layer, row_nr, highlight);
+        }
+        if self.wrap && self.row_count > 0 {
+            output += &self.render_separator();
+            output += &self.render_row(layer, 0, highlight);
+        }
+        output
+    }
+
+    // Every row is shifted half a cell further than the one above, so that each cell touches
+    // its six neighbours: two in its own row and two each in the rows above and below.
+    fn render_hex(&self, highlight: &[Coord]) -> String {
+        let mut output = String::new();
+        for row_nr in 0 .. self.row_count {
+            output += &" ".repeat(row_nr);
+            let cells: Vec<_> = (0 .. self.column_count)
+                .map(|cell_nr| self.render_cell(&[], row_nr, cell_nr, highlight))
+                .collect();
+            output += &cells.join(" ");
+            output += "\n";
+        }
+        if self.wrap {
+            output += "(the edges wrap around)\n";
+        }
+        output
+    }
+
+    fn render_cell(&self, layer: &[usize], row: usize, column: usize,
+                   highlight: &[Coord]) -> String {
+        let coord = Coord::in_layer(layer, row, column);
+        let cell = match *self.get_cell_at(&coord) {
+            CellState::Unset => "_".to_string(),
+            CellState::Blocked => "#".to_string(),
+            CellState::Set(symbol) => self.symbol_name(symbol),
+        };
+        if highlight.contains(&coord) {
+            format!("\x1b[7m{}\x1b[0m", cell)
+        } else {
+            cell
+        }
+    }
+
+    fn render_row(&self, layer: &[usize], row_nr: usize, highlight: &[Coord]) -> String {
+        let mut output = String::new();
+        if self.wrap && self.column_count > 0 {
+            output += &self.render_cell(layer, row_nr, self.column_count - 1, highlight);
+            output += ":";
+        } else {
+            output += "|";
+        }
+        for cell_nr in 0 .. self.column_count {
+            output += &self.render_cell(layer, row_nr, cell_nr, highlight);
+            output += if cell_nr + 1 < self.column_count || !self.wrap { "|" } else { ":" };
+        }
+        if self.wrap && self.column_count > 0 {
+            output += &self.render_cell(layer, row_nr, 0, highlight);
+        }
+        output += "\n";
+        output
+    }
+
+    fn render_separator(&self) -> String {
+        ".".repeat(2 * self.column_count + 3) + "\n"
+    }
+
+    pub fn symbol_name(&self, symbol: PlayerId) -> String {
+        match (self.variant.has_symbol_choice(), symbol) {
+            (true, ::game::X) => "X".to_string(),
+            (true, ::game::O) => "O".to_string(),
+            (_, PlayerId(id)) => format!("{}", id),
         }
     }
 
@@ -91,8 +669,92 @@ impl Grid {
 #[cfg(test)]
 mod test {
     use super::*;
-    use ::game::{CellState, PlayerId};
+    use ::game::{CellState, PlayerId, Variant, Move, Coord, X, O};
+    use ::game::topology::Topology;
+
+
+    // A position that uses most of what a grid can hold
+    #[cfg(feature = "serde")]
+    fn serde_sample() -> Grid {
+        let shape = "1_#__\n_2___\n__1__\n_____";
+        let (mut grid, stones) = Grid::position_from_shape(shape, 4).unwrap();
+        grid.set_position(&stones, PlayerId(2), 0).unwrap();
+        grid.wrap = true;
+        grid.patterns.push(::game::pattern::Pattern::l_tromino());
+        grid
+    }
+
+    #[cfg(feature = "serde")]
+    fn assert_same_grid(expected: &Grid, actual: &Grid) {
+        assert_eq!(expected.to_win, actual.to_win);
+        assert_eq!(expected.dimensions(), actual.dimensions());
+        for index in 0 .. expected.cell_count() {
+            assert_eq!(expected.cell_at_index(index), actual.cell_at_index(index),
+                       "cell {}", index);
+        }
+        assert_eq!(expected.to_move(), actual.to_move());
+        assert_eq!(expected.turns_played(), actual.turns_played());
+        assert_eq!(expected.wrap, actual.wrap);
+        assert_eq!(expected.patterns, actual.patterns);
+        assert_eq!(expected.render(), actual.render());
+    }
 
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_json_round_trip() {
+        let grid = serde_sample();
+        let json = ::serde_json::to_string(&grid).unwrap();
+        assert!(json.contains(&format!("\"position\":\"{}\"", to_notation(&grid))));
+        assert_same_grid(&grid, &::serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_refuses_grids_that_dont_add_up() {
+        let read = |json: &str| ::serde_json::from_str::<Grid>(json).map(|_| ());
+        assert!(read(r#"{"position": "3x3 3 standard x2/3/3 2"}"#).is_ok());
+        // more cells than the board has
+        assert!(read(r#"{"position": "3x3 3 standard x2/3/4 2"}"#).is_err());
+        assert!(read(r#"{"position": "3x3 3 standard x2/3/3 2",
+                         "last_move": {"coord": [5, 5], "player": 1, "symbol": 1}}"#).is_err());
+        assert!(read(r#"{"position": "3x3 3 standard x2/3/3 2",
+                         "patterns": [{"name": "dot", "cells": [[0, 0, 0]]}]}"#).is_err());
+        // the layout of the cells isn't part of what is stored
+        assert!(read(r#"{"inner": ["Unset"], "dimensions": [3, 3], "row_count": 3}"#).is_err());
+        let mut started = Grid::new(19, 19, 6);
+        started.stones_per_turn = 2;
+        started.set_cell(9, 9, X);
+        started.set_cell(0, 0, O);
+        assert!(::serde_json::to_string(&started).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_binary_round_trip() {
+        let grid = serde_sample();
+        let bytes = ::bincode::serialize(&grid).unwrap();
+        assert_same_grid(&grid, &::bincode::deserialize(&bytes).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_moves_and_results() {
+        let mov = Move::with_symbol(2, 3, PlayerId(1), O);
+        let json = ::serde_json::to_string(&mov).unwrap();
+        assert_eq!(mov, ::serde_json::from_str(&json).unwrap());
+        assert_eq!(mov, ::bincode::deserialize(&::bincode::serialize(&mov).unwrap()).unwrap());
+        for state in [GameState::Win(X), GameState::Draw, GameState::Mid, GameState::Scores(3, 2)] {
+            let json = ::serde_json::to_string(&state).unwrap();
+            assert_eq!(state, ::serde_json::from_str(&json).unwrap());
+            let bytes = ::bincode::serialize(&state).unwrap();
+            assert_eq!(state, ::bincode::deserialize(&bytes).unwrap());
+        }
+        let (won, _) = Grid::position_from_shape("111\n22_\n___", 3).unwrap();
+        let lines = grid_observer::winning_lines(&won);
+        let json = ::serde_json::to_string(&lines).unwrap();
+        let parsed: Vec<grid_observer::WinningLine> = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(lines, parsed);
+    }
 
     #[test]
     fn test_grid() {
@@ -100,6 +762,7 @@ mod test {
         match grid.get_cell(0, 0) {
             &CellState::Unset => return,
             &CellState::Set(_) => panic!("Cell in a new grid is set even though it shouldn't."),
+            &CellState::Blocked => panic!("Cell in a new grid is blocked."),
         }
     }
 
@@ -111,6 +774,7 @@ mod test {
                 &CellState::Unset => panic!("Cell should be set after calling set_cell"),
                 &CellState::Set(PlayerId(1)) => return,
                 &CellState::Set(_) => panic!("Cell is set by the wrong player"),
+                &CellState::Blocked => panic!("Cell is blocked after calling set_cell"),
             }
         } else {
             panic!("Cell could not be set although it shouldn't be set before");
@@ -146,4 +810,199 @@ mod test {
         assert_eq!(original.get_cell(0, 1), clone.get_cell(0, 1));
         assert_eq!(original.get_cell(0, 2), clone.get_cell(0, 2));
     }
+
+    #[test]
+    fn test_make_move_places_the_symbol_not_the_player() {
+        let mut grid = Grid::with_variant(3, 3, 3, Variant::Wild);
+        assert!(grid.make_move(Move::with_symbol(1, 1, PlayerId(1), O)));
+        assert_eq!(CellState::Set(O), *grid.get_cell(1, 1));
+        assert_eq!(Some(Move::with_symbol(1, 1, PlayerId(1), O)), grid.last_move);
+        assert!(!grid.make_move(Move::with_symbol(1, 1, PlayerId(2), X)));
+        assert_eq!(CellState::Set(O), *grid.get_cell(1, 1));
+    }
+
+    #[test]
+    fn test_turns_with_several_stones() {
+        let mut grid = Grid::new(19, 19, 6);
+        grid.first_turn_stones = 1;
+        grid.stones_per_turn = 2;
+        assert_eq!(PlayerId(1), grid.to_move());
+        assert_eq!(1, grid.stones_left());
+        grid.set_cell(9, 9, PlayerId(1));
+        assert_eq!(PlayerId(2), grid.to_move());
+        assert_eq!(2, grid.stones_left());
+        grid.set_cell(9, 10, PlayerId(2));
+        assert_eq!(PlayerId(2), grid.to_move());
+        assert_eq!(1, grid.stones_left());
+        // an occupied cell doesn't use up a stone
+        grid.set_cell(9, 10, PlayerId(2));
+        assert_eq!(1, grid.stones_left());
+        grid.set_cell(10, 10, PlayerId(2));
+        assert_eq!(PlayerId(1), grid.to_move());
+        assert_eq!(2, grid.stones_left());
+        assert_eq!(2, grid.turns_played());
+    }
+
+    #[test]
+    fn test_offset() {
+        let mut grid = Grid::new(3, 4, 3);
+        assert_eq!(Some(Coord::new(1, 2)), grid.offset(&Coord::new(0, 1), &[1, 1]));
+        assert_eq!(None, grid.offset(&Coord::new(0, 3), &[0, 1]));
+        assert_eq!(None, grid.offset(&Coord::new(0, 1), &[-1, 1]));
+        grid.wrap = true;
+        assert_eq!(Some(Coord::new(0, 0)), grid.offset(&Coord::new(0, 3), &[0, 1]));
+        assert_eq!(Some(Coord::new(2, 2)), grid.offset(&Coord::new(0, 1), &[-1, 1]));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut grid = Grid::new(2, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(1, 2, PlayerId(2));
+        assert_eq!("|1|_|_|\n|_|_|2|\n", grid.render());
+        grid.wrap = true;
+        assert_eq!("2:_|_|2:_\n.........\n_:1|_|_:1\n2:_|_|2:_\n.........\n_:1|_|_:1\n",
+                   grid.render());
+    }
+
+    #[test]
+    fn test_blocked_cells() {
+        let mut grid = Grid::new(3, 3, 3);
+        assert!(grid.block_cell(1, 1));
+        assert_eq!(CellState::Blocked, *grid.get_cell(1, 1));
+        assert!(!grid.set_cell(1, 1, PlayerId(1)));
+        assert_eq!(CellState::Blocked, *grid.get_cell(1, 1));
+        assert_eq!(8, grid.get_cells_with_state(CellState::Unset).len());
+        assert_eq!(vec![Coord::new(1, 1)], grid.get_cells_with_state(CellState::Blocked));
+        assert_eq!("|_|_|_|\n|_|#|_|\n|_|_|_|\n", grid.render());
+    }
+
+    #[test]
+    fn test_from_shape() {
+        let grid = Grid::from_shape("#_#\n___\n#.#\n", 3).unwrap();
+        assert_eq!(3, grid.row_count);
+        assert_eq!(3, grid.column_count);
+        assert_eq!(3, grid.to_win);
+        assert_eq!(vec![Coord::new(0, 0), Coord::new(0, 2), Coord::new(2, 0), Coord::new(2, 2)],
+                   grid.get_cells_with_state(CellState::Blocked));
+        assert!(Grid::from_shape("#_#\n__\n", 3).is_err());
+        assert!(Grid::from_shape("#x#\n", 3).is_err());
+        assert!(Grid::from_shape("\n", 3).is_err());
+    }
+
+    #[test]
+    fn test_grid_with_three_dimensions() {
+        let mut grid = Grid::with_dimensions(vec![2, 3, 4], 3);
+        assert_eq!(24, grid.cell_count());
+        assert_eq!(3, grid.row_count);
+        assert_eq!(4, grid.column_count);
+        assert!(grid.make_move(Move::at(Coord(vec![1, 2, 3]), PlayerId(1), PlayerId(1))));
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell_at(&Coord(vec![1, 2, 3])));
+        assert_eq!(CellState::Unset, *grid.get_cell_at(&Coord(vec![0, 2, 3])));
+        assert_eq!(vec![Coord(vec![1, 2, 3])],
+                   grid.get_cells_with_state(CellState::Set(PlayerId(1))));
+        for (index, coord) in grid.coords().iter().enumerate() {
+            assert_eq!(index, grid.calc_index(coord));
+        }
+        assert_eq!(Some(Coord(vec![0, 1, 1])), grid.offset(&Coord(vec![1, 2, 2]), &[-1, -1, -1]));
+        assert_eq!(None, grid.offset(&Coord(vec![1, 2, 2]), &[1, 0, 0]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_two_coordinates_on_a_three_dimensional_grid() {
+        let grid = Grid::with_dimensions(vec![2, 3, 4], 3);
+        grid.get_cell(0, 0);
+    }
+
+    #[test]
+    fn test_directions() {
+        assert_eq!(4, Grid::new(3, 3, 3).directions().len());
+        assert_eq!(13, Grid::with_dimensions(vec![4, 4, 4], 4).directions().len());
+        assert_eq!(40, Grid::with_dimensions(vec![3, 3, 3, 3], 3).directions().len());
+    }
+
+    #[test]
+    fn test_render_layers() {
+        let mut grid = Grid::with_dimensions(vec![2, 2, 2], 2);
+        grid.make_move(Move::at(Coord(vec![1, 0, 1]), PlayerId(2), PlayerId(2)));
+        assert_eq!("Layer 1:\n|_|_|\n|_|_|\nLayer 2:\n|_|2|\n|_|_|\n", grid.render());
+        let labelled = grid.render_labelled(&[]);
+        assert!(labelled.starts_with("Layer 1:\n") && labelled.contains("\nLayer 2:\n"));
+    }
+
+    #[test]
+    fn test_hexagon() {
+        let grid = Grid::hexagon(3, 3);
+        assert_eq!(5, grid.row_count);
+        assert_eq!(Topology::Hex, grid.topology());
+        // a hexagon with three cells on each side has 19 cells
+        assert_eq!(19, grid.get_cells_with_state(CellState::Unset).len());
+        assert_eq!("# # _ _ _\n # _ _ _ _\n  _ _ _ _ _\n   _ _ _ _ #\n    _ _ _ # #\n",
+                   grid.render());
+    }
+
+    #[test]
+    fn test_render_highlighted() {
+        let mut grid = Grid::new(2, 2, 2);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(1));
+        assert_eq!("|\x1b[7m1\x1b[0m|_|\n|_|\x1b[7m1\x1b[0m|\n",
+                   grid.render_highlighted(&[Coord::new(0, 0), Coord::new(1, 1)]));
+    }
+
+    #[test]
+    fn test_render_labelled() {
+        let mut grid = Grid::new(2, 3, 2);
+        grid.set_cell(1, 2, PlayerId(1));
+        assert_eq!("   a b c\n1 |_|_|_|\n2 |_|_|1|\n", grid.render_labelled(&[]));
+        grid.wrap = true;
+        assert_eq!("    a b c\n  1:_|_|1:_\n  .........\n1 _:_|_|_:_\n2 1:_|_|1:_\n  .........\n  \
+                    _:_|_|_:_\n",
+                   grid.render_labelled(&[]));
+    }
+
+    #[test]
+    fn test_render_labelled_wide() {
+        let grid = Grid::new(1, 28, 3);
+        let lines: Vec<_> = grid.render_labelled(&[]).lines().map(String::from).collect();
+        assert!(lines[0].ends_with("y z a a"));
+        assert!(lines[1].ends_with("a b"));
+        assert!(lines[2].starts_with("1 |_|"));
+    }
+
+    #[test]
+    fn test_set_position() {
+        let (mut grid, stones) = Grid::position_from_shape("1_2\n_1_\n___", 3).unwrap();
+        assert!(grid.set_position(&stones, PlayerId(1), 0).is_err());
+        grid.set_position(&stones, PlayerId(2), 0).unwrap();
+        assert_eq!(PlayerId(2), grid.to_move());
+        assert_eq!(3, grid.turns_played());
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell(1, 1));
+        assert!(grid.set_position(&stones, PlayerId(2), 0).is_err());
+    }
+
+    #[test]
+    fn test_set_position_with_handicap() {
+        let (mut grid, stones) = Grid::position_from_shape("2_2\n___\n___", 3).unwrap();
+        assert!(grid.clone().set_position(&stones, PlayerId(1), 0).is_err());
+        grid.set_position(&stones, PlayerId(1), 2).unwrap();
+        assert_eq!(PlayerId(1), grid.to_move());
+    }
+
+    #[test]
+    fn test_set_position_rejects_a_decided_game() {
+        let (mut grid, stones) = Grid::position_from_shape("111\n22_\n2__", 3).unwrap();
+        assert!(grid.set_position(&stones, PlayerId(2), 0).is_err());
+        assert!(grid.get_cells_with_state(CellState::Set(PlayerId(1))).is_empty());
+    }
+
+    #[test]
+    fn test_set_position_with_several_stones_per_turn() {
+        let (mut grid, stones) = Grid::position_from_shape("1_____\n_22___\n__11__", 6).unwrap();
+        grid.stones_per_turn = 2;
+        grid.set_position(&stones, PlayerId(2), 0).unwrap();
+        assert_eq!(3, grid.turns_played());
+        assert_eq!(2, grid.stones_left());
+    }
 }
@@ -1,114 +1,397 @@
 use super::grid::Grid;
-use super::{PlayerId, CellState, GameState};
+use super::{PlayerId, CellState, GameState, Variant, Coord, ORDER, CHAOS};
 use std::iter::Iterator;
+use std::collections::HashSet;
+
+// What a player completed
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Shape {
+    // A straight line in the given direction, see Grid::directions
+    Line(Vec<isize>),
+    // One of the patterns of the grid, by its name
+    Pattern(String),
+}
+
+// A line or pattern filled with one symbol. The cells of a line are in the order of its
+// direction.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WinningLine {
+    pub symbol: PlayerId,
+    pub cells: Vec<Coord>,
+    pub shape: Shape,
+}
 
 pub fn check_winner(grid: &Grid) -> GameState {
-    match check_horizontal(&grid)
-            .or(check_vertical(&grid))
-            .or(check_diagonal(&grid)) {
-        Some(id) => GameState::Win(id),
-        None => {
-            if check_full(&grid) {
-                GameState::Draw
-            } else {
-                GameState::Mid
+    let line = grid.directions().iter()
+        .filter_map(|direction| check_direction(grid, direction))
+        .next()
+        .or_else(|| check_patterns(grid, None));
+    judge(grid, line)
+}
+
+// Like check_winner, but also returns what was completed. There may be several lines if
+// the last turn completed more than one, and none if nothing was, like when Chaos wins.
+pub fn check_winner_lines(grid: &Grid) -> (GameState, Vec<WinningLine>) {
+    let lines = winning_lines(grid);
+    (judge(grid, lines.first().map(|line| line.symbol)), lines)
+}
+
+// Every line of at least `to_win` cells with the same symbol and every filled pattern on the
+// board. A line longer than `to_win` is one line with all of its cells.
+pub fn winning_lines(grid: &Grid) -> Vec<WinningLine> {
+    let mut lines = Vec::new();
+    for direction in grid.directions() {
+        for (symbol, cells) in runs(grid, &direction) {
+            lines.push(WinningLine {
+                symbol,
+                cells: cells.into_iter().map(|index| grid.coord_of(index)).collect(),
+                shape: Shape::Line(direction.clone()),
+            });
+        }
+    }
+    if !grid.patterns.is_empty() {
+        for (number, cells) in pattern_indices(grid) {
+            if let Some(symbol) = filled_by(grid, &cells) {
+                lines.push(WinningLine {
+                    symbol,
+                    cells: cells.into_iter().map(|index| grid.coord_of(index)).collect(),
+                    shape: Shape::Pattern(grid.patterns[number].name.clone()),
+                });
             }
         }
     }
+    lines
 }
 
-fn check_horizontal(grid: &Grid) -> Option<PlayerId> {
-    let mut streak_player = 0;
-    let mut streak_length = 0;
-    for row_nr in 0..grid.row_count {
-        for cell_nr in 0 .. grid.column_count {
-            let cell = grid.get_cell(row_nr, cell_nr);
-            check_cell(&cell, &mut streak_player, &mut streak_length);
-            if streak_length >= grid.to_win {
-                return Some(PlayerId(streak_player));
+// The runs of at least `to_win` cells with the same symbol in one direction. Every line is
+// followed from where it enters the board, on a torus once around from the cells where
+// the first coordinate it moves along is 0.
+fn runs(grid: &Grid, direction: &[isize]) -> Vec<(PlayerId, Vec<usize>)> {
+    let to_win = grid.to_win as usize;
+    let backwards: Vec<isize> = direction.iter().map(|step| -step).collect();
+    let moving = match direction.iter().position(|&step| step != 0) {
+        Some(moving) => moving,
+        None => return Vec::new(),
+    };
+    let mut result = Vec::new();
+    // on a torus several of the starts lie on the same line
+    let mut seen = HashSet::new();
+    for start in 0 .. grid.cell_count() {
+        let is_start = if grid.wrap {
+            grid.coord_of(start).0[moving] == 0
+        } else {
+            grid.offset_index(start, &backwards).is_none()
+        };
+        if !is_start {
+            continue;
+        }
+        let mut line = vec![start];
+        while let Some(next) = grid.offset_index(line[line.len() - 1], direction) {
+            if next == start {
+                break;
+            }
+            line.push(next);
+        }
+        if grid.wrap {
+            if to_win > line.len() {
+                continue;
+            }
+            // begin the loop where a run begins, so no run is cut in two
+            let length = line.len();
+            let run_start = (0 .. length).find(|&position| {
+                let before = line[(position + length - 1) % length];
+                grid.cell_at_index(line[position]) != grid.cell_at_index(before)
+            });
+            line.rotate_left(run_start.unwrap_or(0));
+        }
+        for run in line.chunk_by(|&a, &b| grid.cell_at_index(a) == grid.cell_at_index(b)) {
+            if let CellState::Set(symbol) = *grid.cell_at_index(run[0]) {
+                if run.len() >= to_win.max(1) && (!grid.wrap || seen.insert(min_cell(run))) {
+                    result.push((symbol, run.to_vec()));
+                }
             }
         }
-        streak_length = 0;
     }
-    None
+    result
 }
 
-fn check_vertical(grid: &Grid) -> Option<PlayerId> {
-    let mut streak_player = 0;
-    let mut streak_length = 0;
+fn min_cell(run: &[usize]) -> usize {
+    run.iter().cloned().min().unwrap_or(0)
+}
 
-    for col_nr in 0 .. grid.column_count {
-        for row_nr in 0..grid.row_count {
-            let cell = &grid.get_cell(row_nr, col_nr);
-            check_cell(cell, &mut streak_player, &mut streak_length);
-            if streak_length >= grid.to_win {
-                return Some(PlayerId(streak_player));
+// Like check_winner, but only looks at the lines through the given cells. That is enough
+// if the game was undecided before they were set, and a lot quicker on big boards.
+pub fn check_winner_after(grid: &Grid, changed: &[Coord]) -> GameState {
+    let directions = grid.directions();
+    let indices: Vec<usize> = changed.iter().map(|coord| grid.index_of(coord)).collect();
+    let line = indices.iter()
+        .filter_map(|&index| {
+            directions.iter()
+                .filter_map(|direction| check_line_through(grid, index, direction))
+                .next()
+        })
+        .next()
+        .or_else(|| check_patterns(grid, Some(&indices)));
+    judge(grid, line)
+}
+
+// Looks for a pattern filled with one symbol. If cells are given, only the patterns that
+// cover one of them are checked.
+fn check_patterns(grid: &Grid, through: Option<&[usize]>) -> Option<PlayerId> {
+    if grid.patterns.is_empty() {
+        return None;
+    }
+    pattern_indices(grid).into_iter()
+        .filter(|(_, cells)| {
+            through.is_none_or(|changed| cells.iter().any(|cell| changed.contains(cell)))
+        })
+        .filter_map(|(_, cells)| filled_by(grid, &cells))
+        .next()
+}
+
+// The symbol in all of the cells, if they all hold the same one
+fn filled_by(grid: &Grid, cells: &[usize]) -> Option<PlayerId> {
+    let symbol = match *grid.cell_at_index(cells[0]) {
+        CellState::Set(symbol) => symbol,
+        CellState::Unset | CellState::Blocked => return None,
+    };
+    if cells.iter().all(|&index| *grid.cell_at_index(index) == CellState::Set(symbol)) {
+        Some(symbol)
+    } else {
+        None
+    }
+}
+
+// Every place each pattern of the grid fits on the board, in every orientation. The
+// places are the number of the pattern and the indices of the cells it covers.
+pub(crate) fn pattern_indices(grid: &Grid) -> Vec<(usize, Vec<usize>)> {
+    let mut result = Vec::new();
+    for (number, pattern) in grid.patterns.iter().enumerate() {
+        let mut seen = HashSet::new();
+        for cells in pattern.orientations(grid.topology(), grid.dimensions().len()) {
+            for start in 0 .. grid.cell_count() {
+                let placed: Option<Vec<usize>> = cells.iter()
+                    .map(|offset| grid.offset_index(start, offset))
+                    .collect();
+                let placed = match placed {
+                    Some(placed) => placed,
+                    None => continue,
+                };
+                // on a torus a big pattern may cover a cell twice, and symmetric
+                // patterns fit on the same cells in several orientations
+                let mut sorted = placed.clone();
+                sorted.sort();
+                sorted.dedup();
+                if sorted.len() < placed.len() || !seen.insert(sorted) {
+                    continue;
+                }
+                result.push((number, placed));
             }
         }
-        streak_length = 0;
     }
-    None
+    result
+}
+
+// The state of the game given the symbol of a line on the board, if there is one
+fn judge(grid: &Grid, line: Option<PlayerId>) -> GameState {
+    if grid.scoring {
+        return if check_full(grid) {
+            let (first, second) = line_tally(grid);
+            GameState::Scores(first, second)
+        } else {
+            GameState::Mid
+        };
+    }
+    match grid.variant {
+        Variant::Standard => match line {
+            Some(id) => GameState::Win(id),
+            None => draw_or_mid(grid),
+        },
+        // The line may be made of either symbol, it belongs to whoever completed it
+        Variant::Wild => match line {
+            Some(symbol) => {
+                GameState::Win(grid.last_move.as_ref().map_or(symbol, |mov| mov.player))
+            },
+            None => draw_or_mid(grid),
+        },
+        Variant::OrderAndChaos => match line {
+            Some(_) => GameState::Win(ORDER),
+            None if check_full(grid) => GameState::Win(CHAOS),
+            None => GameState::Mid,
+        },
+    }
+}
+
+fn draw_or_mid(grid: &Grid) -> GameState {
+    if check_full(grid) {
+        GameState::Draw
+    } else {
+        GameState::Mid
+    }
 }
 
-fn check_diagonal(grid: &Grid) -> Option<PlayerId> {
-    for rownr in 0 .. grid.row_count {
-        match check_any_diagonal(&grid, rownr, 0) {
-            None => continue,
-            Some(PlayerId(id)) => return Some(PlayerId(id)),
+// The points of player 1 and player 2: one for every run of `to_win` cells with their
+// symbol, so a line with one cell more than needed is worth two, and one for every filled
+// pattern
+pub fn line_tally(grid: &Grid) -> (u32, u32) {
+    let mut points = (0, 0);
+    let patterns = pattern_indices(grid).into_iter().map(|(_, cells)| cells);
+    for cells in window_indices(grid).into_iter().chain(patterns) {
+        match filled_by(grid, &cells) {
+            Some(PlayerId(1)) => points.0 += 1,
+            Some(PlayerId(2)) => points.1 += 1,
+            _ => {},
         }
     }
-    for colnr in 0 .. grid.column_count {
-        match check_any_diagonal(&grid, 0, colnr) {
-            None => continue,
-            Some(PlayerId(id)) => return Some(PlayerId(id)),
+    points
+}
+
+// All runs of `to_win` neighbouring cells, in every direction a line can be made in
+pub fn windows(grid: &Grid) -> Vec<Vec<Coord>> {
+    window_indices(grid).into_iter()
+        .map(|window| window.into_iter().map(|index| grid.coord_of(index)).collect())
+        .collect()
+}
+
+// The windows as indices of the cells, for the ki which looks at them over and over
+pub(crate) fn window_indices(grid: &Grid) -> Vec<Vec<usize>> {
+    let to_win = grid.to_win as usize;
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+    if to_win == 0 {
+        return result;
+    }
+    for direction in grid.directions() {
+        // on a torus a line can't use a cell twice
+        if grid.wrap && to_win > cycle_length(grid, &direction) {
+            continue;
+        }
+        for start in 0 .. grid.cell_count() {
+            let mut window = vec![start];
+            while window.len() < to_win {
+                match grid.offset_index(window[window.len() - 1], &direction) {
+                    Some(next) => window.push(next),
+                    None => break,
+                }
+            }
+            if window.len() < to_win {
+                continue;
+            }
+            // a line that goes all the way around a torus is found from each of its cells
+            if grid.wrap {
+                let mut sorted = window.clone();
+                sorted.sort();
+                if !seen.insert(sorted) {
+                    continue;
+                }
+            }
+            result.push(window);
         }
     }
-    None
+    result
 }
 
-fn check_any_diagonal(grid: &Grid, startrow: usize, startcolumn: usize) -> Option<PlayerId> {
-    check_top_down_diagonal(grid, startrow, startcolumn)
-        .or(check_bottom_up_diagonal(grid, startrow, startcolumn))
+// The number of steps in a direction until a line on a torus is back at its start
+fn cycle_length(grid: &Grid, direction: &[isize]) -> usize {
+    grid.dimensions().iter().zip(direction)
+        .filter(|&(_, &step)| step != 0)
+        .fold(1, |length, (&size, _)| lcm(length, size))
 }
 
-fn check_top_down_diagonal(grid: &Grid, startrow: usize, startcolumn: usize) -> Option<PlayerId> {
-    let mut streak_player = 0;
-    let mut streak_length = 0;
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let rest = x % y;
+        x = y;
+        y = rest;
+    }
+    a.checked_div(x).map_or(0, |a| a * b)
+}
 
-    let mut rownr = startrow;
-    let mut colnr = startcolumn;
-    while (rownr < grid.row_count) & (colnr < grid.column_count) {
-        let cell = &grid.get_cell(rownr, colnr);
-        check_cell(cell, &mut streak_player, &mut streak_length);
-        if streak_length >= grid.to_win {
-            return Some(PlayerId(streak_player));
+// Looks for a line in one direction. Every line is followed from the cell where it enters
+// the board. On a torus there is no such cell, but every line passes the cells where the
+// first coordinate it moves along is 0.
+fn check_direction(grid: &Grid, direction: &[isize]) -> Option<PlayerId> {
+    let backwards: Vec<isize> = direction.iter().map(|step| -step).collect();
+    let moving = direction.iter().position(|&step| step != 0)?;
+    (0 .. grid.cell_count())
+        .filter(|&start| if grid.wrap {
+            grid.coord_of(start).0[moving] == 0
+        } else {
+            grid.offset_index(start, &backwards).is_none()
+        })
+        .filter_map(|start| check_line(grid, start, direction))
+        .next()
+}
+
+// Counts the cells with the same symbol as the given one on both sides of it
+fn check_line_through(grid: &Grid, index: usize, direction: &[isize]) -> Option<PlayerId> {
+    let symbol = match *grid.cell_at_index(index) {
+        CellState::Set(symbol) => symbol,
+        CellState::Unset | CellState::Blocked => return None,
+    };
+    let to_win = grid.to_win as usize;
+    if grid.wrap && to_win > cycle_length(grid, direction) {
+        return None;
+    }
+    let backwards: Vec<isize> = direction.iter().map(|step| -step).collect();
+    let mut length = 1;
+    for step in &[direction, &backwards[..]] {
+        let mut position = index;
+        // on a torus the count has to stop before it is back at the start
+        for _ in 1 .. to_win {
+            match grid.offset_index(position, step) {
+                Some(next) if *grid.cell_at_index(next) == CellState::Set(symbol) => {
+                    length += 1;
+                    position = next;
+                },
+                _ => break,
+            }
         }
-        colnr += 1;
-        rownr += 1
     }
-    None
+    if length >= to_win { Some(symbol) } else { None }
 }
 
-fn check_bottom_up_diagonal(grid: &Grid, startrow: usize, startcolumn: usize) -> Option<PlayerId> {
+// Follows a line from the start cell until the border. On a torus the line is followed once
+// around and then far enough to find a streak that crosses the start.
+fn check_line(grid: &Grid, start: usize, direction: &[isize]) -> Option<PlayerId> {
     let mut streak_player = 0;
     let mut streak_length = 0;
 
-    let cols_iter = startcolumn .. grid.column_count;
-    let rows_iter = (0 .. startrow + 1).rev();
+    let steps = if grid.wrap {
+        let cycle = cycle_length(grid, direction);
+        if grid.to_win as usize > cycle {
+            return None;
+        }
+        cycle + grid.to_win as usize - 1
+    } else {
+        usize::MAX
+    };
 
-    for (colnr, rownr) in cols_iter.zip(rows_iter) {
-        let cell = &grid.get_cell(rownr, colnr);
-        check_cell(cell, &mut streak_player, &mut streak_length);
+    let mut position = Some(start);
+    for _ in 0 .. steps {
+        let index = match position {
+            Some(index) => index,
+            None => break,
+        };
+        check_cell(grid.cell_at_index(index), &mut streak_player, &mut streak_length);
         if streak_length >= grid.to_win {
             return Some(PlayerId(streak_player));
         }
+        position = grid.offset_index(index, direction);
     }
     None
 }
 
 fn check_cell(cell: &CellState, streak_player: &mut u32, streak_length: &mut u32) {
     match cell {
-        &CellState::Unset => *streak_length = 0,
+        &CellState::Unset | &CellState::Blocked => *streak_length = 0,
         &CellState::Set(PlayerId(id)) if id == *streak_player => *streak_length += 1,
         &CellState::Set(PlayerId(id)) => {
             *streak_length = 1;
@@ -117,25 +400,18 @@ fn check_cell(cell: &CellState, streak_player: &mut u32, streak_length: &mut u32
     }
 }
 
+// Blocked cells can't be filled, so they count as full
 fn check_full(grid: &Grid) -> bool {
-    for row_nr in 0 .. grid.row_count {
-        for col_nr in 0 .. grid.column_count {
-            if *grid.get_cell(row_nr, col_nr) == CellState::Unset {
-                return false;
-            }
-        }
-    }
-    true
+    !grid.contains(CellState::Unset)
 }
 
 
-
-
 #[cfg(test)]
 mod test {
     use super::*;
     use ::game::grid::Grid;
-    use ::game::PlayerId;
+    use ::game::{PlayerId, Move, Variant, Coord, X, O, ORDER, CHAOS};
+    use ::game::pattern::Pattern;
 
     #[test]
     #[should_panic]
@@ -267,4 +543,404 @@ mod test {
         grid.set_cell(4, 8, PlayerId(1));
         assert!(check_winner(&grid).is_none());
     }
+
+    #[test]
+    fn test_check_winner_wild_line_of_either_symbol_wins_for_the_mover() {
+        let mut grid = Grid::with_variant(3, 3, 3, Variant::Wild);
+        grid.make_move(Move::with_symbol(0, 0, PlayerId(1), O));
+        grid.make_move(Move::with_symbol(1, 1, PlayerId(2), X));
+        grid.make_move(Move::with_symbol(0, 1, PlayerId(1), X));
+        grid.make_move(Move::with_symbol(0, 2, PlayerId(2), O));
+        assert_eq!(GameState::Mid, check_winner(&grid));
+        grid.make_move(Move::with_symbol(2, 1, PlayerId(1), X));
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_check_winner_wild_completing_the_opponents_symbol_still_wins() {
+        let mut grid = Grid::with_variant(3, 3, 3, Variant::Wild);
+        grid.make_move(Move::with_symbol(0, 0, PlayerId(1), O));
+        grid.make_move(Move::with_symbol(0, 1, PlayerId(1), O));
+        grid.make_move(Move::with_symbol(0, 2, PlayerId(2), O));
+        assert_eq!(GameState::Win(PlayerId(2)), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_check_winner_order_wins_with_line_of_either_symbol() {
+        let mut grid = Grid::with_variant(6, 6, 5, Variant::OrderAndChaos);
+        for row in 1 .. 6 {
+            grid.make_move(Move::with_symbol(row, 2, CHAOS, O));
+        }
+        assert_eq!(GameState::Win(ORDER), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_check_winner_chaos_wins_on_full_board_without_line() {
+        let mut grid = Grid::with_variant(6, 6, 5, Variant::OrderAndChaos);
+        for row in 0 .. 6 {
+            for col in 0 .. 6 {
+                // pairs of columns alternate, which never gives five in a row
+                let symbol = if (row + col / 2) % 2 == 0 { X } else { O };
+                assert_eq!(GameState::Mid, check_winner(&grid));
+                grid.make_move(Move::with_symbol(row, col, ORDER, symbol));
+            }
+        }
+        assert_eq!(GameState::Win(CHAOS), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_windows_cover_every_line_of_to_win_cells() {
+        let grid = Grid::new(3, 4, 3);
+        let windows = windows(&grid);
+        // 2 per row, 4 columns, 2 per diagonal direction
+        assert_eq!(3 * 2 + 4 + 2 * 2, windows.len());
+        assert!(windows.contains(&vec![Coord::new(0, 3), Coord::new(1, 2), Coord::new(2, 1)]));
+        assert!(windows.iter().all(|window| window.len() == 3));
+    }
+
+    #[test]
+    fn test_check_winner_bottom_up_diagonal_bottom_start() {
+        let mut grid = Grid::new(9, 9, 4);
+        grid.set_cell(8, 2, PlayerId(1));
+        grid.set_cell(7, 3, PlayerId(1));
+        grid.set_cell(6, 4, PlayerId(1));
+        grid.set_cell(5, 5, PlayerId(1));
+        assert!(check_winner(&grid).is_some());
+    }
+
+    #[test]
+    fn test_check_winner_horizontal_across_the_edge_with_wrap() {
+        let mut grid = Grid::new(5, 5, 3);
+        grid.set_cell(2, 4, PlayerId(1));
+        grid.set_cell(2, 0, PlayerId(1));
+        grid.set_cell(2, 1, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+        grid.wrap = true;
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_check_winner_vertical_across_the_edge_with_wrap() {
+        let mut grid = Grid::new(4, 4, 3);
+        grid.wrap = true;
+        grid.set_cell(3, 1, PlayerId(2));
+        grid.set_cell(0, 1, PlayerId(2));
+        grid.set_cell(1, 1, PlayerId(2));
+        assert_eq!(GameState::Win(PlayerId(2)), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_check_winner_diagonals_across_the_edge_with_wrap() {
+        let mut grid = Grid::new(4, 5, 4);
+        grid.wrap = true;
+        grid.set_cell(2, 3, PlayerId(1));
+        grid.set_cell(3, 4, PlayerId(1));
+        grid.set_cell(0, 0, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+        grid.set_cell(1, 1, PlayerId(1));
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+
+        let mut grid = Grid::new(4, 5, 4);
+        grid.wrap = true;
+        grid.set_cell(1, 3, PlayerId(1));
+        grid.set_cell(0, 4, PlayerId(1));
+        grid.set_cell(3, 0, PlayerId(1));
+        grid.set_cell(2, 1, PlayerId(1));
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_check_winner_wrap_doesnt_count_cells_twice() {
+        // a full row of three is no line of four, even though it goes around
+        let mut grid = Grid::new(3, 3, 4);
+        grid.wrap = true;
+        grid.set_cell(1, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(1, 2, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+        assert!(windows(&grid).is_empty());
+    }
+
+    #[test]
+    fn test_check_winner_wrap_diagonal_longer_than_the_sides() {
+        // on 2x3 the diagonal goes through all six cells before it is back at the start
+        let mut grid = Grid::new(2, 3, 5);
+        grid.wrap = true;
+        for &(row, col) in &[(0, 0), (1, 1), (0, 2), (1, 0), (0, 1)] {
+            grid.set_cell(row, col, PlayerId(1));
+        }
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_windows_with_wrap() {
+        let mut grid = Grid::new(3, 4, 3);
+        grid.wrap = true;
+        let windows = windows(&grid);
+        // every cell starts a window in every direction, but a whole column only counts once
+        assert_eq!(12 + 4 + 12 + 12, windows.len());
+        assert!(windows.contains(&vec![Coord::new(1, 3), Coord::new(1, 0), Coord::new(1, 1)]));
+        assert_eq!(1, windows.iter()
+            .filter(|window| window.iter().all(|coord| coord.column() == 0))
+            .count());
+    }
+
+    #[test]
+    fn test_check_winner_blocked_cell_breaks_the_line() {
+        let mut grid = Grid::new(1, 5, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(0, 1, PlayerId(1));
+        grid.block_cell(0, 2);
+        grid.set_cell(0, 3, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+        grid.set_cell(0, 4, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+    }
+
+    #[test]
+    fn test_check_winner_draw_with_blocked_cells() {
+        let mut grid = Grid::from_shape("_#_\n#_#\n_#_", 3).unwrap();
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(0, 2, PlayerId(2));
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(2, 0, PlayerId(2));
+        assert_eq!(GameState::Mid, check_winner(&grid));
+        grid.set_cell(2, 2, PlayerId(2));
+        assert_eq!(GameState::Draw, check_winner(&grid));
+    }
+
+    #[test]
+    fn test_check_winner_three_dimensions() {
+        let lines: Vec<Vec<Vec<usize>>> = vec![
+            // along the layers
+            vec![vec![0, 2, 1], vec![1, 2, 1], vec![2, 2, 1], vec![3, 2, 1]],
+            // diagonal through a vertical plane
+            vec![vec![0, 0, 3], vec![1, 1, 3], vec![2, 2, 3], vec![3, 3, 3]],
+            // the space diagonals
+            vec![vec![0, 0, 0], vec![1, 1, 1], vec![2, 2, 2], vec![3, 3, 3]],
+            vec![vec![3, 0, 0], vec![2, 1, 1], vec![1, 2, 2], vec![0, 3, 3]],
+            vec![vec![0, 3, 0], vec![1, 2, 1], vec![2, 1, 2], vec![3, 0, 3]],
+        ];
+        for line in lines {
+            let mut grid = Grid::with_dimensions(vec![4, 4, 4], 4);
+            for (index, coord) in line.into_iter().enumerate() {
+                assert_eq!(GameState::Mid, check_winner(&grid));
+                grid.make_move(Move::at(Coord(coord), PlayerId(2), PlayerId(2)));
+                if index == 1 {
+                    grid.make_move(Move::at(Coord(vec![1, 0, 2]), PlayerId(1), PlayerId(1)));
+                }
+            }
+            assert_eq!(GameState::Win(PlayerId(2)), check_winner(&grid));
+        }
+    }
+
+    #[test]
+    fn test_check_winner_four_dimensions() {
+        let mut grid = Grid::with_dimensions(vec![3, 3, 3, 3], 3);
+        grid.make_move(Move::at(Coord(vec![0, 2, 0, 1]), PlayerId(1), PlayerId(1)));
+        grid.make_move(Move::at(Coord(vec![1, 1, 1, 1]), PlayerId(1), PlayerId(1)));
+        assert_eq!(GameState::Mid, check_winner(&grid));
+        grid.make_move(Move::at(Coord(vec![2, 0, 2, 1]), PlayerId(1), PlayerId(1)));
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_windows_three_dimensions() {
+        // the 76 winning lines of Qubic
+        assert_eq!(76, windows(&Grid::with_dimensions(vec![4, 4, 4], 4)).len());
+        // and the 49 of 3x3x3
+        assert_eq!(49, windows(&Grid::with_dimensions(vec![3, 3, 3], 3)).len());
+    }
+
+    #[test]
+    fn test_check_winner_after_only_looks_at_the_new_cells() {
+        let mut grid = Grid::new(9, 9, 4);
+        grid.set_cell(3, 1, PlayerId(1));
+        grid.set_cell(3, 2, PlayerId(1));
+        grid.set_cell(3, 4, PlayerId(1));
+        assert_eq!(GameState::Mid, check_winner_after(&grid, &[Coord::new(3, 4)]));
+        grid.set_cell(3, 3, PlayerId(1));
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner_after(&grid, &[Coord::new(3, 3)]));
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner_after(&grid, &[Coord::new(3, 1)]));
+        assert_eq!(GameState::Mid, check_winner_after(&grid, &[Coord::new(2, 2)]));
+    }
+
+    #[test]
+    fn test_check_winner_after_with_wrap() {
+        let mut grid = Grid::new(4, 4, 4);
+        grid.wrap = true;
+        grid.set_cell(1, 2, PlayerId(1));
+        grid.set_cell(1, 3, PlayerId(1));
+        grid.set_cell(1, 0, PlayerId(1));
+        assert_eq!(GameState::Mid, check_winner_after(&grid, &[Coord::new(1, 0)]));
+        grid.set_cell(1, 1, PlayerId(1));
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner_after(&grid, &[Coord::new(1, 1)]));
+        // a column of three doesn't become a line of four by going around
+        let mut grid = Grid::new(3, 5, 4);
+        grid.wrap = true;
+        for row in 0 .. 3 {
+            grid.set_cell(row, 0, PlayerId(2));
+        }
+        assert_eq!(GameState::Mid, check_winner_after(&grid, &[Coord::new(1, 0)]));
+    }
+
+    #[test]
+    fn test_check_winner_hex_lines() {
+        let lines = vec![
+            vec![(2, 0), (2, 1), (2, 2)],
+            vec![(0, 3), (1, 3), (2, 3)],
+            vec![(0, 4), (1, 3), (2, 2)],
+        ];
+        for line in lines {
+            let mut grid = Grid::hex(5, 5, 3);
+            for (row, col) in line {
+                assert_eq!(GameState::Mid, check_winner(&grid));
+                grid.set_cell(row, col, PlayerId(1));
+            }
+            assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+        }
+    }
+
+    #[test]
+    fn test_check_winner_hex_other_diagonal_isnt_a_line() {
+        // on a hex board these cells don't touch each other
+        let mut grid = Grid::hex(5, 5, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(2, 2, PlayerId(1));
+        assert_eq!(GameState::Mid, check_winner(&grid));
+        assert_eq!(GameState::Mid, check_winner_after(&grid, &[Coord::new(1, 1)]));
+        assert_eq!(3 * 5 + 3 * 5 + 3 * 3, windows(&grid).len());
+    }
+
+    #[test]
+    fn test_check_winner_reports_the_pattern() {
+        let mut grid = Grid::new(4, 4, 4);
+        grid.patterns.push(Pattern::square(2));
+        grid.set_cell(1, 1, PlayerId(2));
+        grid.set_cell(1, 2, PlayerId(2));
+        grid.set_cell(2, 1, PlayerId(2));
+        assert_eq!((GameState::Mid, Vec::new()), check_winner_lines(&grid));
+        grid.set_cell(2, 2, PlayerId(2));
+        let (state, lines) = check_winner_lines(&grid);
+        assert_eq!(GameState::Win(PlayerId(2)), state);
+        assert_eq!(1, lines.len());
+        assert_eq!(Shape::Pattern("square".to_string()), lines[0].shape);
+        assert_eq!(4, lines[0].cells.len());
+        assert_eq!(GameState::Win(PlayerId(2)), check_winner_after(&grid, &[Coord::new(2, 2)]));
+    }
+
+    #[test]
+    fn test_check_winner_pattern_in_any_orientation() {
+        for cells in &[[(0, 1), (1, 0), (1, 1)], [(2, 2), (2, 1), (1, 2)]] {
+            let mut grid = Grid::new(3, 3, 3);
+            grid.patterns.push(Pattern::l_tromino());
+            for &(row, col) in cells {
+                grid.set_cell(row, col, PlayerId(1));
+            }
+            assert_eq!(GameState::Win(PlayerId(1)), check_winner(&grid));
+        }
+    }
+
+    #[test]
+    fn test_check_winner_lines_still_count_with_patterns() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.patterns.push(Pattern::corners(3, 3));
+        grid.set_cell(1, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(1, 2, PlayerId(1));
+        let (state, lines) = check_winner_lines(&grid);
+        assert_eq!(GameState::Win(PlayerId(1)), state);
+        assert_eq!(vec![Shape::Line(vec![0, 1])],
+                   lines.into_iter().map(|line| line.shape).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_winning_lines_reports_cells_and_direction() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 2, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(2, 0, PlayerId(1));
+        assert_eq!(vec![WinningLine {
+            symbol: PlayerId(1),
+            cells: vec![Coord::new(0, 2), Coord::new(1, 1), Coord::new(2, 0)],
+            shape: Shape::Line(vec![1, -1]),
+        }], winning_lines(&grid));
+    }
+
+    #[test]
+    fn test_winning_lines_one_move_completes_two_lines() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(2));
+        grid.set_cell(0, 1, PlayerId(2));
+        grid.set_cell(1, 2, PlayerId(2));
+        grid.set_cell(2, 2, PlayerId(2));
+        assert!(winning_lines(&grid).is_empty());
+        grid.set_cell(0, 2, PlayerId(2));
+        let (state, lines) = check_winner_lines(&grid);
+        assert_eq!(GameState::Win(PlayerId(2)), state);
+        assert_eq!(2, lines.len());
+        assert!(lines.iter().all(|line| line.cells.contains(&Coord::new(0, 2))));
+    }
+
+    #[test]
+    fn test_winning_lines_overline_is_one_line() {
+        let mut grid = Grid::new(5, 5, 3);
+        for col in 0 .. 5 {
+            grid.set_cell(2, col, PlayerId(1));
+        }
+        let lines = winning_lines(&grid);
+        assert_eq!(1, lines.len());
+        assert_eq!(5, lines[0].cells.len());
+    }
+
+    #[test]
+    fn test_winning_lines_across_the_edge() {
+        let mut grid = Grid::new(4, 4, 3);
+        grid.wrap = true;
+        grid.set_cell(1, 3, PlayerId(1));
+        grid.set_cell(1, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(1));
+        let lines = winning_lines(&grid);
+        assert_eq!(1, lines.len());
+        assert_eq!(vec![Coord::new(1, 3), Coord::new(1, 0), Coord::new(1, 1)], lines[0].cells);
+    }
+
+    #[test]
+    fn test_pattern_places() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.patterns.push(Pattern::corners(3, 3));
+        grid.patterns.push(Pattern::square(2));
+        let places = pattern_indices(&grid);
+        // the corners only fit once, a square at four places
+        assert_eq!(1, places.iter().filter(|&&(number, _)| number == 0).count());
+        assert_eq!(4, places.iter().filter(|&&(number, _)| number == 1).count());
+    }
+
+    #[test]
+    fn test_line_tally_counts_every_run() {
+        let mut grid = Grid::new(4, 4, 3);
+        for col in 0 .. 4 {
+            grid.set_cell(0, col, PlayerId(1));
+        }
+        grid.set_cell(1, 0, PlayerId(2));
+        grid.set_cell(2, 0, PlayerId(2));
+        grid.set_cell(3, 0, PlayerId(2));
+        assert_eq!((2, 1), line_tally(&grid));
+    }
+
+    #[test]
+    fn test_scoring_game_goes_on_until_the_board_is_full() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.scoring = true;
+        for col in 0 .. 3 {
+            grid.set_cell(0, col, PlayerId(1));
+        }
+        assert_eq!(GameState::Mid, check_winner(&grid));
+        for &(row, col) in &[(1, 0), (1, 1), (1, 2), (2, 0), (2, 1), (2, 2)] {
+            grid.set_cell(row, col, PlayerId(2));
+        }
+        assert_eq!(GameState::Scores(1, 2), check_winner(&grid));
+        assert_eq!(GameState::Scores(1, 2), check_winner_after(&grid, &[Coord::new(2, 2)]));
+    }
 }
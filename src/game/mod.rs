@@ -1,19 +1,136 @@
 #[derive(Debug)]
 #[derive(Copy, Clone)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PlayerId(pub u32);
 
+impl PlayerId {
+    // The other player of a two player game
+    pub fn opponent(&self) -> PlayerId {
+        if self.0 == 1 { PlayerId(2) } else { PlayerId(1) }
+    }
+}
+
+// In the variants where a player chooses the symbol, the symbols don't belong to anybody.
+// They are still stored like the marks of player 1 and 2, so all the line checks keep working.
+pub const X: PlayerId = PlayerId(1);
+pub const O: PlayerId = PlayerId(2);
+
+// The sides of Order and Chaos
+pub const ORDER: PlayerId = PlayerId(1);
+pub const CHAOS: PlayerId = PlayerId(2);
+
 #[derive(Debug)]
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CellState {
     Set(PlayerId),
     Unset,
+    // Not part of the playing area, nobody can ever place anything here
+    Blocked,
 }
 
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum GameState {
     Win(PlayerId),
     Draw,
     Mid,
+    // The points of player 1 and player 2 at the end of a game where every line scores
+    Scores(u32, u32),
+}
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Variant {
+    // Every player places their own mark, the first one to complete a line wins.
+    Standard,
+    // Every turn the player chooses X or O. Whoever completes a line of either symbol wins.
+    Wild,
+    // Both sides choose X or O. Order wins by completing a line of either symbol, Chaos wins
+    // if the board is filled without one.
+    OrderAndChaos,
+}
+
+impl Variant {
+    // The symbols a player may place in this variant
+    pub fn symbols(&self, player: PlayerId) -> Vec<PlayerId> {
+        match *self {
+            Variant::Standard => vec![player],
+            Variant::Wild | Variant::OrderAndChaos => vec![X, O],
+        }
+    }
+
+    pub fn has_symbol_choice(&self) -> bool {
+        *self != Variant::Standard
+    }
+}
+
+// A position on a board of any dimension. The last two values are the row and the column,
+// the ones before that select the layer.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Coord(pub Vec<usize>);
+
+impl Coord {
+    pub fn new(row: usize, column: usize) -> Coord {
+        Coord(vec![row, column])
+    }
+
+    pub fn in_layer(layer: &[usize], row: usize, column: usize) -> Coord {
+        let mut values = layer.to_vec();
+        values.push(row);
+        values.push(column);
+        Coord(values)
+    }
+
+    pub fn row(&self) -> usize {
+        self.0[self.0.len() - 2]
+    }
+
+    pub fn column(&self) -> usize {
+        self.0[self.0.len() - 1]
+    }
+
+    pub fn layer(&self) -> &[usize] {
+        &self.0[.. self.0.len() - 2]
+    }
+}
+
+// A move places `symbol` on a cell. The player making it is kept separately, since they don't
+// have to be the same in every variant.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Move {
+    pub coord: Coord,
+    pub player: PlayerId,
+    pub symbol: PlayerId,
+}
+
+impl Move {
+    // A move where the player places their own mark
+    pub fn new(row: usize, column: usize, player: PlayerId) -> Move {
+        Move::with_symbol(row, column, player, player)
+    }
+
+    pub fn with_symbol(row: usize, column: usize, player: PlayerId, symbol: PlayerId) -> Move {
+        Move::at(Coord::new(row, column), player, symbol)
+    }
+
+    pub fn at(coord: Coord, player: PlayerId, symbol: PlayerId) -> Move {
+        Move {
+            coord,
+            player,
+            symbol,
+        }
+    }
 }
 
 impl PartialEq for CellState {
@@ -25,6 +142,12 @@ impl PartialEq for CellState {
                     _ => false,
                 }
             },
+            CellState::Blocked => {
+                match *other {
+                    CellState::Blocked => true,
+                    _ => false,
+                }
+            },
             CellState::Set(PlayerId(own_id)) => {
                 match *other {
                     CellState::Unset => false,
@@ -37,19 +160,28 @@ impl PartialEq for CellState {
 }
 
 impl GameState {
+    #[cfg(test)]
     fn is_some(&self) -> bool {
         match *self {
             GameState::Draw => false,
             GameState::Mid => false,
+            GameState::Scores(..) => false,
             _ => true,
         } 
     }
 
+    #[cfg(test)]
     fn is_none(&self) -> bool {
         !self.is_some()
     }
 }
 
 
+pub mod algebraic;
 pub mod grid;
 pub mod grid_observer;
+pub mod notation;
+pub mod opening;
+pub mod pattern;
+pub mod record;
+pub mod topology;
@@ -0,0 +1,267 @@
+use super::grid::{checked_cell_count, Grid};
+use super::topology::Topology;
+use super::{CellState, Variant, Move, Coord, X, O};
+
+// A position in one line, like FEN for chess:
+//
+//     3x3 3 standard x1o/1x1/3 2
+//
+// The fields are the size of every dimension, the cells needed for a line, the variant, the
+// cells and the side to move. Rules that differ from the default follow in a sixth field,
+// separated by commas: 'wrap', 'hex', 'score', 'stones=1/2' for the stones of the first and
+// of every later turn, and 'handicap=N' for stones beyond the ones of the turns played.
+//
+// The rows of the cells are separated by '/' and the layers of boards with more dimensions
+// by '|'. 'x' and 'o' are stones of the first and of the second symbol, '#' is a blocked
+// cell and a number stands for that many free cells. Patterns aren't part of the notation.
+pub fn to_notation(grid: &Grid) -> String {
+    let dimensions: Vec<String> = grid.dimensions().iter().map(|size| size.to_string()).collect();
+    let layers: Vec<String> = grid.layers().iter()
+        .map(|layer| {
+            let rows: Vec<String> = (0 .. grid.row_count)
+                .map(|row| notate_row(grid, layer, row))
+                .collect();
+            rows.join("/")
+        })
+        .collect();
+    let mut notation = format!("{} {} {} {} {}", dimensions.join("x"), grid.to_win,
+                               variant_name(grid.variant), layers.join("|"), grid.to_move().0);
+    let mut options = Vec::new();
+    if grid.wrap {
+        options.push("wrap".to_string());
+    }
+    if grid.topology() == Topology::Hex {
+        options.push("hex".to_string());
+    }
+    if grid.scoring {
+        options.push("score".to_string());
+    }
+    if grid.first_turn_stones != 1 || grid.stones_per_turn != 1 {
+        options.push(format!("stones={}/{}", grid.first_turn_stones, grid.stones_per_turn));
+    }
+    if grid.extra_stones() > 0 {
+        options.push(format!("handicap={}", grid.extra_stones()));
+    }
+    if !options.is_empty() {
+        notation += " ";
+        notation += &options.join(",");
+    }
+    notation
+}
+
+fn notate_row(grid: &Grid, layer: &[usize], row: usize) -> String {
+    let mut output = String::new();
+    let mut free = 0;
+    for column in 0 .. grid.column_count {
+        let symbol = match *grid.get_cell_at(&Coord::in_layer(layer, row, column)) {
+            CellState::Unset => {
+                free += 1;
+                continue;
+            },
+            CellState::Blocked => '#',
+            CellState::Set(X) => 'x',
+            CellState::Set(_) => 'o',
+        };
+        if free > 0 {
+            output += &free.to_string();
+            free = 0;
+        }
+        output.push(symbol);
+    }
+    if free > 0 {
+        output += &free.to_string();
+    }
+    output
+}
+
+pub fn variant_name(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Standard => "standard",
+        Variant::Wild => "wild",
+        Variant::OrderAndChaos => "order-chaos",
+    }
+}
+
+pub fn parse_variant(name: &str) -> Result<Variant, String> {
+    match name {
+        "standard" => Ok(Variant::Standard),
+        "wild" => Ok(Variant::Wild),
+        "order-chaos" => Ok(Variant::OrderAndChaos),
+        other => Err(format!("Unknown variant '{}'.", other)),
+    }
+}
+
+// Reads a position written by to_notation. The position may already be decided.
+pub fn parse_notation(notation: &str) -> Result<Grid, String> {
+    let fields: Vec<&str> = notation.split_whitespace().collect();
+    if fields.len() != 5 && fields.len() != 6 {
+        return Err(format!("Expected 5 or 6 fields, got {}.", fields.len()));
+    }
+    let dimensions: Vec<usize> = fields[0].split('x')
+        .map(|size| size.parse().ok().filter(|&size| size > 0))
+        .collect::<Option<_>>()
+        .filter(|dimensions: &Vec<usize>| dimensions.len() >= 2)
+        .ok_or_else(|| format!("'{}' is not a board size like 3x3.", fields[0]))?;
+    // the board is made before the cells are read, so a huge size must not get that far
+    checked_cell_count(&dimensions)?;
+    let to_win = fields[1].parse()
+        .map_err(|_| format!("'{}' is not a number of cells.", fields[1]))?;
+    let variant = parse_variant(fields[2])?;
+    let to_move = match fields[4] {
+        "1" => X,
+        "2" => O,
+        other => return Err(format!("'{}' is not a side, the sides are 1 and 2.", other)),
+    };
+
+    let (mut hex, mut wrap, mut scoring) = (false, false, false);
+    let mut stones_per_turn = (1, 1);
+    let mut handicap = 0;
+    for option in fields.get(5).map_or(Vec::new(), |options| options.split(',').collect()) {
+        let mut parts = option.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("wrap"), None) => wrap = true,
+            (Some("hex"), None) => hex = true,
+            (Some("score"), None) => scoring = true,
+            (Some("stones"), Some(value)) => {
+                let stones: Vec<u32> = value.split('/')
+                    .filter_map(|part| part.parse().ok())
+                    .collect();
+                if stones.len() != 2 {
+                    return Err(format!("'{}' is not a number of stones like 1/2.", value));
+                }
+                if stones.contains(&0) {
+                    return Err("A turn has at least one stone.".to_string());
+                }
+                stones_per_turn = (stones[0], stones[1]);
+            },
+            (Some("handicap"), Some(value)) => {
+                handicap = value.parse().map_err(|_| format!("'{}' is not a number.", value))?;
+            },
+            _ => return Err(format!("Unknown option '{}'.", option)),
+        }
+    }
+
+    let mut grid = if hex {
+        if dimensions.len() != 2 {
+            return Err("Hex boards have two dimensions.".to_string());
+        }
+        Grid::hex(dimensions[0], dimensions[1], to_win)
+    } else {
+        Grid::with_dimensions(dimensions, to_win)
+    };
+    grid.variant = variant;
+    grid.wrap = wrap;
+    grid.scoring = scoring;
+    grid.first_turn_stones = stones_per_turn.0;
+    grid.stones_per_turn = stones_per_turn.1;
+
+    let stones = read_cells(&mut grid, fields[3])?;
+    grid.place_position(&stones, to_move, handicap)?;
+    Ok(grid)
+}
+
+// Blocks the blocked cells and returns the stones
+fn read_cells(grid: &mut Grid, cells: &str) -> Result<Vec<Move>, String> {
+    let layers: Vec<&str> = cells.split('|').collect();
+    if layers.len() != grid.layers().len() {
+        return Err(format!("Expected {} layers, got {}.", grid.layers().len(), layers.len()));
+    }
+    let mut stones = Vec::new();
+    for (layer, layer_cells) in grid.layers().into_iter().zip(layers) {
+        let rows: Vec<&str> = layer_cells.split('/').collect();
+        if rows.len() != grid.row_count {
+            return Err(format!("Expected {} rows, got {}.", grid.row_count, rows.len()));
+        }
+        for (row, row_cells) in rows.into_iter().enumerate() {
+            let mut column = 0;
+            let mut free = String::new();
+            for symbol in row_cells.chars().chain(Some(' ')) {
+                if symbol.is_ascii_digit() {
+                    free.push(symbol);
+                    continue;
+                }
+                if !free.is_empty() {
+                    column += free.parse::<usize>().map_err(|_| format!("'{}' is too big.", free))?;
+                    free.clear();
+                }
+                if symbol == ' ' {
+                    break;
+                }
+                if column >= grid.column_count {
+                    return Err(format!("Row {} has more than {} cells.", row, grid.column_count));
+                }
+                let coord = Coord::in_layer(&layer, row, column);
+                match symbol {
+                    '#' => { grid.block_cell_at(&coord); },
+                    'x' => stones.push(Move::at(coord, X, X)),
+                    'o' => stones.push(Move::at(coord, O, O)),
+                    other => return Err(format!("Unknown cell '{}' in row {}.", other, row)),
+                }
+                column += 1;
+            }
+            if column != grid.column_count {
+                return Err(format!("Row {} has {} cells instead of {}.", row, column,
+                                   grid.column_count));
+            }
+        }
+    }
+    Ok(stones)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::MAX_CELLS;
+    use ::game::grid_observer::check_winner;
+    use ::game::GameState;
+
+    #[test]
+    fn test_to_notation() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, X);
+        grid.set_cell(0, 2, O);
+        grid.set_cell(1, 1, X);
+        assert_eq!("3x3 3 standard x1o/1x1/3 2", to_notation(&grid));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let notations = [
+            "3x3 3 standard x1o/1x1/3 2",
+            "3x3 3 wild 3/1x1/3 2",
+            "5x5 4 standard ##3/#4/5/4#/3## 1 hex",
+            "2x2x2 2 standard x1/2|2/1o 1 wrap,score",
+            "19x19 6 standard 19/19/19/19/19/19/19/19/19/9x9/19/19/19/19/19/19/19/19/19 2 \
+             stones=1/2",
+            "3x3 3 standard o2/3/3 1 handicap=1",
+        ];
+        for notation in notations.iter() {
+            let grid = parse_notation(notation).unwrap();
+            assert_eq!(*notation, to_notation(&grid));
+        }
+    }
+
+    #[test]
+    fn test_parse_notation() {
+        let grid = parse_notation("3x3 3 standard xxx/oo1/3 2").unwrap();
+        assert_eq!(CellState::Set(O), *grid.get_cell(1, 1));
+        assert_eq!(O, grid.to_move());
+        assert_eq!(GameState::Win(X), check_winner(&grid));
+    }
+
+    #[test]
+    fn test_parse_notation_errors() {
+        assert!(parse_notation("3x3 3 standard x1o/1x1/3").is_err());
+        assert!(parse_notation("3x0 3 standard x1o/1x1/3 2").is_err());
+        assert!(parse_notation("3x3 3 standard x1o/1x1/4 2").is_err());
+        assert!(parse_notation("3x3 3 standard x1o/1x1 2").is_err());
+        assert!(parse_notation("3x3 3 standard x1o/1x1/3 1").is_err());
+        assert!(parse_notation("3x3 3 classic x1o/1x1/3 2").is_err());
+        assert_eq!(Some(format!("A board can have {} cells at most.", MAX_CELLS)),
+                   parse_notation("100000x100000 3 standard 1 1").err());
+        assert!(parse_notation("4294967296x4294967296x2 3 standard 1 1").is_err());
+        assert_eq!(Some("A turn has at least one stone.".to_string()),
+                   parse_notation("3x3 3 standard 3/3/3 1 stones=0/0").err());
+        assert!(parse_notation("3x3 3 standard 3/3/3 1 stones=1/0").is_err());
+    }
+}
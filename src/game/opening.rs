@@ -0,0 +1,143 @@
+use super::grid::Grid;
+use super::{Coord, Move};
+
+// How the first stones of a game are placed. Whoever places the first stone tends to have
+// the upper hand on big boards, the protocols let the other player take over a position that
+// looks too good.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Opening {
+    // The first player simply starts
+    Free,
+    // After the first stone the second player may swap sides and so take it over
+    Swap,
+    // The first player places three stones, two of side 1 and one of side 2. The second
+    // player then picks a side, or places two more stones, one of each side, and lets the
+    // first player pick.
+    Swap2,
+    // Like the beginning of Soosyrv-8: the first player places three stones and the second
+    // player may swap. Side 2 places the fourth stone, after which side 1 may swap again.
+    // The choice between several offered fifth stones is left out.
+    Soosyrv,
+    // One of the BALANCED_OPENINGS is placed, then the second player may swap
+    Balanced(usize),
+}
+
+// What a player decides when the protocol gives them a choice. The sides are meant from the
+// point of view of whoever chooses.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OpeningChoice {
+    // Keep playing the side they have
+    Keep,
+    // Take the other side
+    Swap,
+    // Place two more stones, one of each side, and leave the choice to the other player
+    PlaceTwo,
+}
+
+// Three stone openings around the centre of the board, as (row, column) away from it.
+// None of them gives either side an obvious threat, so they make for even games with five
+// in a row.
+pub const BALANCED_OPENINGS: [[(isize, isize); 3]; 4] = [
+    [(0, 0), (-1, 0), (1, 1)],
+    [(0, 0), (-1, 1), (1, -1)],
+    [(0, 0), (-1, 0), (0, 2)],
+    [(0, 0), (-1, 1), (2, 0)],
+];
+
+impl Opening {
+    // Reads 'free', 'swap', 'swap2', 'soosyrv' or 'balanced' with the number of the
+    // opening, like 'balanced:2'
+    pub fn parse(name: &str) -> Result<Opening, String> {
+        match name {
+            "free" => Ok(Opening::Free),
+            "swap" => Ok(Opening::Swap),
+            "swap2" => Ok(Opening::Swap2),
+            "soosyrv" => Ok(Opening::Soosyrv),
+            "balanced" => Ok(Opening::Balanced(1)),
+            _ if name.starts_with("balanced:") => {
+                match name["balanced:".len() ..].parse() {
+                    Ok(number) if number >= 1 && number <= BALANCED_OPENINGS.len() =>
+                        Ok(Opening::Balanced(number)),
+                    _ => Err(format!("the balanced openings are numbered 1 to {}",
+                                     BALANCED_OPENINGS.len())),
+                }
+            },
+            _ => Err(format!("unknown opening '{}', choose free, swap, swap2, soosyrv or \
+                              balanced", name)),
+        }
+    }
+
+    // The name parse reads
+    pub fn name(&self) -> String {
+        match *self {
+            Opening::Free => "free".to_string(),
+            Opening::Swap => "swap".to_string(),
+            Opening::Swap2 => "swap2".to_string(),
+            Opening::Soosyrv => "soosyrv".to_string(),
+            Opening::Balanced(number) => format!("balanced:{}", number),
+        }
+    }
+}
+
+// Places balanced opening `number`, counted from 1, in the centre of the board. The stones
+// belong to side 1 and 2 in turn, like regular turns.
+pub fn place_balanced(grid: &mut Grid, number: usize) -> Result<(), String> {
+    let stones = number.checked_sub(1)
+        .and_then(|index| BALANCED_OPENINGS.get(index))
+        .ok_or_else(|| format!("there is no balanced opening {}", number))?;
+    let centre: Vec<usize> = grid.dimensions().iter().map(|size| size / 2).collect();
+    let mut coords = Vec::new();
+    for &(row, column) in stones.iter() {
+        let step = {
+            let mut step = vec![0; centre.len() - 2];
+            step.push(row);
+            step.push(column);
+            step
+        };
+        match grid.offset(&Coord(centre.clone()), &step) {
+            Some(ref coord) if !coords.contains(coord) => coords.push(coord.clone()),
+            _ => return Err("the opening doesn't fit on the board".to_string()),
+        }
+    }
+    for coord in coords {
+        let side = grid.to_move();
+        if !grid.make_move(Move::at(coord, side, side)) {
+            return Err("the opening doesn't fit on the board".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::{CellState, PlayerId};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(Opening::Swap2), Opening::parse("swap2"));
+        assert_eq!(Ok(Opening::Balanced(3)), Opening::parse("balanced:3"));
+        assert!(Opening::parse("balanced:0").is_err());
+        assert!(Opening::parse("pie").is_err());
+        for opening in &[Opening::Free, Opening::Soosyrv, Opening::Balanced(4)] {
+            assert_eq!(Ok(*opening), Opening::parse(&opening.name()));
+        }
+    }
+
+    #[test]
+    fn test_place_balanced() {
+        let mut grid = Grid::new(15, 15, 5);
+        place_balanced(&mut grid, 1).unwrap();
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell(7, 7));
+        assert_eq!(CellState::Set(PlayerId(2)), *grid.get_cell(6, 7));
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell(8, 8));
+        assert_eq!(PlayerId(2), grid.to_move());
+        assert!(place_balanced(&mut Grid::new(3, 3, 3), 3).is_err());
+    }
+}
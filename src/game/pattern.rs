@@ -0,0 +1,149 @@
+use super::topology::Topology;
+
+// A shape that wins when all of its cells hold the same symbol, just like a line. It counts
+// anywhere on the board and in every orientation, turned and mirrored.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Pattern {
+    pub name: String,
+    // The positions of the cells relative to each other, in (row, column) or with more
+    // dimensions like a Coord
+    pub cells: Vec<Vec<isize>>,
+}
+
+impl Pattern {
+    pub fn new(name: &str, cells: Vec<Vec<isize>>) -> Pattern {
+        Pattern {
+            name: name.to_string(),
+            cells,
+        }
+    }
+
+    // Reads a pattern drawn like a board, one line per row: 'X' is a cell of the pattern,
+    // '_' or '.' a cell that doesn't matter.
+    pub fn parse(name: &str, shape: &str) -> Result<Pattern, String> {
+        let mut cells = Vec::new();
+        for (row, line) in shape.lines().enumerate() {
+            for (column, symbol) in line.trim_end().chars().enumerate() {
+                match symbol {
+                    'X' | 'x' => cells.push(vec![row as isize, column as isize]),
+                    '_' | '.' | ' ' => {},
+                    other => return Err(format!("unknown symbol '{}' in row {}", other, row)),
+                }
+            }
+        }
+        if cells.is_empty() {
+            return Err("the pattern has no cells".to_string());
+        }
+        Ok(Pattern::new(name, cells))
+    }
+
+    // The pattern drawn the way parse reads it, from its first row and column on
+    pub fn shape(&self) -> String {
+        let min = |axis: usize| self.cells.iter().map(|cell| cell[axis]).min().unwrap_or(0);
+        let max = |axis: usize| self.cells.iter().map(|cell| cell[axis]).max().unwrap_or(0);
+        let rows: Vec<String> = (min(0) ..= max(0))
+            .map(|row| {
+                (min(1) ..= max(1))
+                    .map(|column| if self.cells.contains(&vec![row, column]) { 'X' } else { '_' })
+                    .collect()
+            })
+            .collect();
+        rows.join("\n")
+    }
+
+    // A filled square of `size` x `size` cells
+    pub fn square(size: usize) -> Pattern {
+        let size = size as isize;
+        let cells = (0 .. size)
+            .flat_map(|row| (0 .. size).map(move |column| vec![row, column]))
+            .collect();
+        Pattern::new("square", cells)
+    }
+
+    // Three cells in an L
+    pub fn l_tromino() -> Pattern {
+        Pattern::new("L-tromino", vec![vec![0, 0], vec![1, 0], vec![1, 1]])
+    }
+
+    // The four corners of a rectangle, with the size of the board these are its corners
+    pub fn corners(row_count: usize, column_count: usize) -> Pattern {
+        let last_row = row_count as isize - 1;
+        let last_column = column_count as isize - 1;
+        Pattern::new("four corners", vec![vec![0, 0], vec![0, last_column],
+                                          vec![last_row, 0], vec![last_row, last_column]])
+    }
+
+    // The cells of the pattern in every orientation on a board with the given topology and
+    // number of dimensions. A pattern with fewer dimensions than the board lies in its last
+    // ones, before it is turned. Every orientation is shifted so its smallest offset along
+    // each axis is 0, and orientations that look the same only appear once.
+    pub fn orientations(&self, topology: Topology, dimensions: usize) -> Vec<Vec<Vec<isize>>> {
+        let cells: Vec<Vec<isize>> = self.cells.iter()
+            .map(|cell| {
+                let mut padded = vec![0; dimensions.saturating_sub(cell.len())];
+                padded.extend(cell);
+                padded
+            })
+            .collect();
+        let mut result: Vec<Vec<Vec<isize>>> = topology.orientations(&cells).into_iter()
+            .map(normalize)
+            .collect();
+        result.sort();
+        result.dedup();
+        result
+    }
+}
+
+fn normalize(mut cells: Vec<Vec<isize>>) -> Vec<Vec<isize>> {
+    if let Some(first) = cells.first() {
+        let mut minimum = first.clone();
+        for cell in &cells {
+            for (lowest, &value) in minimum.iter_mut().zip(cell) {
+                *lowest = (*lowest).min(value);
+            }
+        }
+        for cell in &mut cells {
+            for (value, lowest) in cell.iter_mut().zip(&minimum) {
+                *value -= lowest;
+            }
+        }
+    }
+    cells.sort();
+    cells
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let pattern = Pattern::parse("corner", "XX\nX_\n").unwrap();
+        assert_eq!(vec![vec![0, 0], vec![0, 1], vec![1, 0]], pattern.cells);
+        assert!(Pattern::parse("empty", "__\n").is_err());
+        assert!(Pattern::parse("wrong", "X#\n").is_err());
+    }
+
+    #[test]
+    fn test_shape() {
+        assert_eq!("X_\nXX", Pattern::l_tromino().shape());
+        let t = Pattern::parse("T", "XXX\n_X_\n").unwrap();
+        assert_eq!(t, Pattern::parse("T", &t.shape()).unwrap());
+    }
+
+    #[test]
+    fn test_orientations() {
+        assert_eq!(1, Pattern::square(2).orientations(Topology::Square, 2).len());
+        assert_eq!(4, Pattern::l_tromino().orientations(Topology::Square, 2).len());
+        // an L of four cells is different when mirrored
+        let l = Pattern::parse("L", "X\nX\nXX").unwrap();
+        assert_eq!(8, l.orientations(Topology::Square, 2).len());
+        // in three dimensions a square can lie in three planes
+        assert_eq!(3, Pattern::square(2).orientations(Topology::Square, 3).len());
+        // a hex cell has six neighbours, so there are six ways to pair them
+        assert_eq!(6, Pattern::l_tromino().orientations(Topology::Hex, 2).len());
+    }
+}
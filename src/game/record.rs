@@ -0,0 +1,430 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::algebraic;
+use super::grid::Grid;
+use super::grid_observer::check_winner;
+use super::pattern::Pattern;
+use super::notation::{parse_notation, to_notation, parse_variant, variant_name};
+use super::{PlayerId, GameState, Move, Coord, X, O};
+
+// A game written down like PGN for chess: tags about the game followed by its turns.
+//
+//     [Player1 "Human"]
+//     [Player2 "Ki"]
+//     [Date "2026.10.18"]
+//     [Variant "standard"]
+//     [Size "3x3"]
+//     [ToWin "3"]
+//     [Start "3x3 3 standard 3/3/3 1"]
+//     [Result "1-0"]
+//
+//     1. b2 {the centre} 2. a1 [-12] 3. b1 4. c1 5. b3 1-0
+//
+// Every turn is numbered and lists its stones in algebraic notation, like 'c3'. Records
+// that write them as the coordinates from 0 separated by ',', like '2,2', are read as well. In the
+// variants where the symbol is chosen it follows as '=X' or '=O'. A comment in braces and an
+// evaluation in brackets, from the point of view of the side that made the turn, may come
+// after the stones. The Start tag is the position the game started from in the one-line
+// notation, without it the game starts on an empty board of the Size, ToWin and Variant tags.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Record {
+    pub tags: Vec<(String, String)>,
+    pub turns: Vec<RecordedTurn>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecordedTurn {
+    pub stones: Vec<Move>,
+    pub comment: Option<String>,
+    pub evaluation: Option<i32>,
+}
+
+impl RecordedTurn {
+    pub fn new(stones: Vec<Move>) -> RecordedTurn {
+        RecordedTurn {
+            stones,
+            comment: None,
+            evaluation: None,
+        }
+    }
+}
+
+// The result as it is written in a record: '1-0' and '0-1' for a win, '1/2-1/2' for a draw,
+// the points like '3:2' in a game where every line scores and '*' for a game that goes on
+pub fn result_text(state: &GameState) -> String {
+    match *state {
+        GameState::Win(PlayerId(1)) => "1-0".to_string(),
+        GameState::Win(_) => "0-1".to_string(),
+        GameState::Draw => "1/2-1/2".to_string(),
+        GameState::Scores(first, second) => format!("{}:{}", first, second),
+        GameState::Mid => "*".to_string(),
+    }
+}
+
+impl Record {
+    // An empty record of a game starting from `start`, with the tags of its rules
+    pub fn new(start: &Grid) -> Record {
+        let dimensions: Vec<String> = start.dimensions().iter()
+            .map(|size| size.to_string())
+            .collect();
+        let mut record = Record {
+            tags: Vec::new(),
+            turns: Vec::new(),
+        };
+        // unknown until they are set, like in PGN
+        record.set_tag("Player1", "?");
+        record.set_tag("Player2", "?");
+        record.set_tag("Date", "?");
+        record.set_tag("Variant", variant_name(start.variant));
+        record.set_tag("Size", &dimensions.join("x"));
+        record.set_tag("ToWin", &start.to_win.to_string());
+        record.set_tag("Start", &to_notation(start));
+        for (number, pattern) in start.patterns.iter().enumerate() {
+            record.set_tag(&format!("Pattern{}", number + 1),
+                           &format!("{}: {}", pattern.name, pattern.shape().replace('\n', "/")));
+        }
+        record.set_tag("Result", "*");
+        record
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Changes a tag, or adds it at the end
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // The position the game started from, with the patterns of the Pattern tags
+    pub fn start(&self) -> Result<Grid, String> {
+        let mut grid = self.board()?;
+        for number in 1 .. {
+            let pattern = match self.tag(&format!("Pattern{}", number)) {
+                Some(pattern) => pattern,
+                None => break,
+            };
+            let mut parts = pattern.rsplitn(2, ": ");
+            let shape = parts.next().unwrap_or("").replace('/', "\n");
+            let name = parts.next()
+                .ok_or_else(|| format!("'{}' is not a pattern like 'name: XX/X_'.", pattern))?;
+            grid.patterns.push(Pattern::parse(name, &shape)?);
+        }
+        Ok(grid)
+    }
+
+    fn board(&self) -> Result<Grid, String> {
+        if let Some(start) = self.tag("Start") {
+            return parse_notation(start);
+        }
+        let size = self.tag("Size").ok_or("The record has neither a Start nor a Size tag.")?;
+        let dimensions: Vec<usize> = size.split('x')
+            .map(|size| size.parse().ok().filter(|&size| size > 0))
+            .collect::<Option<_>>()
+            .filter(|dimensions: &Vec<usize>| dimensions.len() >= 2)
+            .ok_or_else(|| format!("'{}' is not a board size like 3x3.", size))?;
+        let to_win = self.tag("ToWin").unwrap_or("3").parse()
+            .map_err(|_| "The ToWin tag is not a number.".to_string())?;
+        let mut grid = Grid::with_dimensions(dimensions, to_win);
+        grid.variant = parse_variant(self.tag("Variant").unwrap_or("standard"))?;
+        Ok(grid)
+    }
+
+    // Every position of the game, from the start to the end. The last turn may be short of
+    // stones, if one before its end won the game.
+    pub fn replay(&self) -> Result<Vec<Grid>, String> {
+        let mut grid = self.start()?;
+        let mut positions = vec![grid.clone()];
+        for (number, turn) in self.turns.iter().enumerate() {
+            let turns_played = grid.turns_played();
+            for stone in &turn.stones {
+                if stone.player != grid.to_move() || !grid.is_on_board(&stone.coord)
+                        || !grid.make_move(stone.clone()) {
+                    return Err(format!("Turn {} can't be played.", number + 1));
+                }
+            }
+            let ended = number + 1 == self.turns.len() && check_winner(&grid) != GameState::Mid;
+            if grid.turns_played() != turns_played + 1 && !ended {
+                return Err(format!("Turn {} has the wrong number of stones.", number + 1));
+            }
+            positions.push(grid.clone());
+        }
+        Ok(positions)
+    }
+
+    // Writes the record to a file, and creates the directory it is in if needed
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        match Path::new(path).parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => fs::create_dir_all(directory)?,
+            _ => {},
+        }
+        fs::write(path, self.write())
+    }
+
+    pub fn write(&self) -> String {
+        let mut output = String::new();
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            output += &format!("[{} \"{}\"]\n", name, value);
+        }
+        output += "\n";
+        let mut line = String::new();
+        let tokens = self.turns.iter().enumerate().flat_map(|(number, turn)| {
+            let mut tokens = vec![format!("{}.", number + 1)];
+            tokens.extend(turn.stones.iter().map(write_stone));
+            if let Some(ref comment) = turn.comment {
+                tokens.push(format!("{{{}}}", comment.replace('}', ")")));
+            }
+            if let Some(evaluation) = turn.evaluation {
+                tokens.push(format!("[{}]", evaluation));
+            }
+            tokens
+        });
+        for token in tokens.chain(Some(self.tag("Result").unwrap_or("*").to_string())) {
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                output += &line;
+                output += "\n";
+                line.clear();
+            }
+            if !line.is_empty() {
+                line += " ";
+            }
+            line += &token;
+        }
+        output += &line;
+        output += "\n";
+        output
+    }
+
+    pub fn parse(text: &str) -> Result<Record, String> {
+        let mut record = Record {
+            tags: Vec::new(),
+            turns: Vec::new(),
+        };
+        let mut moves = String::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with('[') && record.turns.is_empty() && moves.is_empty() {
+                let (name, value) = parse_tag(line)?;
+                record.set_tag(&name, &value);
+            } else {
+                moves += line;
+                moves += " ";
+            }
+        }
+        let start = record.start()?;
+        let mut side = start.to_move();
+        let mut chars = moves.chars().peekable();
+        while let Some(&next) = chars.peek() {
+            if next.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            if next == '{' || next == '[' {
+                chars.next();
+                let end = if next == '{' { '}' } else { ']' };
+                let content: String = chars.by_ref().take_while(|&c| c != end).collect();
+                let turn = record.turns.last_mut().ok_or("A comment comes before the first turn.")?;
+                if next == '{' {
+                    turn.comment = Some(content);
+                } else {
+                    turn.evaluation = Some(content.trim().parse()
+                        .map_err(|_| format!("'{}' is not an evaluation.", content))?);
+                }
+                continue;
+            }
+            let token: String = chars.by_ref().take_while(|c| !c.is_whitespace()).collect();
+            if token.ends_with('.') && token[.. token.len() - 1].parse::<usize>().is_ok() {
+                if !record.turns.is_empty() {
+                    side = side.opponent();
+                }
+                record.turns.push(RecordedTurn::new(Vec::new()));
+            } else if token == "*" || token.contains('-')
+                || (token.contains(':') && !token.chars().any(|c| c.is_ascii_alphabetic())) {
+                // the result, which is also in the tags
+            } else {
+                let turn = record.turns.last_mut().ok_or("A stone comes before the first turn.")?;
+                turn.stones.push(parse_stone(&token, side)?);
+            }
+        }
+        Ok(record)
+    }
+}
+
+pub(crate) fn write_stone(stone: &Move) -> String {
+    let cell = algebraic::format(&stone.coord);
+    if stone.symbol == stone.player {
+        cell
+    } else {
+        format!("{}={}", cell, if stone.symbol == X { "X" } else { "O" })
+    }
+}
+
+pub(crate) fn parse_stone(token: &str, side: PlayerId) -> Result<Move, String> {
+    let mut parts = token.splitn(2, '=');
+    let cell = parts.next().unwrap_or("");
+    let coord = if cell.contains(',') {
+        let values: Option<Vec<usize>> = cell.split(',').map(|value| value.parse().ok()).collect();
+        match values {
+            Some(ref values) if values.len() >= 2 => Coord(values.clone()),
+            _ => return Err(format!("'{}' is not a stone.", token)),
+        }
+    } else {
+        algebraic::parse(cell).map_err(|_| format!("'{}' is not a stone.", token))?
+    };
+    let symbol = match parts.next() {
+        None => side,
+        Some("X") => X,
+        Some("O") => O,
+        Some(other) => return Err(format!("'{}' is not a symbol.", other)),
+    };
+    Ok(Move::at(coord, side, symbol))
+}
+
+// The current date and time in UTC as year, month, day, hours, minutes and seconds
+pub fn now() -> (i64, u64, u64, u64, u64, u64) {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    // days since 1970 to a date, counted in eras of 400 years that start on the 1st of March
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+                       - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let time = seconds % 86400;
+    (year, month as u64, day as u64, time / 3600, time / 60 % 60, time % 60)
+}
+
+// Reads '[Name "value"]'
+fn parse_tag(line: &str) -> Result<(String, String), String> {
+    let inner = line.trim_start_matches('[').trim_end_matches(']');
+    let mut parts = inner.splitn(2, ' ');
+    let name = parts.next().unwrap_or("");
+    let value = parts.next().unwrap_or("").trim();
+    if name.is_empty() || !value.starts_with('"') || !value.ends_with('"') || value.len() < 2 {
+        return Err(format!("'{}' is not a tag like [Name \"value\"].", line));
+    }
+    let mut result = String::new();
+    let mut escaped = false;
+    for c in value[1 .. value.len() - 1].chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+        } else {
+            result.push(c);
+            escaped = false;
+        }
+    }
+    Ok((name.to_string(), result))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid_observer::check_winner;
+    use ::game::Variant;
+
+    fn sample() -> Record {
+        let mut record = Record::new(&Grid::new(3, 3, 3));
+        record.set_tag("Player1", "Ann \"the best\"");
+        let stones = [(1, 1), (0, 0), (0, 1), (0, 2), (2, 1)];
+        for (number, &(row, col)) in stones.iter().enumerate() {
+            let side = PlayerId(number as u32 % 2 + 1);
+            record.turns.push(RecordedTurn::new(vec![Move::new(row, col, side)]));
+        }
+        record.turns[0].comment = Some("the centre".to_string());
+        record.turns[1].evaluation = Some(-12);
+        record.set_tag("Result", "1-0");
+        record
+    }
+
+    #[test]
+    fn test_write() {
+        let text = sample().write();
+        assert!(text.starts_with("[Player1 \"Ann \\\"the best\\\"\"]\n[Player2 \"?\"]\n"));
+        assert!(text.contains("[Variant \"standard\"]\n[Size \"3x3\"]\n"));
+        assert!(text.ends_with("\n1. b2 {the centre} 2. a1 [-12] 3. b1 4. c1 5. b3 1-0\n"));
+    }
+
+    #[test]
+    fn test_patterns_are_kept() {
+        let mut grid = Grid::new(4, 4, 4);
+        grid.patterns.push(Pattern::l_tromino());
+        let record = Record::parse(&Record::new(&grid).write()).unwrap();
+        assert_eq!(Some("L-tromino: X_/XX"), record.tag("Pattern1"));
+        assert_eq!(vec![Pattern::l_tromino()], record.start().unwrap().patterns);
+    }
+
+    #[test]
+    fn test_parse_numeric_stones() {
+        let text = sample().write().replace("b2", "1,1").replace("a1", "0,0");
+        assert_eq!(sample(), Record::parse(&text).unwrap());
+        let layers = Record::parse("[Size \"3x3x3\"]\n1. 2:c3 2. 0,1,1 *").unwrap();
+        assert_eq!(Coord(vec![1, 2, 2]), layers.turns[0].stones[0].coord);
+        assert_eq!(Coord(vec![0, 1, 1]), layers.turns[1].stones[0].coord);
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let record = sample();
+        assert_eq!(record, Record::parse(&record.write()).unwrap());
+    }
+
+    #[test]
+    fn test_replay() {
+        let positions = sample().replay().unwrap();
+        assert_eq!(6, positions.len());
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&positions[5]));
+        assert_eq!(GameState::Mid, check_winner(&positions[4]));
+    }
+
+    #[test]
+    fn test_parse_without_start() {
+        let record = Record::parse("[Size \"4x4\"]\n[ToWin \"3\"]\n[Variant \"wild\"]\n\n\
+                                    1. 0,0=O 2. 1,1=X *").unwrap();
+        let positions = record.replay().unwrap();
+        assert_eq!(Variant::Wild, positions[0].variant);
+        assert_eq!(PlayerId(2), record.turns[1].stones[0].player);
+        assert_eq!(::game::CellState::Set(O), *positions[2].get_cell(0, 0));
+    }
+
+    #[test]
+    fn test_replay_rejects_a_taken_cell() {
+        let record = Record::parse("[Size \"3x3\"]\n\n1. 1,1 2. 1,1").unwrap();
+        assert!(record.replay().is_err());
+    }
+
+    #[test]
+    fn test_replay_a_last_turn_that_won_early() {
+        let text = "[Start \"5x5 3 standard 5/5/5/5/5 1 stones=1/2\"]\n\n\
+                    1. a1 2. a3 b3 3. b1 e5 4. a4 b4 5. c1 1-0";
+        let positions = Record::parse(text).unwrap().replay().unwrap();
+        assert_eq!(GameState::Win(PlayerId(1)), check_winner(&positions[5]));
+        let short = Record::parse(&text.replace("3. b1 e5", "3. b1")).unwrap();
+        assert_eq!(Some("Turn 3 has the wrong number of stones.".to_string()), short.replay().err());
+    }
+
+    #[test]
+    fn test_replay_rejects_a_stone_off_the_board() {
+        let record = Record::parse("[Size \"3x3\"]\n\n1. z9").unwrap();
+        assert_eq!(Some("Turn 1 can't be played.".to_string()), record.replay().err());
+        let record = Record::parse("[Size \"3x3\"]\n\n1. b2 2. 1,1,1").unwrap();
+        assert_eq!(Some("Turn 2 can't be played.".to_string()), record.replay().err());
+    }
+}
@@ -0,0 +1,138 @@
+// How the cells of a board are connected to each other. The cells are always stored in a
+// rectangle (or a box with more dimensions), the topology decides which of them are
+// neighbours and so along which directions lines run.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Topology {
+    // Every cell touches the cells next to it along each dimension and diagonally
+    Square,
+    // A board of hexagons in axial coordinates: the row and the column are the two axes.
+    // Every cell has six neighbours and lines run in three directions. Hex boards have
+    // exactly two dimensions.
+    Hex,
+}
+
+// The axes of a hex board in (row, column): along the row, along the column and the
+// diagonal between them
+const HEX_DIRECTIONS: [[isize; 2]; 3] = [[0, 1], [1, 0], [1, -1]];
+
+impl Topology {
+    // The directions a line can be made in. Every direction only appears once, not also
+    // its opposite: on a 2D square board these are horizontal, vertical and both diagonals,
+    // in 3D there are 13 of them.
+    pub fn directions(&self, dimensions: usize) -> Vec<Vec<isize>> {
+        match *self {
+            Topology::Square => steps(dimensions, 1).into_iter()
+                .filter(|direction| direction.iter().find(|&&step| step != 0) == Some(&1))
+                .collect(),
+            Topology::Hex => HEX_DIRECTIONS.iter().map(|direction| direction.to_vec()).collect(),
+        }
+    }
+
+    // Every step to a cell at most `distance` cells away
+    pub fn neighbourhood(&self, dimensions: usize, distance: isize) -> Vec<Vec<isize>> {
+        let mut offsets = steps(dimensions, distance);
+        offsets.retain(|offset| offset.iter().any(|&step| step != 0));
+        if *self == Topology::Hex {
+            // the distance on a hex board also counts the third, implicit axis
+            offsets.retain(|offset| (offset[0] + offset[1]).abs() <= distance);
+        }
+        offsets
+    }
+
+    // The cells turned and mirrored in every way that keeps neighbours next to each other.
+    // On a square board that is any order of the axes with any of them reversed, on a hex
+    // board the six turns around a cell, each also mirrored.
+    pub fn orientations(&self, cells: &[Vec<isize>]) -> Vec<Vec<Vec<isize>>> {
+        match *self {
+            Topology::Square => {
+                let dimensions = cells.first().map_or(0, |cell| cell.len());
+                let mut result = Vec::new();
+                for order in permutations(dimensions) {
+                    for flips in 0 .. 1 << dimensions {
+                        result.push(cells.iter()
+                            .map(|cell| order.iter().enumerate()
+                                .map(|(axis, &from)| {
+                                    if flips & (1 << axis) != 0 { -cell[from] } else { cell[from] }
+                                })
+                                .collect())
+                            .collect());
+                    }
+                }
+                result
+            },
+            Topology::Hex => {
+                let mut result = Vec::new();
+                let mut turned = cells.to_vec();
+                for _ in 0 .. 6 {
+                    let mirrored = turned.iter().map(|cell| vec![cell[1], cell[0]]).collect();
+                    result.push(mirrored);
+                    // a sixth of a turn: the row becomes the sum of both, the column minus
+                    // the old row
+                    turned = turned.iter().map(|cell| vec![cell[0] + cell[1], -cell[0]]).collect();
+                    result.push(turned.clone());
+                }
+                result
+            },
+        }
+    }
+}
+
+// Every order of the numbers 0 .. count
+fn permutations(count: usize) -> Vec<Vec<usize>> {
+    if count == 0 {
+        return vec![Vec::new()];
+    }
+    let mut result = Vec::new();
+    for shorter in permutations(count - 1) {
+        for position in 0 ..= shorter.len() {
+            let mut order = shorter.clone();
+            order.insert(position, count - 1);
+            result.push(order);
+        }
+    }
+    result
+}
+
+// Every combination of steps between -distance and distance along each dimension
+fn steps(dimensions: usize, distance: isize) -> Vec<Vec<isize>> {
+    let mut steps: Vec<Vec<isize>> = vec![Vec::new()];
+    for _ in 0 .. dimensions {
+        steps = steps.into_iter()
+            .flat_map(|offset| (-distance ..= distance).map(move |step| {
+                let mut offset = offset.clone();
+                offset.push(step);
+                offset
+            }))
+            .collect();
+    }
+    steps
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_square_directions() {
+        assert_eq!(vec![vec![0, 1], vec![1, -1], vec![1, 0], vec![1, 1]],
+                   Topology::Square.directions(2));
+        assert_eq!(13, Topology::Square.directions(3).len());
+    }
+
+    #[test]
+    fn test_hex_directions() {
+        assert_eq!(3, Topology::Hex.directions(2).len());
+    }
+
+    #[test]
+    fn test_neighbourhood() {
+        assert_eq!(8, Topology::Square.neighbourhood(2, 1).len());
+        assert_eq!(26, Topology::Square.neighbourhood(3, 1).len());
+        assert_eq!(6, Topology::Hex.neighbourhood(2, 1).len());
+        assert!(!Topology::Hex.neighbourhood(2, 1).contains(&vec![1, 1]));
+        assert_eq!(18, Topology::Hex.neighbourhood(2, 2).len());
+    }
+}
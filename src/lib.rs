@@ -0,0 +1,28 @@
+// the code of the first version of the game is kept in its own style
+#![allow(clippy::match_like_matches_macro, clippy::match_ref_pats, clippy::needless_return)]
+
+extern crate crossterm;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(any(feature = "web", all(test, feature = "serde")))]
+extern crate serde_json;
+#[cfg(feature = "web")]
+extern crate tiny_http;
+#[cfg(feature = "web")]
+extern crate tungstenite;
+#[cfg(all(test, feature = "serde"))]
+extern crate bincode;
+
+pub mod engine;
+pub mod game;
+pub mod player;
+pub mod play;
+pub mod rating;
+pub mod selfplay;
+pub mod server;
+pub mod spectator;
+pub mod tournament;
+pub mod tui;
+#[cfg(feature = "web")]
+pub mod web;
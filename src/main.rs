@@ -1,44 +1,838 @@
-use ::game::grid::Grid;
-use ::game::{PlayerId,GameState::*};
-use ::game::grid_observer::check_winner;
-use ::player::terminal::TerminalPlayer;
-use ::player::ki::KiPlayer;
-use ::player::Player;
+extern crate tic_tac_toe;
 
-mod game;
-mod player;
+use std::env;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tic_tac_toe::engine;
+use tic_tac_toe::server;
+use tic_tac_toe::game::algebraic;
+use tic_tac_toe::game::grid::Grid;
+use tic_tac_toe::game::{PlayerId, Variant, Move, GameState::*};
+use tic_tac_toe::game::grid_observer::{check_winner, check_winner_lines, line_tally, Shape,
+                                       WinningLine};
+use tic_tac_toe::game::pattern::Pattern;
+use tic_tac_toe::player::Player;
+use tic_tac_toe::player::terminal::TerminalPlayer;
+use tic_tac_toe::player::tui::TuiPlayer;
+use tic_tac_toe::player::ki::KiPlayer;
+use tic_tac_toe::player::external::{ExternalPlayer, DEFAULT_GRACE};
+use tic_tac_toe::player::network::{Connection, NetworkPlayer};
+use tic_tac_toe::game::opening::Opening;
+use tic_tac_toe::game::notation::{parse_notation, to_notation};
+use tic_tac_toe::game::record::{now, Record};
+use tic_tac_toe::play::Game;
+use tic_tac_toe::spectator::{Broadcast, WatchEvent, Watcher};
+use tic_tac_toe::tournament::{Entrant, Pairings, Results, Tournament};
+use tic_tac_toe::rating::{self, Ratings, System};
+use tic_tac_toe::selfplay::SelfPlay;
+use tic_tac_toe::tui::Screen;
 
 const ROWS: usize = 3;
 const COLUMNS: usize = 3;
 const TO_WIN: u32 = 3;
-const PLAYER_COUNT: usize = 2;
 
-fn main() {
-    let term_player_1 =  TerminalPlayer::new(1);
-    let term_player_2 =  KiPlayer::new(2);
-    let players: Vec<&Player> = vec![&term_player_1, &term_player_2];
-    let mut cur_id: usize = 0;
-    let mut grid = Grid::new(ROWS, COLUMNS, TO_WIN);
+// Order and Chaos is played on a 6x6 board with five in a row
+const ORDER_CHAOS_SIZE: usize = 6;
+const ORDER_CHAOS_TO_WIN: u32 = 5;
+
+// Connect6: one stone in the first turn, then two per turn, six to win on 19x19
+const CONNECT6_SIZE: usize = 19;
+const CONNECT6_TO_WIN: u32 = 6;
+const CONNECT6_STONES_PER_TURN: u32 = 2;
+
+// Qubic: four in a row on 4x4x4
+const QUBIC_SIZE: usize = 4;
+const QUBIC_TO_WIN: u32 = 4;
+
+// Hex: four in a row on a hexagon with five cells on each side
+const HEX_SIDE: usize = 5;
+const HEX_TO_WIN: u32 = 4;
+
+// How long an engine searches each turn, if no move time is given
+const DEFAULT_MOVE_TIME_MS: u64 = 1000;
+
+fn new_grid(variant: Option<&str>) -> Grid {
+    match variant {
+        Some("wild") => Grid::with_variant(ROWS, COLUMNS, TO_WIN, Variant::Wild),
+        Some("order-chaos") => Grid::with_variant(ORDER_CHAOS_SIZE, ORDER_CHAOS_SIZE,
+                                                  ORDER_CHAOS_TO_WIN, Variant::OrderAndChaos),
+        Some("connect6") => {
+            let mut grid = Grid::new(CONNECT6_SIZE, CONNECT6_SIZE, CONNECT6_TO_WIN);
+            grid.stones_per_turn = CONNECT6_STONES_PER_TURN;
+            grid
+        },
+        Some("qubic") => Grid::with_dimensions(vec![QUBIC_SIZE; 3], QUBIC_TO_WIN),
+        Some("hex") => Grid::hexagon(HEX_SIDE, HEX_TO_WIN),
+        Some("standard") | None => Grid::new(ROWS, COLUMNS, TO_WIN),
+        Some(other) => {
+            println!("Unknown variant '{}', choose standard, wild, order-chaos, connect6, \
+                      qubic or hex.", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Puts the rules of the variant on another board
+fn with_rules(mut grid: Grid, rules: &Grid) -> Grid {
+    grid.variant = rules.variant;
+    grid.first_turn_stones = rules.first_turn_stones;
+    grid.stones_per_turn = rules.stones_per_turn;
+    grid
+}
+
+// Reads a size like '4x4x4'
+fn parse_size(size: &str) -> Grid {
+    let dimensions: Result<Vec<usize>, _> = size.split('x').map(|value| value.parse()).collect();
+    match dimensions {
+        Ok(ref dimensions) if dimensions.len() >= 2 && !dimensions.contains(&0) =>
+            Grid::with_dimensions(dimensions.clone(), TO_WIN),
+        _ => {
+            println!("Invalid size '{}', expected at least two dimensions like '3x3'.", size);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Puts the rules of the variant on a board with the shape read from the file. The stones
+// drawn on it are returned to start the game with.
+fn load_board(path: &str, rules: Grid) -> (Grid, Vec<Move>) {
+    let shape = fs::read_to_string(path).unwrap_or_else(|error| {
+        println!("Can't read the board '{}': {}", path, error);
+        std::process::exit(1);
+    });
+    let (grid, stones) = Grid::position_from_shape(&shape, rules.to_win).unwrap_or_else(|error| {
+        println!("The board '{}' is invalid: {}", path, error);
+        std::process::exit(1);
+    });
+    (with_rules(grid, &rules), stones)
+}
+
+// The number given to an option, the program ends if it isn't one above 0
+fn count<T: FromStr + Default + PartialOrd>(option: &str, value: Option<String>) -> T {
+    let count = value.and_then(|value| value.parse().ok()).filter(|count| *count > T::default());
+    count.unwrap_or_else(|| {
+        println!("{} needs a number above 0.", option);
+        std::process::exit(1);
+    })
+}
+
+// The number given to an option, the program ends if it isn't one
+fn number<T: FromStr>(option: &str, value: Option<String>) -> T {
+    value.and_then(|value| value.parse().ok()).unwrap_or_else(|| {
+        println!("{} needs a number.", option);
+        std::process::exit(1);
+    })
+}
+
+// The value given to an option, the program ends if there is none
+fn value(option: &str, value: Option<String>, what: &str) -> String {
+    value.unwrap_or_else(|| {
+        println!("{} needs {}.", option, what);
+        std::process::exit(1);
+    })
+}
+
+// Starts from the stones. Without a side to move, whichever side fits the stones moves.
+fn set_position(grid: &mut Grid, stones: &[Move], to_move: Option<u32>, handicap: u32) {
+    let sides = match to_move {
+        Some(side) => vec![PlayerId(side)],
+        None => vec![PlayerId(1), PlayerId(2)],
+    };
+    let mut result = Ok(());
+    for side in sides {
+        result = grid.set_position(stones, side, handicap);
+        if result.is_ok() {
+            return;
+        }
+    }
+    if let Err(error) = result {
+        println!("Can't start from this position: {}", error);
+        std::process::exit(1);
+    }
+}
+
+// A pattern by name, or else read from a file
+fn load_pattern(name: &str, grid: &Grid) -> Pattern {
+    match name {
+        "square" => Pattern::square(2),
+        "l-tromino" => Pattern::l_tromino(),
+        "corners" => Pattern::corners(grid.row_count, grid.column_count),
+        path => {
+            let shape = fs::read_to_string(path).unwrap_or_else(|error| {
+                println!("Can't read the pattern '{}': {}", path, error);
+                std::process::exit(1);
+            });
+            Pattern::parse(path, &shape).unwrap_or_else(|error| {
+                println!("The pattern '{}' is invalid: {}", path, error);
+                std::process::exit(1);
+            })
+        }
+    }
+}
+
+// Says what was completed and where, like 'a line of X at 0 0, 1 1, 2 2'
+fn describe(line: &WinningLine, grid: &Grid) -> String {
+    let cells: Vec<String> = line.cells.iter().map(algebraic::format).collect();
+    let shape = match line.shape {
+        Shape::Line(_) => "a line".to_string(),
+        Shape::Pattern(ref name) => format!("the {}", name),
+    };
+    format!("{} of {} at {}", shape, grid.symbol_name(line.symbol), cells.join(", "))
+}
 
+fn parse_opening(name: Option<String>) -> Opening {
+    Opening::parse(name.as_deref().unwrap_or("")).unwrap_or_else(|error| {
+        println!("Invalid opening: {}", error);
+        std::process::exit(1);
+    })
+}
+
+// Writes the record of the game to the given file, or to one named after the time in 'games'
+fn save_record(game: &Game, names: [&str; 2], path: Option<String>) {
+    let (year, month, day, hours, minutes, seconds) = now();
+    let mut record = game.record(names);
+    record.set_tag("Date", &format!("{}.{:02}.{:02}", year, month, day));
+    let path = path.unwrap_or_else(|| {
+        format!("games/{}-{:02}-{:02}-{:02}{:02}{:02}.txt",
+                year, month, day, hours, minutes, seconds)
+    });
+    match record.save(&path) {
+        Ok(()) => println!("The game is saved in {}.", path),
+        Err(error) => println!("Can't save the game in {}: {}", path, error),
+    }
+}
+
+// Shows a recorded game turn by turn, the next one comes with Enter
+fn replay(path: &str) {
+    let text = fs::read_to_string(path).unwrap_or_else(|error| {
+        println!("Can't read the game '{}': {}", path, error);
+        std::process::exit(1);
+    });
+    let (record, positions) = match Record::parse(&text).and_then(|record| {
+        let positions = record.replay()?;
+        Ok((record, positions))
+    }) {
+        Ok(replayed) => replayed,
+        Err(error) => {
+            println!("The game '{}' is invalid: {}", path, error);
+            std::process::exit(1);
+        }
+    };
+    for (name, value) in &record.tags {
+        println!("{}: {}", name, value);
+    }
+    println!();
+    positions[0].pretty_print();
+    let mut waiting = true;
+    for (number, (turn, position)) in record.turns.iter().zip(&positions[1 ..]).enumerate() {
+        if waiting {
+            let mut input = String::new();
+            waiting = io::stdin().read_line(&mut input).is_ok_and(|read| read > 0);
+        }
+        let stones: Vec<String> = turn.stones.iter()
+            .map(|stone| {
+                let symbol = position.symbol_name(stone.symbol);
+                format!("{} at {}", symbol, algebraic::format(&stone.coord))
+            })
+            .collect();
+        println!("\nTurn {}: {}", number + 1, stones.join(", "));
+        if let Some(ref comment) = turn.comment {
+            println!("{}", comment);
+        }
+        if let Some(evaluation) = turn.evaluation {
+            println!("Evaluation: {}", evaluation);
+        }
+        position.pretty_print();
+    }
+    println!("Result: {}", record.tag("Result").unwrap_or("*"));
+}
+
+// The player of a kind, 'Human', 'Ki' or the command that starts an engine
+fn new_player<'a>(kind: &str, id: u32, screen: &'a Option<Screen>, move_time: Duration)
+                  -> Result<Box<dyn Player + 'a>, String> {
+    match (kind, screen) {
+        ("Ki", _) | ("ki", _) => Ok(Box::new(KiPlayer::new(id))),
+        ("Human", Some(screen)) | ("human", Some(screen)) => {
+            Ok(Box::new(TuiPlayer::new(id, screen)))
+        },
+        ("Human", None) | ("human", None) => Ok(Box::new(TerminalPlayer::new(id))),
+        ("Engine", _) => {
+            Err(format!("Player {} was an engine, give its command with --player{} \
+                         engine:COMMAND.", id, id))
+        },
+        (kind, _) => match kind.strip_prefix("engine:") {
+            Some(command) => {
+                Ok(Box::new(ExternalPlayer::start(id, command, move_time, DEFAULT_GRACE)?))
+            },
+            None => Err(format!("'{}' is no player, use human, ki or engine:COMMAND.", kind)),
+        },
+    }
+}
+
+// Plays the tournament and saves its records and crosstable in `directory`, or in a directory
+// named after the time in 'games'
+fn play_tournament(tournament: &Tournament, directory: Option<String>, ratings: &str) {
+    let (year, month, day, hours, minutes, seconds) = now();
+    let directory = directory.unwrap_or_else(|| {
+        format!("games/tournament-{}-{:02}-{:02}-{:02}{:02}{:02}", year, month, day, hours, minutes,
+                seconds)
+    });
+    let results = tournament.run(&mut |results: &Results, played| {
+        let [first, second] = played.entrants;
+        println!("Round {}: {} - {} {}", played.round, results.names[first], results.names[second],
+                 played.record.tag("Result").unwrap_or("*"));
+    });
+    let results = results.unwrap_or_else(|error| {
+        println!("The tournament can't go on: {}", error);
+        std::process::exit(1);
+    });
+    for (number, played) in results.games.iter().enumerate() {
+        let mut record = played.record.clone();
+        record.set_tag("Date", &format!("{}.{:02}.{:02}", year, month, day));
+        let path = format!("{}/game-{:03}.txt", directory, number + 1);
+        if let Err(error) = record.save(&path) {
+            println!("Can't save the game in {}: {}", path, error);
+        }
+    }
+    let summary = format!("{}\n\n{}\n", results.crosstable(), results.pairings());
+    println!("\n{}", summary);
+    match fs::write(format!("{}/crosstable.txt", directory), &summary) {
+        Ok(()) => println!("The games and the crosstable are saved in {}.", directory),
+        Err(error) => println!("Can't save the crosstable in {}: {}", directory, error),
+    }
+    // the entrants are rated by their kind, the numbers that tell the same kinds apart only
+    // matter in this tournament
+    let records: Vec<Record> = results.games.iter()
+        .map(|played| {
+            let mut record = played.record.clone();
+            record.set_tag("Player1", &tournament.entrants[played.entrants[0]].kind);
+            record.set_tag("Player2", &tournament.entrants[played.entrants[1]].kind);
+            record
+        })
+        .collect();
+    rate(&records, ratings);
+}
+
+// Adds the finished games to the ratings in `path` and shows the new ratings of their players
+fn rate(records: &[Record], path: &str) {
+    let mut ratings = match Ratings::load(path) {
+        Ok(ratings) => ratings,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        },
+    };
+    let mut rated: Vec<(String, String)> = Vec::new();
+    for record in records {
+        let names = [record.tag("Player1").unwrap_or("?"), record.tag("Player2").unwrap_or("?")];
+        match ratings.add_record(record) {
+            Ok(ref pool) if names[0] != names[1] => {
+                for name in &names {
+                    let player = (pool.clone(), name.to_string());
+                    if !rated.contains(&player) {
+                        rated.push(player);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    if rated.is_empty() {
+        return;
+    }
+    if let Err(error) = ratings.save(path) {
+        println!("Can't save the ratings in {}: {}", path, error);
+        return;
+    }
+    for (pool, name) in rated {
+        let rating = ratings.get(&pool, &name);
+        println!("{} is rated {:.0} Elo and {:.0} ± {:.0} Glicko-2 in {}.", name, rating.elo,
+                 rating.glicko, 2.0 * rating.deviation, pool);
+    }
+}
+
+// Plays the games of `self_play` and writes their positions to the file
+fn generate(self_play: &SelfPlay, path: &str) {
+    let file = fs::File::create(path).unwrap_or_else(|error| {
+        println!("Can't write the data to {}: {}", path, error);
+        std::process::exit(1);
+    });
+    println!("Playing {} games in {} threads with the seed {}.", self_play.games, self_play.threads,
+             self_play.seed);
+    let mut output = io::BufWriter::new(file);
+    let step = (self_play.games / 10).max(1);
+    let positions = self_play.run(&mut output, &mut |written| {
+        if written % step == 0 || written == self_play.games {
+            println!("{} of {} games played.", written, self_play.games);
+        }
+    });
+    match positions {
+        Ok(positions) => println!("{} positions are written to {}.", positions, path),
+        Err(error) => {
+            println!("The games can't go on: {}", error);
+            std::process::exit(1);
+        },
+    }
+}
+
+// Follows a game on a server or a broadcast until it ends
+fn spectate(address: &str, game: &str) {
+    let mut watcher = Watcher::connect(address, game).unwrap_or_else(|error| {
+        println!("Can't watch the game '{}' on {}: {}", game, address, error);
+        std::process::exit(1);
+    });
+    println!("Watching the game '{}'.", game);
     loop {
-        match check_winner(&grid) {
-            Win(PlayerId(id)) => {
-                println!("Congratulations, Player {}. You Win!", id);
+        match watcher.next_event() {
+            Ok(Some(WatchEvent::Start(grid))) => grid.pretty_print(),
+            Ok(Some(WatchEvent::Turn(side, stones, grid))) => {
+                println!("Player {} plays {}.", side.0, stones.join(" and "));
+                grid.pretty_print();
+            },
+            Ok(Some(WatchEvent::Result(result))) => {
+                println!("The game ends {}.", result);
                 return;
             },
-            Mid => {
-                players[cur_id].make_turn(&mut grid);
-                if cur_id >= (PLAYER_COUNT - 1) {
-                    cur_id = 0;
-                } else {
-                    cur_id += 1;
+            Ok(None) => {
+                println!("The game can't be watched any more.");
+                return;
+            },
+            Err(error) => {
+                println!("{}", error);
+                std::process::exit(1);
+            },
+        }
+    }
+}
+
+fn main() {
+    let mut variant = None;
+    let mut board = None;
+    let mut size = None;
+    let mut to_win = None;
+    let mut wrap = false;
+    let mut tui = false;
+    let mut scoring = false;
+    let mut opening = Opening::Free;
+    let mut patterns = Vec::new();
+    let mut to_move = None;
+    let mut handicap = 0;
+    let mut position = None;
+    let mut record = None;
+    let mut resume = None;
+    let mut chosen = [None, None];
+    let mut move_time = Duration::from_millis(DEFAULT_MOVE_TIME_MS);
+    let mut connect = None;
+    let mut game_name = "game".to_string();
+    let mut token = None;
+    let mut serve = None;
+    let mut watch = None;
+    let mut broadcast = None;
+    let mut delay = Duration::from_secs(0);
+    let mut tournament = None;
+    let mut entrants = Vec::new();
+    let mut games = None;
+    let mut rounds = None;
+    let mut names = [None, None];
+    let mut ratings = rating::DEFAULT_PATH.to_string();
+    let mut leaderboard = false;
+    let mut system = System::Elo;
+    let mut self_play = None;
+    let mut threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut seed = None;
+    let mut random_turns = 2;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--wrap" => wrap = true,
+            "--tui" => tui = true,
+            "--score" => scoring = true,
+            "--opening" => opening = parse_opening(args.next()),
+            "--board" => {
+                board = Some(args.next().unwrap_or_else(|| {
+                    println!("--board needs the path of a board.");
+                    std::process::exit(1);
+                }));
+            },
+            "--position" => position = Some(value("--position", args.next(), "a position")),
+            "--record" => record = Some(value("--record", args.next(), "the path of a record")),
+            "--resume" => resume = Some(value("--resume", args.next(), "the path of a saved game")),
+            "--player1" => chosen[0] = args.next(),
+            "--player2" => chosen[1] = args.next(),
+            "--move-time" => move_time = Duration::from_millis(number("--move-time", args.next())),
+            "--engine" => {
+                if let Err(error) = engine::run(io::BufReader::new(io::stdin()), io::stdout()) {
+                    eprintln!("The engine stopped: {}", error);
+                    std::process::exit(1);
                 }
+                return;
+            },
+            "--serve" => serve = Some(args.next().unwrap_or_else(|| "0.0.0.0:7878".to_string())),
+            "--watch" => watch = args.next(),
+            "--broadcast" => broadcast = args.next(),
+            "--delay" => {
+                delay = args.next().and_then(|value| value.parse().ok())
+                    .filter(|seconds: &f64| seconds.is_finite() && *seconds >= 0.0)
+                    .map_or(delay, Duration::from_secs_f64);
             },
-            Draw => {
-                println!("Draw! You are equally good!");
+            #[cfg(feature = "web")]
+            "--http" => {
+                let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+                println!("Serving the API on http://{}.", address);
+                if let Err(error) = tic_tac_toe::web::serve(&address) {
+                    println!("{}", error);
+                    std::process::exit(1);
+                }
                 return;
+            },
+            "--tournament" => tournament = args.next(),
+            "--entrant" => entrants.extend(args.next()),
+            "--games" => games = Some(count("--games", args.next())),
+            "--rounds" => rounds = Some(count("--rounds", args.next())),
+            "--name1" => names[0] = args.next(),
+            "--name2" => names[1] = args.next(),
+            "--ratings" => ratings = args.next().unwrap_or(ratings),
+            "--leaderboard" => leaderboard = true,
+            "--rating-system" => {
+                system = System::parse(&args.next().unwrap_or_default()).unwrap_or_else(|error| {
+                    println!("{}", error);
+                    std::process::exit(1);
+                });
+            },
+            "--self-play" => self_play = args.next(),
+            "--threads" => threads = count("--threads", args.next()),
+            "--seed" => seed = Some(number("--seed", args.next())),
+            "--random-turns" => random_turns = number("--random-turns", args.next()),
+            "--connect" => connect = args.next(),
+            "--game" => game_name = args.next().unwrap_or(game_name),
+            "--rejoin" => token = args.next(),
+            "--replay" => {
+                replay(&args.next().unwrap_or_default());
+                return;
+            },
+            "--size" => size = args.next(),
+            "--to-move" => to_move = Some(number("--to-move", args.next())),
+            "--handicap" => handicap = number("--handicap", args.next()),
+            "--pattern" => patterns.extend(args.next()),
+            "--to-win" => to_win = Some(count("--to-win", args.next())),
+            _ => variant = Some(arg),
+        }
+    }
+    if let Some(address) = serve {
+        let listener = TcpListener::bind(&address).unwrap_or_else(|error| {
+            println!("Can't serve games on {}: {}", address, error);
+            std::process::exit(1);
+        });
+        println!("Serving games on {}.", address);
+        if let Err(error) = server::serve(listener, delay) {
+            println!("The server stopped: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(address) = watch {
+        spectate(&address, &game_name);
+        return;
+    }
+    if leaderboard {
+        match Ratings::load(&ratings) {
+            Ok(ref loaded) if loaded.pools.is_empty() => {
+                println!("No games are rated in {} yet.", ratings)
+            },
+            Ok(loaded) => println!("{}", loaded.leaderboard(system)),
+            Err(error) => println!("{}", error),
+        }
+        return;
+    }
+    let mut grid = new_grid(variant.as_deref());
+    if let Some(size) = size {
+        let to_win = grid.to_win;
+        grid = with_rules(parse_size(&size), &grid);
+        grid.to_win = to_win;
+    }
+    let mut stones = Vec::new();
+    if let Some(path) = board {
+        let (board, board_stones) = load_board(&path, grid);
+        grid = board;
+        stones = board_stones;
+    }
+    if let Some(to_win) = to_win {
+        grid.to_win = to_win;
+    }
+    grid.wrap = wrap;
+    grid.scoring = scoring;
+    for name in patterns {
+        let pattern = load_pattern(&name, &grid);
+        grid.patterns.push(pattern);
+    }
+    if !stones.is_empty() || to_move.is_some() {
+        set_position(&mut grid, &stones, to_move, handicap);
+    }
+    if let Some(notation) = position {
+        let patterns = grid.patterns.clone();
+        grid = parse_notation(&notation).unwrap_or_else(|error| {
+            println!("The position '{}' is invalid: {}", notation, error);
+            std::process::exit(1);
+        });
+        grid.patterns = patterns;
+        if check_winner(&grid) != Mid {
+            println!("The position '{}' is invalid: the game is over already.", notation);
+            std::process::exit(1);
+        }
+    }
+    if let Some(name) = tournament {
+        let pairings = Pairings::parse(&name, rounds).unwrap_or_else(|error| {
+            println!("{}", error);
+            std::process::exit(1);
+        });
+        let tournament = Tournament {
+            entrants: Entrant::all(&entrants),
+            pairings,
+            games: games.unwrap_or(2),
+            start: grid,
+            opening,
+            move_time,
+        };
+        play_tournament(&tournament, record, &ratings);
+        return;
+    }
+    if let Some(path) = self_play {
+        // a seed from the time, which is printed so that the data can be made again
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+        });
+        let self_play = SelfPlay {
+            players: [chosen[0].clone().unwrap_or_else(|| "ki".to_string()),
+                      chosen[1].clone().unwrap_or_else(|| "ki".to_string())],
+            games: games.unwrap_or(100),
+            threads,
+            seed,
+            random_turns,
+            start: grid,
+            move_time,
+        };
+        generate(&self_play, &path);
+        return;
+    }
+    let screen = if tui {
+        Some(Screen::open().unwrap_or_else(|error| {
+            println!("Can't use the terminal as a screen: {}", error);
+            std::process::exit(1);
+        }))
+    } else {
+        None
+    };
+    // a save says which kind of player each one is
+    let saved = resume.map(|path| {
+        let text = fs::read_to_string(&path).unwrap_or_else(|error| {
+            println!("Can't read the game '{}': {}", path, error);
+            std::process::exit(1);
+        });
+        Record::parse(&text).unwrap_or_else(|error| {
+            println!("The game '{}' is invalid: {}", path, error);
+            std::process::exit(1);
+        })
+    });
+    let mut kinds: Vec<String> = saved.as_ref()
+        .and_then(|record| record.tag("Players"))
+        .unwrap_or(if connect.is_some() { "Human Human" } else { "Human Ki" })
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    kinds.resize(2, "Human".to_string());
+    for (kind, chosen) in kinds.iter_mut().zip(&chosen) {
+        if let Some(chosen) = chosen {
+            *kind = chosen.clone();
+        }
+    }
+    // the notation of the engine protocol has no patterns
+    let patterns = match saved {
+        Some(ref record) => record.start().is_ok_and(|start| !start.patterns.is_empty()),
+        None => !grid.patterns.is_empty(),
+    };
+    // a game on a server has the local player on one side and the opponent on the other
+    let connection = connect.map(|address| {
+        let connection = match token {
+            Some(ref token) => Connection::rejoin(&address, &game_name, token, screen.is_none()),
+            None => Connection::join(&address, &game_name, &grid, screen.is_none()),
+        };
+        connection.unwrap_or_else(|error| {
+            if let Some(ref screen) = screen {
+                screen.close();
             }
+            println!("Can't join the game '{}' on {}: {}", game_name, address, error);
+            std::process::exit(1);
+        })
+    });
+    if let Some(ref connection) = connection {
+        let message = format!("You play Player {} in the game '{}'. If you leave, come back with \
+                               --connect <server> --game {} --rejoin {}", connection.side().0,
+                              game_name, game_name, connection.token());
+        match screen {
+            Some(ref screen) => screen.set_status(&message),
+            None => println!("{}", message),
         }
     }
-}
+    let players: Result<Vec<Box<dyn Player>>, String> = (0 .. 2)
+        .map(|player| -> Result<Box<dyn Player>, String> {
+            let id = player as u32 + 1;
+            if let Some(ref connection) = connection {
+                if connection.side().0 != id {
+                    return Ok(Box::new(NetworkPlayer::remote(connection)));
+                }
+            }
+            let external = kinds[player].starts_with("engine:");
+            if external && patterns {
+                return Err("Patterns can't be described to an engine.".to_string());
+            }
+            let local = new_player(&kinds[player], id, &screen, move_time)?;
+            Ok(match connection {
+                Some(ref connection) => Box::new(NetworkPlayer::local(connection, local)),
+                None => local,
+            })
+        })
+        .collect();
+    let players = players.unwrap_or_else(|error| {
+        if let Some(ref screen) = screen {
+            screen.close();
+        }
+        println!("{}", error);
+        std::process::exit(1);
+    });
+    // spectators can follow the game from the start, the broadcast has to outlive it
+    let broadcast = broadcast.map(|address| {
+        let listener = TcpListener::bind(&address).unwrap_or_else(|error| {
+            if let Some(ref screen) = screen {
+                screen.close();
+            }
+            println!("Can't broadcast the game on {}: {}", address, error);
+            std::process::exit(1);
+        });
+        Broadcast::start(listener, &game_name, delay)
+    });
+    let mut game = match saved {
+        Some(record) => Game::resume(&record, [&*players[0], &*players[1]]).unwrap_or_else(|error| {
+            if let Some(ref screen) = screen {
+                screen.close();
+            }
+            println!("Can't resume the game: {}", error);
+            std::process::exit(1);
+        }),
+        None => match connection {
+            // the turns played on the server so far come as the first turns of the players
+            Some(ref connection) => {
+                Game::new(connection.start(), [&*players[0], &*players[1]], Opening::Free)
+            },
+            None => Game::new(grid, [&*players[0], &*players[1]], opening),
+        },
+    };
+    if let Some(ref broadcast) = broadcast {
+        game.watch(broadcast);
+    }
+    if let Err(error) = game.play_opening() {
+        if let Some(ref screen) = screen {
+            screen.close();
+        }
+        println!("Can't play the opening: {}", error);
+        std::process::exit(1);
+    }
 
+    let state = loop {
+        // the sides may have been swapped in the opening, so the players are looked up
+        let player_1 = game.player_of(PlayerId(1)) + 1;
+        let player_2 = game.player_of(PlayerId(2)) + 1;
+        let state = game.state();
+        if state != Mid {
+            break state;
+        }
+        let points = if game.grid.scoring {
+            let (first, second) = line_tally(&game.grid);
+            format!("Points: Player {} has {}, Player {} has {}.",
+                    player_1, first, player_2, second)
+        } else {
+            String::new()
+        };
+        let player = game.player_of(game.grid.to_move()) + 1;
+        match screen {
+            Some(ref screen) => {
+                let status = if players[player - 1].name() != "Human" {
+                    format!("{} Player {} is thinking.", points, player)
+                } else {
+                    points
+                };
+                let _ = screen.show(&game, status.trim());
+            },
+            None if !points.is_empty() => println!("{}", points),
+            None => {},
+        }
+        let turns = game.turns.len();
+        game.play_turn();
+        if screen.is_none() && game.turns.len() > turns {
+            if let Some(turn) = game.turns.last() {
+                let cells: Vec<String> = turn.stones.iter()
+                    .map(|stone| algebraic::format(&stone.coord))
+                    .collect();
+                println!("Player {} plays {}.", player, cells.join(" and "));
+            }
+        }
+    };
+
+    let player_1 = game.player_of(PlayerId(1)) + 1;
+    let player_2 = game.player_of(PlayerId(2)) + 1;
+    let result = match state {
+        Win(winner) => match game.forfeit() {
+            Some((side, reason)) => {
+                format!("Player {} forfeits: {} Congratulations, Player {}. You Win!",
+                        game.player_of(*side) + 1, reason, game.player_of(winner) + 1)
+            },
+            None => format!("Congratulations, Player {}. You Win!", game.player_of(winner) + 1),
+        },
+        Scores(first, second) if first != second => {
+            let winner = if first > second { player_1 } else { player_2 };
+            format!("Congratulations, Player {}. You Win!", winner)
+        },
+        _ => "Draw! You are equally good!".to_string(),
+    };
+    if let Some(ref screen) = screen {
+        let _ = screen.finish(&game, &result);
+        screen.close();
+    }
+    let grid = &game.grid;
+    grid.pretty_print();
+    println!("Position: {}", to_notation(grid));
+    if let (Win(_), None) = (&state, game.forfeit()) {
+        for line in &check_winner_lines(grid).1 {
+            println!("Completed {}.", describe(line, grid));
+        }
+    }
+    if let Scores(first, second) = state {
+        println!("Player {} scored {}, Player {} scored {}.", player_1, first, player_2, second);
+    }
+    println!("{}", result);
+    // a human or the opponent over the network is only rated under a name of their own, not as
+    // one of all the others
+    let unnamed = (0 .. 2).find(|&player| {
+        names[player].is_none() && ["Human", "Remote"].contains(&players[player].name())
+    });
+    let names = [names[0].as_deref().unwrap_or(players[0].name()),
+                 names[1].as_deref().unwrap_or(players[1].name())];
+    save_record(&game, names, record);
+    match unnamed {
+        Some(player) => {
+            println!("The game isn't rated, as Player {} has no name. Give one with --name{}.",
+                     player + 1, player + 1)
+        },
+        None => rate(&[game.record(names)], &ratings),
+    }
+    if let Some(broadcast) = broadcast {
+        if delay > Duration::from_secs(0) {
+            println!("Waiting for the spectators, who see the game {} s late.",
+                     delay.as_secs_f64());
+        }
+        broadcast.flush();
+    }
+}
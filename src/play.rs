@@ -0,0 +1,550 @@
+use std::time::{Duration, Instant};
+use ::game::grid::Grid;
+use ::game::{PlayerId, GameState};
+use ::game::grid_observer::check_winner;
+use ::game::opening::{self, Opening, OpeningChoice};
+use ::game::record::{now, Record, RecordedTurn, result_text};
+use ::game::{CellState, Move};
+use ::player::Player;
+use ::spectator::Spectator;
+
+// The version of the files unfinished games are saved in. Older saves can still be resumed,
+// version 0 is a plain game record.
+pub const SAVE_VERSION: u32 = 1;
+
+// A game between two players, who are numbered 0 and 1 in the order they were given.
+// Player 0 starts with side 1. Which side each of them plays can change in the opening.
+pub struct Game<'a> {
+    pub grid: Grid,
+    pub opening: Opening,
+    // The turns played so far, after the position the game started from
+    pub turns: Vec<RecordedTurn>,
+    start: Grid,
+    players: [&'a dyn Player; 2],
+    // the player of side 1 and the player of side 2
+    sides: [usize; 2],
+    // the time each player took for their turns
+    clocks: [Duration; 2],
+    opening_played: bool,
+    // the side that forfeited and why
+    forfeit: Option<(PlayerId, String)>,
+    spectators: Vec<&'a dyn Spectator>,
+}
+
+impl<'a> Game<'a> {
+    pub fn new(grid: Grid, players: [&'a dyn Player; 2], opening: Opening) -> Game<'a> {
+        Game {
+            start: grid.clone(),
+            grid,
+            opening,
+            turns: Vec::new(),
+            players,
+            sides: [0, 1],
+            clocks: [Duration::from_secs(0); 2],
+            opening_played: opening == Opening::Free,
+            forfeit: None,
+            spectators: Vec::new(),
+        }
+    }
+
+    // Continues a game from a save, or from the record of a game that wasn't finished. The
+    // players are given in the order of the Players tag.
+    pub fn resume(record: &Record, players: [&'a dyn Player; 2]) -> Result<Game<'a>, String> {
+        let version = match record.tag("Save") {
+            Some(version) => {
+                version.parse().map_err(|_| format!("'{}' is not a save version.", version))?
+            },
+            None => 0,
+        };
+        if version > SAVE_VERSION {
+            return Err(format!("The game is saved in version {}, only up to {} can be read.",
+                               version, SAVE_VERSION));
+        }
+        let start = record.start()?;
+        let grid = record.replay()?.pop().unwrap_or_else(|| start.clone());
+        let sides = match record.tag("Sides") {
+            Some("1 2") | None => [0, 1],
+            Some("2 1") => [1, 0],
+            Some(other) => {
+                return Err(format!("'{}' are not the players of the sides, like '1 2'.", other));
+            },
+        };
+        let mut clocks = [Duration::from_secs(0); 2];
+        if let Some(text) = record.tag("Clocks") {
+            let seconds: Vec<f64> = text.split_whitespace()
+                .map(|value| {
+                    value.parse().ok().filter(|&value: &f64| value >= 0.0 && value.is_finite())
+                })
+                .collect::<Option<_>>()
+                .filter(|seconds: &Vec<f64>| seconds.len() == 2)
+                .ok_or_else(|| format!("'{}' are not the times of both players.", text))?;
+            clocks = [Duration::from_secs_f64(seconds[0]), Duration::from_secs_f64(seconds[1])];
+        }
+        // the Opening tag is only there while the opening is still to be played
+        let opening = match record.tag("Opening") {
+            Some(name) => Some(Opening::parse(name)?),
+            None => None,
+        };
+        Ok(Game {
+            grid,
+            opening: opening.unwrap_or(Opening::Free),
+            turns: record.turns.clone(),
+            start,
+            players,
+            sides,
+            clocks,
+            opening_played: opening.is_none(),
+            forfeit: None,
+            spectators: Vec::new(),
+        })
+    }
+
+    // The number of the player who plays `side`
+    pub fn player_of(&self, side: PlayerId) -> usize {
+        self.sides[side.0 as usize - 1]
+    }
+
+    pub fn side_of(&self, player: usize) -> PlayerId {
+        if self.sides[0] == player { PlayerId(1) } else { PlayerId(2) }
+    }
+
+    // How long `player` took for their turns so far
+    pub fn clock(&self, player: usize) -> Duration {
+        self.clocks[player]
+    }
+
+    // The side that lost by breaking a rule and why
+    pub fn forfeit(&self) -> Option<&(PlayerId, String)> {
+        self.forfeit.as_ref()
+    }
+
+    // Lets the spectator follow the game, from its start and every turn so far
+    pub fn watch(&mut self, spectator: &'a dyn Spectator) {
+        spectator.start(&self.start);
+        let mut grid = self.start.clone();
+        for turn in &self.turns {
+            let side = grid.to_move();
+            for stone in &turn.stones {
+                grid.make_move(stone.clone());
+            }
+            spectator.turn(turn, side, &grid);
+        }
+        let state = self.state();
+        if state != GameState::Mid {
+            spectator.end(&state);
+        }
+        self.spectators.push(spectator);
+    }
+
+    // How the game stands. A forfeit ends it whatever the board looks like.
+    pub fn state(&self) -> GameState {
+        match self.forfeit {
+            Some((side, _)) => GameState::Win(PlayerId(3 - side.0)),
+            None => check_winner(&self.grid),
+        }
+    }
+
+    // Plays the game to its end, opening included
+    pub fn play(&mut self) -> Result<GameState, String> {
+        self.play_opening()?;
+        loop {
+            let state = self.state();
+            if state != GameState::Mid {
+                return Ok(state);
+            }
+            self.play_turn();
+        }
+    }
+
+    // The next turn, made by whoever plays the side to move
+    pub fn play_turn(&mut self) {
+        let player = self.player_of(self.grid.to_move());
+        self.turn_by(player);
+    }
+
+    // The record of the game so far. The players are named in the tags by the side they
+    // ended up with.
+    pub fn record(&self, names: [&str; 2]) -> Record {
+        let mut record = Record::new(&self.start);
+        record.set_tag("Player1", names[self.player_of(PlayerId(1))]);
+        record.set_tag("Player2", names[self.player_of(PlayerId(2))]);
+        record.set_tag("Result", &result_text(&self.state()));
+        if let Some((side, ref reason)) = self.forfeit {
+            record.set_tag("Termination", &format!("Side {} forfeits: {}", side.0, reason));
+        }
+        record.turns = self.turns.clone();
+        record
+    }
+
+    // Everything needed to resume the game: its record with the players, who plays which
+    // side, the clocks and the opening if it is still to come
+    pub fn saved(&self) -> Record {
+        let names = [self.players[0].name(), self.players[1].name()];
+        let mut record = self.record(names);
+        record.set_tag("Save", &SAVE_VERSION.to_string());
+        record.set_tag("Players", &names.join(" "));
+        record.set_tag("Sides", &format!("{} {}", self.sides[0] + 1, self.sides[1] + 1));
+        record.set_tag("Clocks", &format!("{:.3} {:.3}", self.clocks[0].as_secs_f64(),
+                                          self.clocks[1].as_secs_f64()));
+        if !self.opening_played {
+            record.set_tag("Opening", &self.opening.name());
+        }
+        record
+    }
+
+    // Saves the game to `path`, or to a file named after the time in 'games' if it is empty.
+    // Gives back the file it was saved in.
+    pub fn save(&self, path: &str) -> Result<String, String> {
+        let (year, month, day, hours, minutes, seconds) = now();
+        let path = if path.is_empty() {
+            format!("games/saved-{}-{:02}-{:02}-{:02}{:02}{:02}.txt",
+                    year, month, day, hours, minutes, seconds)
+        } else {
+            path.to_string()
+        };
+        let mut record = self.saved();
+        record.set_tag("Date", &format!("{}.{:02}.{:02}", year, month, day));
+        match record.save(&path) {
+            Ok(()) => Ok(path),
+            Err(error) => Err(format!("Can't save the game in {}: {}", path, error)),
+        }
+    }
+
+    // Lets `player` make the next turn, whichever side it belongs to, and records it. The
+    // player may have the game saved before they place a stone.
+    fn turn_by(&mut self, player: usize) {
+        let side = self.grid.to_move();
+        let before = self.grid.clone();
+        loop {
+            let started = Instant::now();
+            self.players[player].make_turn_as(&mut self.grid, side);
+            self.clocks[player] += started.elapsed();
+            if let Some(reason) = self.players[player].forfeit() {
+                self.grid = before;
+                self.forfeit = Some((side, reason));
+                self.tell_end();
+                return;
+            }
+            if self.grid.turns_played() != before.turns_played()
+                || check_winner(&self.grid) != GameState::Mid {
+                break;
+            }
+            match self.players[player].save_request() {
+                Some(_) if !self.opening_played => {
+                    // the choices of the protocol so far aren't part of the save
+                    let refused = "The game can be saved once the opening is over.".to_string();
+                    self.players[player].saved(&Err(refused));
+                },
+                Some(path) => {
+                    let result = self.save(&path);
+                    self.players[player].saved(&result);
+                },
+                None => break,
+            }
+        }
+        let stones: Vec<Move> = (0 .. self.grid.cell_count())
+            .map(|index| self.grid.coord_of(index))
+            .filter(|coord| *before.get_cell_at(coord) == CellState::Unset)
+            .filter_map(|coord| match *self.grid.get_cell_at(&coord) {
+                CellState::Set(symbol) => Some(Move::at(coord, side, symbol)),
+                CellState::Unset | CellState::Blocked => None,
+            })
+            .collect();
+        if stones.is_empty() {
+            // a turn without stones would leave the game where it is for good
+            self.forfeit = Some((side, "The turn has no stones.".to_string()));
+            self.tell_end();
+            return;
+        }
+        let mut turn = RecordedTurn::new(stones);
+        turn.evaluation = self.players[player].evaluation();
+        for spectator in &self.spectators {
+            spectator.turn(&turn, side, &self.grid);
+        }
+        self.turns.push(turn);
+        self.tell_end();
+    }
+
+    // Tells the spectators the result once the game is over
+    fn tell_end(&self) {
+        let state = self.state();
+        if state != GameState::Mid {
+            for spectator in &self.spectators {
+                spectator.end(&state);
+            }
+        }
+    }
+
+    // Places the stones and makes the choices of the opening protocol. After that the
+    // game goes on with regular turns.
+    pub fn play_opening(&mut self) -> Result<(), String> {
+        if self.opening_played {
+            return Ok(());
+        }
+        match self.opening {
+            Opening::Free => {},
+            Opening::Swap => {
+                self.turns_by(0, 1);
+                self.offer(1, &[OpeningChoice::Keep, OpeningChoice::Swap]);
+            },
+            Opening::Swap2 => {
+                self.turns_by(0, 3);
+                let options = [OpeningChoice::Keep, OpeningChoice::Swap, OpeningChoice::PlaceTwo];
+                if self.offer(1, &options) == OpeningChoice::PlaceTwo {
+                    self.turns_by(1, 2);
+                    self.offer(0, &[OpeningChoice::Keep, OpeningChoice::Swap]);
+                }
+            },
+            Opening::Soosyrv => {
+                self.turns_by(0, 3);
+                self.offer(1, &[OpeningChoice::Keep, OpeningChoice::Swap]);
+                let second = self.player_of(PlayerId(2));
+                self.turns_by(second, 1);
+                self.offer(1 - second, &[OpeningChoice::Keep, OpeningChoice::Swap]);
+            },
+            Opening::Balanced(number) => {
+                opening::place_balanced(&mut self.grid, number)?;
+                // the opening is where the game starts
+                self.start = self.grid.clone();
+                for spectator in &self.spectators {
+                    spectator.start(&self.start);
+                }
+                self.offer(1, &[OpeningChoice::Keep, OpeningChoice::Swap]);
+            },
+        }
+        self.opening_played = true;
+        Ok(())
+    }
+
+    // Lets `player` make the next turns, whichever side they belong to
+    fn turns_by(&mut self, player: usize, turns: u32) {
+        for _ in 0 .. turns {
+            if self.state() != GameState::Mid {
+                return;
+            }
+            self.turn_by(player);
+        }
+    }
+
+    fn offer(&mut self, player: usize, options: &[OpeningChoice]) -> OpeningChoice {
+        if self.state() != GameState::Mid {
+            return OpeningChoice::Keep;
+        }
+        let choice = self.players[player].choose_opening(&self.grid, self.side_of(player), options);
+        if let Some(reason) = self.players[player].forfeit() {
+            self.forfeit = Some((self.side_of(player), reason));
+            return OpeningChoice::Keep;
+        }
+        if choice == OpeningChoice::Swap {
+            self.sides.swap(0, 1);
+            if let Some(turn) = self.turns.last_mut() {
+                turn.comment = Some("the sides are swapped".to_string());
+            }
+        }
+        choice
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::env;
+    use std::fs;
+    use ::game::{CellState, Move};
+
+    // Places its stones on the first free cells and always makes the same choice
+    struct ScriptedPlayer {
+        choice: OpeningChoice,
+    }
+
+    impl Player for ScriptedPlayer {
+        fn make_turn (&self, _grid: &mut Grid) {
+            unreachable!("the game always says which side to play");
+        }
+
+        fn make_turn_as (&self, grid: &mut Grid, side: PlayerId) {
+            let turn = grid.turns_played();
+            while grid.turns_played() == turn {
+                let coord = grid.get_cells_with_state(CellState::Unset)[0].clone();
+                grid.make_move(Move::at(coord, side, side));
+            }
+        }
+
+        fn choose_opening (&self, _grid: &Grid, _side: PlayerId,
+                           options: &[OpeningChoice]) -> OpeningChoice {
+            if options.contains(&self.choice) { self.choice } else { OpeningChoice::Keep }
+        }
+    }
+
+    // Asks once to have the game saved, then plays like a ScriptedPlayer
+    struct SavingPlayer {
+        path: String,
+        asked: Cell<bool>,
+        result: RefCell<Option<Result<String, String>>>,
+    }
+
+    impl Player for SavingPlayer {
+        fn make_turn (&self, _grid: &mut Grid) {
+            unreachable!("the game always says which side to play");
+        }
+
+        fn make_turn_as (&self, grid: &mut Grid, side: PlayerId) {
+            if !self.asked.get() {
+                return;
+            }
+            ScriptedPlayer { choice: OpeningChoice::Keep }.make_turn_as(grid, side);
+        }
+
+        fn choose_opening (&self, _grid: &Grid, _side: PlayerId,
+                           _options: &[OpeningChoice]) -> OpeningChoice {
+            OpeningChoice::Keep
+        }
+
+        fn save_request (&self) -> Option<String> {
+            if self.asked.replace(true) { None } else { Some(self.path.clone()) }
+        }
+
+        fn saved (&self, result: &Result<String, String>) {
+            *self.result.borrow_mut() = Some(result.clone());
+        }
+    }
+
+    // Never places a stone
+    struct IdlePlayer;
+
+    impl Player for IdlePlayer {
+        fn make_turn (&self, _grid: &mut Grid) {}
+
+        fn make_turn_as (&self, _grid: &mut Grid, _side: PlayerId) {}
+
+        fn choose_opening (&self, _grid: &Grid, _side: PlayerId,
+                           _options: &[OpeningChoice]) -> OpeningChoice {
+            OpeningChoice::Keep
+        }
+    }
+
+    #[test]
+    fn test_save_and_resume() {
+        let first = ScriptedPlayer { choice: OpeningChoice::Keep };
+        let second = ScriptedPlayer { choice: OpeningChoice::Swap };
+        let mut game = Game::new(Grid::new(5, 5, 4), [&first, &second], Opening::Swap);
+        game.play_opening().unwrap();
+        game.play_turn();
+        game.clocks = [Duration::from_millis(1500), Duration::from_millis(250)];
+        let saved = Record::parse(&game.saved().write()).unwrap();
+        assert_eq!(Some("Player Player"), saved.tag("Players"));
+        assert_eq!(Some("2 1"), saved.tag("Sides"));
+        assert_eq!(None, saved.tag("Opening"));
+
+        let mut resumed = Game::resume(&saved, [&first, &second]).unwrap();
+        assert_eq!(game.grid.render(), resumed.grid.render());
+        assert_eq!(game.grid.to_move(), resumed.grid.to_move());
+        assert_eq!(game.player_of(PlayerId(1)), resumed.player_of(PlayerId(1)));
+        assert_eq!(Duration::from_millis(1500), resumed.clock(0));
+        assert_eq!(game.turns, resumed.turns);
+        // the opening is over, so the game goes on with the next turn
+        resumed.play_opening().unwrap();
+        assert_eq!(game.grid.turns_played(), resumed.grid.turns_played());
+    }
+
+    #[test]
+    fn test_resume_rejects_newer_saves() {
+        let mut record = Record::new(&Grid::new(3, 3, 3));
+        record.set_tag("Save", &(SAVE_VERSION + 1).to_string());
+        let player = ScriptedPlayer { choice: OpeningChoice::Keep };
+        assert!(Game::resume(&record, [&player, &player]).is_err());
+        // a plain record is version 0
+        record.tags.retain(|(tag, _)| tag != "Save");
+        assert!(Game::resume(&record, [&player, &player]).is_ok());
+    }
+
+    #[test]
+    fn test_save_request() {
+        let path = env::temp_dir().join(format!("tic-tac-toe-save-{}.txt", std::process::id()));
+        let first = SavingPlayer {
+            path: path.to_string_lossy().into_owned(),
+            asked: Cell::new(false),
+            result: RefCell::new(None),
+        };
+        let second = ScriptedPlayer { choice: OpeningChoice::Keep };
+        let mut game = Game::new(Grid::new(3, 3, 3), [&first, &second], Opening::Free);
+        game.play_turn();
+        // the game was saved before the first stone, and the turn was made after that
+        assert_eq!(Some(Ok(path.to_string_lossy().into_owned())), *first.result.borrow());
+        assert_eq!(1, game.grid.turns_played());
+        assert_eq!(1, game.turns.len());
+        let saved = Record::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(saved.turns.is_empty());
+        assert_eq!(Some("1"), saved.tag("Save"));
+    }
+
+    #[test]
+    fn test_swap() {
+        let first = ScriptedPlayer { choice: OpeningChoice::Keep };
+        let second = ScriptedPlayer { choice: OpeningChoice::Swap };
+        let mut game = Game::new(Grid::new(5, 5, 5), [&first, &second], Opening::Swap);
+        game.play_opening().unwrap();
+        // the second player took over the first stone, now the first player plays side 2
+        assert_eq!(1, game.grid.turns_played());
+        assert_eq!(1, game.player_of(PlayerId(1)));
+        assert_eq!(0, game.player_of(game.grid.to_move()));
+    }
+
+    #[test]
+    fn test_swap2_with_two_more_stones() {
+        let first = ScriptedPlayer { choice: OpeningChoice::Swap };
+        let second = ScriptedPlayer { choice: OpeningChoice::PlaceTwo };
+        let mut game = Game::new(Grid::new(5, 5, 5), [&first, &second], Opening::Swap2);
+        game.play_opening().unwrap();
+        assert_eq!(5, game.grid.turns_played());
+        assert_eq!(PlayerId(2), game.side_of(0));
+    }
+
+    #[test]
+    fn test_soosyrv_swaps_twice() {
+        let first = ScriptedPlayer { choice: OpeningChoice::Swap };
+        let second = ScriptedPlayer { choice: OpeningChoice::Swap };
+        let mut game = Game::new(Grid::new(5, 5, 5), [&first, &second], Opening::Soosyrv);
+        game.play_opening().unwrap();
+        assert_eq!(4, game.grid.turns_played());
+        // the second player took side 1, placed nothing more and lost it again
+        assert_eq!(PlayerId(1), game.side_of(0));
+        assert_eq!(CellState::Set(PlayerId(2)), *game.grid.get_cell(0, 3));
+    }
+
+    #[test]
+    fn test_balanced_opening() {
+        let first = ScriptedPlayer { choice: OpeningChoice::Keep };
+        let second = ScriptedPlayer { choice: OpeningChoice::Keep };
+        let mut game = Game::new(Grid::new(15, 15, 5), [&first, &second], Opening::Balanced(2));
+        game.play_opening().unwrap();
+        assert_eq!(3, game.grid.turns_played());
+        assert_eq!(1, game.player_of(game.grid.to_move()));
+        let mut small = Game::new(Grid::new(3, 3, 3), [&first, &second], Opening::Balanced(3));
+        assert!(small.play().is_err());
+    }
+
+    #[test]
+    fn test_turn_without_stones_forfeits() {
+        let mut game = Game::new(Grid::new(3, 3, 3), [&IdlePlayer, &IdlePlayer], Opening::Free);
+        assert_eq!(GameState::Win(PlayerId(2)), game.play().unwrap());
+        assert!(game.turns.is_empty());
+        assert_eq!(Some(&(PlayerId(1), "The turn has no stones.".to_string())), game.forfeit());
+    }
+
+    #[test]
+    fn test_record() {
+        let first = ScriptedPlayer { choice: OpeningChoice::Keep };
+        let second = ScriptedPlayer { choice: OpeningChoice::Swap };
+        let mut game = Game::new(Grid::new(3, 3, 3), [&first, &second], Opening::Swap);
+        let state = game.play().unwrap();
+        let record = game.record(["first", "second"]);
+        assert_eq!(Some("second"), record.tag("Player1"));
+        assert_eq!(Some(result_text(&state).as_str()), record.tag("Result"));
+        assert_eq!(Some("the sides are swapped".to_string()), record.turns[0].comment);
+        let positions = record.replay().unwrap();
+        assert_eq!(game.grid.render(), positions[positions.len() - 1].render());
+    }
+}
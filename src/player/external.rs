@@ -0,0 +1,367 @@
+use std::cell::{Cell, RefCell};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use ::engine::{parse_turn, PROTOCOL_VERSION};
+use ::game::grid::Grid;
+use ::game::PlayerId;
+use ::game::notation::to_notation;
+use ::game::opening::OpeningChoice;
+use ::player::Player;
+
+// How long an engine may take on top of its move time, to start, to answer other commands
+// and to come back after it was told to stop
+pub const DEFAULT_GRACE: Duration = Duration::from_millis(2000);
+
+// A program that speaks the engine protocol of docs/engine-protocol.md, started for the
+// game. Every turn it gets the position and searches with `genmove`.
+//
+// The engine forfeits the game when it
+// - crashes, closes its output or answers in a way the protocol doesn't allow,
+// - answers `genmove` with an error or with stones that aren't a legal turn,
+// - hasn't answered `genmove` within the move time and the grace. It is then told to
+//   stop and forfeits if that doesn't bring an answer within another grace either.
+pub struct ExternalPlayer {
+    id: u32,
+    process: RefCell<Child>,
+    input: RefCell<ChildStdin>,
+    // the lines the engine writes, read in another thread to be able to wait with a timeout
+    lines: Receiver<String>,
+    move_time: Duration,
+    grace: Duration,
+    evaluation: Cell<Option<i32>>,
+    forfeit: RefCell<Option<String>>,
+}
+
+impl ExternalPlayer {
+    // Starts the engine, a program with its arguments separated by spaces, and checks that it
+    // speaks the same version of the protocol
+    pub fn start(id: u32, command: &str, move_time: Duration,
+                 grace: Duration) -> Result<ExternalPlayer, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("The engine has no command.")?;
+        let mut process = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("Can't start the engine '{}': {}", command, error))?;
+        let input = process.stdin.take().ok_or("The engine has no input.")?;
+        let output = process.stdout.take().ok_or("The engine has no output.")?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() {
+                        return;
+                    },
+                    Err(_) => return,
+                }
+            }
+        });
+        let player = ExternalPlayer {
+            id,
+            process: RefCell::new(process),
+            input: RefCell::new(input),
+            lines,
+            move_time,
+            grace,
+            evaluation: Cell::new(None),
+            forfeit: RefCell::new(None),
+        };
+        match player.ask("protocol_version", grace)? {
+            Ok(ref version) if *version == PROTOCOL_VERSION.to_string() => Ok(player),
+            Ok(version) => Err(format!("The engine speaks version {} of the protocol, not {}.",
+                                       version, PROTOCOL_VERSION)),
+            Err(error) => Err(format!("The engine doesn't tell its protocol version: {}", error)),
+        }
+    }
+
+    fn send(&self, command: &str) -> Result<(), String> {
+        let mut input = self.input.borrow_mut();
+        writeln!(input, "{}", command).and_then(|_| input.flush())
+            .map_err(|_| "The engine stopped reading its input.".to_string())
+    }
+
+    // The next answer of the engine: its result or its error. Fails if the engine ends, if it
+    // takes longer than `timeout` or if the answer isn't one.
+    fn answer(&self, timeout: Duration) -> Result<Result<String, String>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(left) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err("The engine didn't answer in time.".to_string());
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err("The engine crashed.".to_string());
+                },
+            };
+            if line.is_empty() && !lines.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+        let first = &lines[0];
+        let mark = first.chars().next();
+        let rest = &first[mark.map_or(0, char::len_utf8) ..];
+        let marked = mark == Some('=') || mark == Some('?');
+        if !marked || !(rest.is_empty() || rest.starts_with(' ')) {
+            return Err(format!("The engine answered '{}', not '= result' or '? error'.", first));
+        }
+        let mut text = vec![rest.trim_start_matches(' ')];
+        text.extend(lines[1 ..].iter().map(String::as_str));
+        let text = text.join("\n").trim_matches('\n').to_string();
+        Ok(if mark == Some('=') { Ok(text) } else { Err(text) })
+    }
+
+    fn ask(&self, command: &str, timeout: Duration) -> Result<Result<String, String>, String> {
+        self.send(command)?;
+        self.answer(timeout)
+    }
+
+    // Asks for a command that has to work
+    fn tell(&self, command: &str) -> Result<String, String> {
+        self.ask(command, self.grace)?
+            .map_err(|error| format!("The engine refused '{}': {}", command, error))
+    }
+
+    // Searches the turn of the side to move, with `stop` once the time is up
+    fn generate(&self, grid: &Grid) -> Result<Grid, String> {
+        self.tell(&format!("position {}", to_notation(grid)))?;
+        let millis = self.move_time.as_millis();
+        self.send(&format!("genmove movetime {}", millis))?;
+        let answer = match self.answer(self.move_time + self.grace) {
+            Err(ref error) if error.ends_with("in time.") => {
+                self.send("stop")?;
+                let answer = self.answer(self.grace).map_err(|error| {
+                    format!("{} It had {} ms and was told to stop.", error, millis)
+                })?;
+                self.resync()?;
+                answer
+            },
+            answer => answer?,
+        };
+        let stones = answer.map_err(|error| format!("The engine found no turn: {}", error))?;
+        let words: Vec<&str> = stones.split_whitespace().collect();
+        let turn = parse_turn(grid, &words)
+            .map_err(|error| format!("The engine played '{}': {}", stones, error))?;
+        let evaluation = self.ask("evaluation", self.grace)?;
+        self.evaluation.set(evaluation.ok().and_then(|value| value.parse().ok()));
+        Ok(turn)
+    }
+
+    // A search that ended just as it was stopped leaves an answer to 'stop' behind. It is
+    // skipped up to the answer of a command that can't be mistaken for it.
+    fn resync(&self) -> Result<(), String> {
+        self.send("protocol_version")?;
+        while self.answer(self.grace)? != Ok(PROTOCOL_VERSION.to_string()) {}
+        Ok(())
+    }
+
+    fn give_up(&self, reason: String) {
+        *self.forfeit.borrow_mut() = Some(reason);
+        let _ = self.process.borrow_mut().kill();
+    }
+}
+
+impl Player for ExternalPlayer {
+    fn make_turn (&self, grid: &mut Grid) {
+        self.make_turn_as(grid, PlayerId(self.id));
+    }
+
+    // The position tells the engine which side to play, which is always the side to move
+    fn make_turn_as (&self, grid: &mut Grid, _side: PlayerId) {
+        if self.forfeit.borrow().is_some() {
+            return;
+        }
+        match self.generate(grid) {
+            Ok(turn) => *grid = turn,
+            Err(reason) => self.give_up(reason),
+        }
+    }
+
+    fn evaluation (&self) -> Option<i32> {
+        self.evaluation.get()
+    }
+
+    fn name (&self) -> &str {
+        "Engine"
+    }
+
+    // The protocol has no command for the opening, so the engine searches the position and
+    // swaps if it is better for the other side, like the ki. The search is taken back.
+    fn choose_opening (&self, grid: &Grid, side: PlayerId,
+                       options: &[OpeningChoice]) -> OpeningChoice {
+        if self.forfeit.borrow().is_some() {
+            return OpeningChoice::Keep;
+        }
+        let score = self.generate(grid).and_then(|_| {
+            self.tell("undo")?;
+            Ok(self.evaluation.get())
+        });
+        match score {
+            Ok(Some(score)) => {
+                let own_score = if side == grid.to_move() { score } else { -score };
+                if own_score < 0 && options.contains(&OpeningChoice::Swap) {
+                    OpeningChoice::Swap
+                } else {
+                    OpeningChoice::Keep
+                }
+            },
+            Ok(None) => OpeningChoice::Keep,
+            Err(reason) => {
+                self.give_up(reason);
+                OpeningChoice::Keep
+            },
+        }
+    }
+
+    fn forfeit (&self) -> Option<String> {
+        self.forfeit.borrow().clone()
+    }
+}
+
+// The engine gets 'quit' and a grace to end by itself before it is killed
+impl Drop for ExternalPlayer {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + self.grace;
+        let mut process = self.process.borrow_mut();
+        while Instant::now() < deadline {
+            match process.try_wait() {
+                Ok(Some(_)) | Err(_) => return,
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use ::game::GameState;
+    use ::game::opening::Opening;
+    use ::play::Game;
+    use ::player::ki::KiPlayer;
+
+    const MOVE_TIME: Duration = Duration::from_millis(50);
+    const GRACE: Duration = Duration::from_millis(300);
+
+    // A shell script in the temp directory, which is deleted when the test is done with it
+    struct Script {
+        path: PathBuf,
+    }
+
+    impl Script {
+        fn new(name: &str, script: &str) -> Script {
+            let file = format!("tic_tac_toe_engine_{}_{}.sh", name, std::process::id());
+            let path = env::temp_dir().join(file);
+            fs::write(&path, script).unwrap();
+            Script { path }
+        }
+
+        // The command that runs the script
+        fn command(&self) -> String {
+            format!("sh {}", self.path.display())
+        }
+    }
+
+    impl Drop for Script {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    // A shell script that speaks the protocol and runs `genmove` to answer genmove
+    fn fake_engine(name: &str, genmove: &str) -> Script {
+        Script::new(name, &format!("while read command rest; do\n\
+                              case \"$command\" in\n\
+                              protocol_version) printf '= 1\\n\\n' ;;\n\
+                              genmove) {} ;;\n\
+                              evaluation) printf '= 7\\n\\n' ;;\n\
+                              quit) printf '=\\n\\n'; exit 0 ;;\n\
+                              *) printf '=\\n\\n' ;;\n\
+                              esac\n\
+                              done\n", genmove))
+    }
+
+    // The engine with the script it runs, which has to stay until the end of the test
+    fn start(name: &str, genmove: &str) -> (ExternalPlayer, Script) {
+        let script = fake_engine(name, genmove);
+        (ExternalPlayer::start(2, &script.command(), MOVE_TIME, GRACE).unwrap(), script)
+    }
+
+    #[test]
+    fn test_plays_the_turn_of_the_engine() {
+        let (player, _script) = start("legal", "printf '= b2\\n\\n'");
+        let mut grid = Grid::new(3, 3, 3);
+        player.make_turn(&mut grid);
+        assert_eq!("3x3 3 standard 3/1x1/3 2", to_notation(&grid));
+        assert_eq!(Some(7), player.evaluation());
+        assert_eq!(None, player.forfeit());
+    }
+
+    #[test]
+    fn test_illegal_turns_forfeit() {
+        let (player, _script) = start("illegal", "printf '= d9\\n\\n'");
+        let mut grid = Grid::new(3, 3, 3);
+        player.make_turn(&mut grid);
+        assert_eq!(Some("The engine played 'd9': d9 is not on the board.".to_string()),
+                   player.forfeit());
+        assert_eq!(0, grid.turns_played());
+    }
+
+    #[test]
+    fn test_crashes_forfeit() {
+        let (player, _script) = start("crash", "exit 1");
+        let mut grid = Grid::new(3, 3, 3);
+        player.make_turn(&mut grid);
+        assert_eq!(Some("The engine crashed.".to_string()), player.forfeit());
+    }
+
+    #[test]
+    fn test_slow_engines_forfeit() {
+        let (player, _script) = start("slow", "sleep 2; printf '= a1\\n\\n'");
+        let mut grid = Grid::new(3, 3, 3);
+        let started = Instant::now();
+        player.make_turn(&mut grid);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let forfeit = "The engine didn't answer in time. It had 50 ms and was told to stop.";
+        assert_eq!(Some(forfeit.to_string()), player.forfeit());
+    }
+
+    #[test]
+    fn test_other_protocol_versions_are_refused() {
+        let script = Script::new("version", "read command; printf '= 2\\n\\n'");
+        match ExternalPlayer::start(2, &script.command(), MOVE_TIME, GRACE) {
+            Err(error) => assert_eq!("The engine speaks version 2 of the protocol, not 1.", error),
+            Ok(_) => panic!("the engine was started"),
+        }
+    }
+
+    #[test]
+    fn test_forfeit_ends_the_game() {
+        let ki = KiPlayer::new(1);
+        let (engine, _script) = start("game", "printf '= a1\\n\\n'");
+        let mut game = Game::new(Grid::new(3, 3, 3), [&ki, &engine], Opening::Free);
+        // the ki opens in a1, which the engine plays again
+        assert_eq!(GameState::Win(PlayerId(1)), game.play().unwrap());
+        let reason = "The engine played 'a1': a1 is not free.".to_string();
+        assert_eq!(Some(&(PlayerId(2), reason)), game.forfeit());
+        let record = game.record(["Ki", "Engine"]);
+        assert_eq!(Some("1-0"), record.tag("Result"));
+        assert_eq!(Some("Side 2 forfeits: The engine played 'a1': a1 is not free."),
+                   record.tag("Termination"));
+        assert_eq!(1, record.turns.len());
+    }
+}
@@ -1,16 +1,75 @@
+use std::cmp;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use ::player::Player;
 use ::game::grid::Grid;
 use ::game::grid_observer;
-use ::game::{CellState, PlayerId, GameState};
+use ::game::opening::OpeningChoice;
+use ::game::{CellState, PlayerId, GameState, Variant, Move, Coord, ORDER};
+
+// Free cells up to which the ki searches until the game ends, if no depth is given
+const FULL_SEARCH_CELLS: usize = 9;
+// The depth in turns used on bigger boards, if no depth is given
+const DEFAULT_DEPTH: u32 = 2;
+const DEFAULT_MULTI_STONE_DEPTH: u32 = 1;
+// On boards with more cells than this only the cells next to a stone are considered
+const NEIGHBOURHOOD_SEARCH_CELLS: usize = 64;
+const WIN_SCORE: i32 = 1_000_000;
 
 pub struct KiPlayer {
     id: u32,
+    depth: Option<u32>,
+    // how long a turn may take, the search goes deeper until then
+    move_time: Option<Duration>,
+    // the score of the last turn found, from the side that made it
+    evaluation: Cell<Option<i32>>,
 }
 
 impl KiPlayer {
     pub fn new(id: u32) -> KiPlayer {
         KiPlayer {
-            id: id,
+            id,
+            depth: None,
+            move_time: None,
+            evaluation: Cell::new(None),
+        }
+    }
+
+    // A ki that looks at most `depth` turns ahead. Lower depths make for an easier opponent.
+    pub fn with_depth(id: u32, depth: u32) -> KiPlayer {
+        KiPlayer {
+            id,
+            depth: Some(depth),
+            move_time: None,
+            evaluation: Cell::new(None),
+        }
+    }
+
+    // A ki that searches deeper and deeper until `move_time` is up, in every turn
+    pub fn with_move_time(id: u32, move_time: Duration) -> KiPlayer {
+        KiPlayer {
+            id,
+            depth: None,
+            move_time: Some(move_time),
+            evaluation: Cell::new(None),
+        }
+    }
+
+    fn search_depth(&self, grid: &Grid) -> u32 {
+        match self.depth {
+            Some(depth) => depth,
+            None => {
+                let free = grid.get_cells_with_state(CellState::Unset).len();
+                if free <= FULL_SEARCH_CELLS {
+                    free as u32
+                } else if grid.stones_per_turn > 1 {
+                    DEFAULT_MULTI_STONE_DEPTH
+                } else {
+                    DEFAULT_DEPTH
+                }
+            }
         }
     }
 }
@@ -23,25 +82,14 @@ pub enum GameEvaluation {
     Draw,
 }
 
-#[derive(Clone)]
-struct Move {
-    pub row: usize,
-    pub column: usize,
-}
-
-impl Move {
-    fn new(row: usize, column: usize) -> Move {
-        Move {
-            row: row,
-            column: column,
-        }
-    }
-}
-
 //TODO: cache evaluations and just look at differences
 //TODO: benchmark
 pub fn evaluate_game(grid: &Grid, perspective: PlayerId) -> Option<GameEvaluation> {
-    match grid_observer::check_winner(grid) {
+    evaluate_state(grid_observer::check_winner(grid), perspective)
+}
+
+fn evaluate_state(state: GameState, perspective: PlayerId) -> Option<GameEvaluation> {
+    match state {
         GameState::Mid => None,
         GameState::Win(winner) => {
             if winner == perspective {
@@ -50,63 +98,367 @@ pub fn evaluate_game(grid: &Grid, perspective: PlayerId) -> Option<GameEvaluatio
                 Some(GameEvaluation::Lose)
             }
         },
-        GameState::Draw => Some(GameEvaluation::Draw)
+        GameState::Draw => Some(GameEvaluation::Draw),
+        GameState::Scores(first, second) => {
+            let (own, other) = if perspective == PlayerId(1) {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            match own.cmp(&other) {
+                cmp::Ordering::Greater => Some(GameEvaluation::Win),
+                cmp::Ordering::Less => Some(GameEvaluation::Lose),
+                cmp::Ordering::Equal => Some(GameEvaluation::Draw),
+            }
+        }
     }
 }
 
-//TODO: Implement different difficulties
-// less difficulty: lower depth limit
-// if no move can be found within the depth limit, chose a random move
-// maybe the depth limit can be a percentage of the maximum depth?
-//TODO: Cleaner return value. Maybe a GameEnd enum with Draw or Winner(PlayerId) as options?
-fn minimax(grid: &Grid, current_player: PlayerId, other_player: PlayerId, depth: u32)
-    -> (GameEvaluation, Option<Move>, u32) {
-    let evaluation = evaluate_game(grid, current_player);
+// The free cells worth placing a stone on. On big boards these are only the ones close to a
+// stone that is already placed, or the centre of an empty board. With several stones per turn
+// a line can be extended further, so the neighbourhood grows with the stones.
+fn candidate_cells(grid: &Grid) -> Vec<Coord> {
+    let free = grid.get_cells_with_state(CellState::Unset);
+    if grid.cell_count() <= NEIGHBOURHOOD_SEARCH_CELLS {
+        return free;
+    }
+    let distance = cmp::max(grid.stones_left() as isize, 1);
+    let offsets = grid.neighbourhood(distance);
+    let near_stone = |index: usize| {
+        offsets.iter().any(|offset| match grid.offset_index(index, offset) {
+            Some(other) => matches!(*grid.cell_at_index(other), CellState::Set(_)),
+            None => false,
+        })
+    };
+    let candidates: Vec<_> = (0 .. grid.cell_count())
+        .filter(|&index| *grid.cell_at_index(index) == CellState::Unset && near_stone(index))
+        .map(|index| grid.coord_of(index))
+        .collect();
+    if candidates.is_empty() {
+        let centre = Coord(grid.dimensions().iter().map(|size| size / 2).collect());
+        if free.contains(&centre) {
+            return vec![centre];
+        }
+        return free;
+    }
+    candidates
+}
 
-    if evaluation.is_some() {
-        (evaluation.unwrap(), None, depth)
+// The score of a game that ended with points for the player to move. It is won or lost like
+// any game, by as many points as the lead, so a bigger lead is better.
+fn points_score(first: u32, second: u32, current_player: PlayerId, depth: u32) -> i32 {
+    let (own, other) = if current_player == PlayerId(1) {
+        (first, second)
     } else {
-        let mut wins: Vec<(Move, u32)> = Vec::new();
-        let mut draws: Vec<(Move, u32)> = Vec::new();
-        let mut loses: Vec<(Move, u32)> = Vec::new();
+        (second, first)
+    };
+    let lead = i64::from(own) - i64::from(other);
+    let margin = cmp::min(lead.abs().saturating_mul(1000), i64::from(WIN_SCORE / 4)) as i32;
+    match lead.cmp(&0) {
+        cmp::Ordering::Greater => WIN_SCORE / 2 + margin - depth as i32,
+        cmp::Ordering::Less => depth as i32 - WIN_SCORE / 2 - margin,
+        cmp::Ordering::Equal => 0,
+    }
+}
+
+// Every way to place the stones of the current turn. A turn with several stones is a single
+// decision, so the order of its stones doesn't matter. Every turn has all its stones, unless
+// they fill the board and end the game, as the search gives the next move to the opponent.
+pub(crate) fn possible_turns(grid: &Grid, player: PlayerId) -> Vec<Vec<Move>> {
+    let mut cells = candidate_cells(grid);
+    let stones = grid.stones_left() as usize;
+    // with too few cells near the stones for the whole turn, other free cells make it up
+    for coord in grid.get_cells_with_state(CellState::Unset) {
+        if cells.len() >= stones {
+            break;
+        }
+        if !cells.contains(&coord) {
+            cells.push(coord);
+        }
+    }
+    let stones = cmp::min(stones, cells.len());
+    let symbols = grid.variant.symbols(player);
+    let mut turns = Vec::new();
+    add_turns(&cells, stones, &symbols, player, &mut Vec::new(), &mut turns);
+    turns
+}
+
+fn add_turns(cells: &[Coord], stones: usize, symbols: &[PlayerId], player: PlayerId,
+             turn: &mut Vec<Move>, turns: &mut Vec<Vec<Move>>) {
+    if turn.len() == stones {
+        turns.push(turn.clone());
+        return;
+    }
+    for (index, coord) in cells.iter().enumerate() {
+        for &symbol in symbols {
+            turn.push(Move::at(coord.clone(), player, symbol));
+            add_turns(&cells[index + 1 ..], stones, symbols, player, turn, turns);
+            turn.pop();
+        }
+    }
+}
+
+// Rates an undecided position for the player to move, by looking at the lines that can
+// still be completed. A line the player to move can complete with the stones of this turn
+// is as good as a win. When every line scores, the completed lines count the most and the
+// game goes on, so there is nothing like a win.
+fn heuristic(grid: &Grid, windows: &[Vec<usize>], current_player: PlayerId) -> i32 {
+    let stones = grid.stones_left() as usize;
+    let mut score = 0;
+    for window in windows {
+        let mut symbol = None;
+        let mut count = 0;
+        let mut open = true;
+        for &index in window {
+            match *grid.cell_at_index(index) {
+                CellState::Set(id) if symbol.is_none() || symbol == Some(id) => {
+                    symbol = Some(id);
+                    count += 1;
+                },
+                CellState::Set(_) | CellState::Blocked => {
+                    open = false;
+                    break;
+                },
+                CellState::Unset => {},
+            }
+        }
+        if !open || count == 0 {
+            continue;
+        }
+        let completable = count + stones >= window.len();
+        let weight = 1 << (2 * count);
+        if grid.scoring {
+            score += if symbol == Some(current_player) { weight } else { -weight };
+            continue;
+        }
+        match grid.variant {
+            Variant::Standard => {
+                if symbol == Some(current_player) {
+                    if completable {
+                        return WIN_SCORE / 2;
+                    }
+                    score += weight
+                } else {
+                    score -= weight
+                }
+            },
+            // Every open line is a chance for Order
+            Variant::OrderAndChaos => {
+                if current_player == ORDER {
+                    if completable {
+                        return WIN_SCORE / 2;
+                    }
+                    score += weight
+                } else {
+                    score -= weight
+                }
+            },
+            // A line of either symbol is won by whoever moves next
+            Variant::Wild => {
+                if completable {
+                    return WIN_SCORE / 2;
+                }
+            },
+        }
+    }
+    score
+}
+
+// The lines and the places of the patterns, partly filled patterns count like partly filled
+// lines
+fn windows(grid: &Grid) -> Vec<Vec<usize>> {
+    let mut windows = grid_observer::window_indices(grid);
+    windows.extend(grid_observer::pattern_indices(grid).into_iter().map(|(_, cells)| cells));
+    windows
+}
+
+// How long a search may go on. Without a depth the ki picks one itself, unless there is a
+// deadline, then it searches deeper and deeper until the time is up. The stop flag ends any
+// search early.
+#[derive(Default)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub deadline: Option<Instant>,
+    // set from another thread to end the search
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+// The state of a single search. The windows only depend on the size of the grid, so they are
+// looked up once instead of in every position.
+struct Search<'a> {
+    windows: Vec<Vec<usize>>,
+    max_depth: u32,
+    // None for a search that always runs to the end
+    limits: Option<&'a Limits>,
+    aborted: Cell<bool>,
+}
+
+impl<'a> Search<'a> {
+    fn out_of_time(&self) -> bool {
+        let limits = match self.limits {
+            Some(limits) => limits,
+            None => return false,
+        };
+        limits.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || limits.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
 
-        for (row, col) in grid.get_cells_with_state(CellState::Unset) {
+    // Returns the best score for the current player together with the turn that achieves it.
+    // Quicker wins and slower losses score better. The state of the game is passed in, since
+    // after a turn only the lines through its stones need to be checked.
+    fn minimax(&self, grid: &Grid, state: GameState, current_player: PlayerId, depth: u32,
+               mut alpha: i32, beta: i32) -> (i32, Option<Vec<Move>>) {
+        if let GameState::Scores(first, second) = state {
+            return (points_score(first, second, current_player, depth), None);
+        }
+        match evaluate_state(state, current_player) {
+            Some(GameEvaluation::Win) => return (WIN_SCORE - depth as i32, None),
+            Some(GameEvaluation::Lose) => return (depth as i32 - WIN_SCORE, None),
+            Some(GameEvaluation::Draw) => return (0, None),
+            None => {},
+        }
+        if depth >= self.max_depth {
+            return (heuristic(grid, &self.windows, current_player), None);
+        }
+        // the first turn at the root is always looked at, see find_turn_within
+        if depth > 0 && (self.aborted.get() || self.out_of_time()) {
+            self.aborted.set(true);
+            return (0, None);
+        }
+
+        let mut best_score = -WIN_SCORE - 1;
+        let mut best_turn = None;
+        for turn in possible_turns(grid, current_player) {
             //TODO: Use multiple threads
             let mut new_grid = grid.clone();
-            new_grid.set_cell(row, col, current_player);
-            let (evaluation, _, depth) = minimax(&new_grid, other_player, current_player, depth + 1);
-            match evaluation {
-                //TODO: Stop mixing up perspectives
-                GameEvaluation::Lose => wins.push((Move::new(row, col), depth)),
-                GameEvaluation::Draw => draws.push((Move::new(row, col), depth)),
-                GameEvaluation::Win => loses.push((Move::new(row, col), depth)),
+            for mov in &turn {
+                new_grid.make_move(mov.clone());
             }
+            let placed: Vec<Coord> = turn.iter().map(|mov| mov.coord.clone()).collect();
+            let state = grid_observer::check_winner_after(&new_grid, &placed);
+            let (score, _) = self.minimax(&new_grid, state, current_player.opponent(),
+                                          depth + 1, -beta, -alpha);
+            let score = -score;
+            if score > best_score {
+                best_score = score;
+                best_turn = Some(turn);
+            }
+            alpha = cmp::max(alpha, score);
+            if alpha >= beta {
+                break;
+            }
+            if depth == 0 && self.out_of_time() {
+                self.aborted.set(true);
+                break;
+            }
+        }
+        (best_score, best_turn)
+    }
+}
+
+impl KiPlayer {
+    // The stones the ki would place in the current turn
+    pub fn find_turn(&self, grid: &Grid) -> Option<Vec<Move>> {
+        self.find_turn_as(grid, PlayerId(self.id))
+    }
+
+    pub fn find_turn_as(&self, grid: &Grid, side: PlayerId) -> Option<Vec<Move>> {
+        if let Some(move_time) = self.move_time {
+            let limits = Limits { deadline: Some(Instant::now() + move_time), ..Limits::default() };
+            return self.find_turn_within(grid, side, &limits);
         }
+        let (score, turn) = self.search(grid, side);
+        self.evaluation.set(Some(score));
+        turn
+    }
 
-        if !wins.is_empty() {
-            let (mov, depth) = wins[0].clone();
-            (GameEvaluation::Win, Some(mov), depth)
-        } else if !draws.is_empty() {
-            let (mov, depth) = draws[0].clone();
-            (GameEvaluation::Draw, Some(mov), depth)
-        } else if !loses.is_empty() {
-            let (mov, depth) = loses[0].clone();
-            (GameEvaluation::Lose, Some(mov), depth)
-        } else {
-            panic!("No possible move, even though the game shouldn't be finished.");
+    // Like find_turn_as, within the given limits. The search goes one turn deeper at a time
+    // and the turn of the deepest search that was finished is taken. When the time is up
+    // during the first search, one turn deep, the best of the turns it looked at is taken, so
+    // there is a turn even if the time is up right away.
+    pub fn find_turn_within(&self, grid: &Grid, side: PlayerId,
+                            limits: &Limits) -> Option<Vec<Move>> {
+        let free = grid.get_cells_with_state(CellState::Unset).len() as u32;
+        let max_depth = match limits.depth {
+            Some(depth) => depth,
+            None if limits.deadline.is_some() => free,
+            None => self.search_depth(grid),
+        };
+        let windows = windows(grid);
+        let mut best = None;
+        for depth in 1 ..= cmp::max(max_depth, 1) {
+            let search = Search {
+                windows: windows.clone(),
+                max_depth: depth,
+                limits: Some(limits),
+                aborted: Cell::new(false),
+            };
+            let state = grid_observer::check_winner(grid);
+            let (score, turn) = search.minimax(grid, state, side, 0, -WIN_SCORE - 1, WIN_SCORE + 1);
+            if search.aborted.get() {
+                if best.is_none() {
+                    self.evaluation.set(Some(score));
+                    best = turn;
+                }
+                break;
+            }
+            self.evaluation.set(Some(score));
+            best = turn;
+            // a won or lost game doesn't get better with a deeper search, and neither does one
+            // that was searched to its end
+            if score.abs() > WIN_SCORE / 2 || depth >= free {
+                break;
+            }
         }
+        best
     }
 
+    // The best score `side` can reach when it is their turn, with the turn that reaches it
+    fn search(&self, grid: &Grid, side: PlayerId) -> (i32, Option<Vec<Move>>) {
+        let search = Search {
+            windows: windows(grid),
+            max_depth: self.search_depth(grid),
+            limits: None,
+            aborted: Cell::new(false),
+        };
+        let state = grid_observer::check_winner(grid);
+        search.minimax(grid, state, side, 0, -WIN_SCORE - 1, WIN_SCORE + 1)
     }
+}
 
 impl Player for KiPlayer {
     fn make_turn (&self, grid: &mut Grid) {
-        //TODO: Proper way to determine other player (Array of players?)
-        let other_id = if self.id == 1 {2} else {1};
-        let (_, position, _) = minimax(grid, PlayerId(self.id), PlayerId(other_id), 0);
-        // TODO: don't use unwrap
-        let position = position.unwrap();
-        grid.set_cell(position.row, position.column, PlayerId(self.id));
+        self.make_turn_as(grid, PlayerId(self.id));
+    }
+
+    fn make_turn_as (&self, grid: &mut Grid, side: PlayerId) {
+        let turn = self.find_turn_as(grid, side)
+            .expect("No possible move, even though the game shouldn't be finished.");
+        for mov in turn {
+            grid.make_move(mov);
+        }
+    }
+
+    fn evaluation (&self) -> Option<i32> {
+        self.evaluation.get()
+    }
+
+    fn name (&self) -> &str {
+        "Ki"
+    }
+
+    // Swaps if the position is better for the other side. The ki never places more
+    // stones itself, that's the choice of a player who'd rather not judge the position.
+    fn choose_opening (&self, grid: &Grid, side: PlayerId,
+                       options: &[OpeningChoice]) -> OpeningChoice {
+        let to_move = grid.to_move();
+        let (score, _) = self.search(grid, to_move);
+        let own_score = if side == to_move { score } else { -score };
+        if own_score < 0 && options.contains(&OpeningChoice::Swap) {
+            OpeningChoice::Swap
+        } else {
+            OpeningChoice::Keep
+        }
     }
 }
 
@@ -114,9 +466,11 @@ impl Player for KiPlayer {
 mod test {
     use super::*;
     use ::player::Player;
-    use ::game::{CellState, PlayerId};
+    use ::game::{CellState, PlayerId, GameState, Variant, Move, Coord, X, O, ORDER, CHAOS};
     use ::game::grid::Grid;
     use ::game::grid_observer;
+    use ::game::notation::parse_notation;
+    use ::game::pattern::Pattern;
 
 
     #[test]
@@ -125,10 +479,10 @@ mod test {
         let mut grid = Grid::new(1, 1, 1);
         let ki = KiPlayer::new(KI_ID);
         ki.make_turn(&mut grid);
-        match grid.get_cell(0, 0) {
-            &CellState::Unset => panic!("The ki didn't do anything."),
-            &CellState::Set(PlayerId(KI_ID)) => return,
-            &CellState::Set(PlayerId(id)) =>
+        match *grid.get_cell(0, 0) {
+            CellState::Unset | CellState::Blocked => panic!("The ki didn't do anything."),
+            CellState::Set(PlayerId(KI_ID)) => {},
+            CellState::Set(PlayerId(id)) =>
                 panic!("The ki with the {} made a turn for player {}.", KI_ID, id),
         }
     }
@@ -162,6 +516,33 @@ mod test {
     //     assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(1, 2));
     // }
 
+    #[test]
+    fn ki_finds_a_turn_when_stopped_at_once() {
+        let grid = Grid::new(7, 7, 4);
+        let ki = KiPlayer::new(1);
+        let stopped = Limits { stop: Some(Arc::new(AtomicBool::new(true))), ..Limits::default() };
+        let turn = ki.find_turn_within(&grid, PlayerId(1), &stopped);
+        assert_eq!(Some(1), turn.map(|turn| turn.len()));
+        let late = Limits { deadline: Some(Instant::now()), ..Limits::default() };
+        assert!(ki.find_turn_within(&grid, PlayerId(1), &late).is_some());
+    }
+
+    #[test]
+    fn ki_wins_within_a_deadline() {
+        let mut grid = Grid::new(5, 5, 3);
+        grid.set_cell(2, 1, PlayerId(1));
+        grid.set_cell(0, 0, PlayerId(2));
+        grid.set_cell(2, 2, PlayerId(1));
+        grid.set_cell(4, 4, PlayerId(2));
+        let limits = Limits { deadline: Some(Instant::now() + ::std::time::Duration::from_secs(5)),
+                              ..Limits::default() };
+        let turn = KiPlayer::new(1).find_turn_within(&grid, PlayerId(1), &limits).unwrap();
+        for mov in turn {
+            grid.make_move(mov);
+        }
+        assert_eq!(GameState::Win(PlayerId(1)), grid_observer::check_winner(&grid));
+    }
+
     #[test]
     fn ki_wins_without_active_opponent() {
         const KI_ID: u32 = 1;
@@ -208,4 +589,190 @@ mod test {
         grid.set_cell(0, 2, PlayerId(OPPONENT_ID));
         assert_eq!(GameEvaluation::Lose, evaluate_game(&grid, PlayerId(KI_ID)).unwrap());
     }
+
+    #[test]
+    fn ki_completes_a_line_of_the_opponents_symbol_in_wild() {
+        const KI_ID: u32 = 2;
+        let mut grid = Grid::with_variant(3, 3, 3, Variant::Wild);
+        grid.make_move(Move::with_symbol(0, 0, PlayerId(1), X));
+        grid.make_move(Move::with_symbol(2, 2, PlayerId(2), O));
+        grid.make_move(Move::with_symbol(1, 1, PlayerId(1), O));
+        grid.make_move(Move::with_symbol(0, 2, PlayerId(2), O));
+        grid.make_move(Move::with_symbol(1, 0, PlayerId(1), X));
+        KiPlayer::new(KI_ID).make_turn(&mut grid);
+        assert_eq!(GameState::Win(PlayerId(KI_ID)), grid_observer::check_winner(&grid));
+    }
+
+    #[test]
+    fn ki_as_order_completes_five() {
+        let mut grid = Grid::with_variant(6, 6, 5, Variant::OrderAndChaos);
+        for col in 0 .. 4 {
+            grid.make_move(Move::with_symbol(3, col, CHAOS, O));
+        }
+        grid.make_move(Move::with_symbol(3, 4, CHAOS, X));
+        grid.make_move(Move::with_symbol(0, 5, CHAOS, O));
+        grid.make_move(Move::with_symbol(1, 5, CHAOS, O));
+        grid.make_move(Move::with_symbol(2, 5, CHAOS, O));
+        grid.make_move(Move::with_symbol(4, 5, CHAOS, O));
+        KiPlayer::with_depth(ORDER.0, 1).make_turn(&mut grid);
+        assert_eq!(GameState::Win(ORDER), grid_observer::check_winner(&grid));
+    }
+
+    #[test]
+    fn ki_as_chaos_blocks_a_four() {
+        let mut grid = Grid::with_variant(6, 6, 5, Variant::OrderAndChaos);
+        for col in 0 .. 4 {
+            grid.make_move(Move::with_symbol(0, col, ORDER, X));
+        }
+        KiPlayer::with_depth(CHAOS.0, 2).make_turn(&mut grid);
+        assert_eq!(CellState::Set(O), *grid.get_cell(0, 4));
+    }
+
+    #[test]
+    fn ki_completes_six_with_two_stones() {
+        const KI_ID: u32 = 1;
+        // four in a row, blocked on one side, and two stones to place
+        let empty = |rows: usize| vec!["19"; rows].join("/");
+        let position = format!("19x19 6 standard x18/{}/4oxxxx10/{}/oo17/ooo16 1 stones=1/2",
+                               empty(8), empty(7));
+        let mut grid = parse_notation(&position).unwrap();
+        assert_eq!(2, grid.stones_left());
+        KiPlayer::new(KI_ID).make_turn(&mut grid);
+        assert_eq!(GameState::Win(PlayerId(KI_ID)), grid_observer::check_winner(&grid));
+    }
+
+    #[test]
+    fn possible_turns_place_all_stones_of_the_turn() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.stones_per_turn = 2;
+        grid.set_cell(0, 0, PlayerId(1));
+        let turns = possible_turns(&grid, PlayerId(2));
+        // every pair of the 8 free cells, each only once
+        assert_eq!(8 * 7 / 2, turns.len());
+        assert!(turns.iter().all(|turn| turn.len() == 2 && turn[0] != turn[1]));
+    }
+
+    #[test]
+    fn possible_turns_fill_the_turn_away_from_the_stones() {
+        // on an empty big board only the centre is near enough to count
+        let position = format!("19x19 6 standard {} 1 stones=2/2", vec!["19"; 19].join("/"));
+        let grid = parse_notation(&position).unwrap();
+        let turns = possible_turns(&grid, PlayerId(1));
+        assert!(!turns.is_empty());
+        assert!(turns.iter().all(|turn| turn.len() == 2));
+        // only a turn that fills the board has fewer stones
+        let grid = parse_notation("2x4 3 standard xoxo/ox1o 1 stones=1/2").unwrap();
+        assert_eq!(2, grid.stones_left());
+        let turns = possible_turns(&grid, PlayerId(1));
+        assert_eq!(vec![1], turns.iter().map(Vec::len).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn ki_wins_across_the_edge_with_wrap() {
+        const KI_ID: u32 = 1;
+        let mut grid = Grid::new(10, 10, 4);
+        grid.wrap = true;
+        grid.set_cell(4, 8, PlayerId(KI_ID));
+        grid.set_cell(4, 9, PlayerId(KI_ID));
+        grid.set_cell(4, 0, PlayerId(KI_ID));
+        grid.set_cell(4, 7, PlayerId(2));
+        KiPlayer::new(KI_ID).make_turn(&mut grid);
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(4, 1));
+    }
+
+    #[test]
+    fn ki_plays_around_blocked_cells() {
+        const KI_ID: u32 = 1;
+        // the row through the middle is blocked, the only line left is the column
+        let mut grid = Grid::from_shape("_#_\n___\n_#_", 3).unwrap();
+        grid.set_cell(0, 0, PlayerId(KI_ID));
+        grid.set_cell(1, 0, PlayerId(KI_ID));
+        grid.set_cell(1, 1, PlayerId(2));
+        grid.set_cell(1, 2, PlayerId(2));
+        KiPlayer::new(KI_ID).make_turn(&mut grid);
+        assert_eq!(GameState::Win(PlayerId(KI_ID)), grid_observer::check_winner(&grid));
+        assert_eq!(CellState::Blocked, *grid.get_cell(0, 1));
+        assert_eq!(CellState::Blocked, *grid.get_cell(2, 1));
+    }
+
+    #[test]
+    fn ki_blocks_in_qubic() {
+        const KI_ID: u32 = 2;
+        let mut grid = Grid::with_dimensions(vec![4, 4, 4], 4);
+        for layer in 0 .. 3 {
+            let coord = Coord(vec![layer, layer, 3 - layer]);
+            grid.make_move(Move::at(coord, PlayerId(1), PlayerId(1)));
+        }
+        grid.make_move(Move::at(Coord(vec![0, 0, 0]), PlayerId(2), PlayerId(2)));
+        grid.make_move(Move::at(Coord(vec![1, 0, 0]), PlayerId(2), PlayerId(2)));
+        KiPlayer::with_depth(KI_ID, 2).make_turn(&mut grid);
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell_at(&Coord(vec![3, 3, 0])));
+    }
+
+    #[test]
+    fn ki_blocks_on_a_hex_board() {
+        const KI_ID: u32 = 2;
+        let mut grid = Grid::hexagon(5, 4);
+        grid.set_cell(2, 6, PlayerId(1));
+        grid.set_cell(3, 5, PlayerId(1));
+        grid.set_cell(4, 4, PlayerId(1));
+        grid.set_cell(5, 3, PlayerId(KI_ID));
+        KiPlayer::with_depth(KI_ID, 2).make_turn(&mut grid);
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(1, 7));
+    }
+
+    #[test]
+    fn ki_blocks_a_pattern() {
+        const KI_ID: u32 = 2;
+        let mut grid = Grid::new(4, 4, 4);
+        grid.patterns.push(Pattern::square(2));
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(0, 1, PlayerId(1));
+        grid.set_cell(1, 0, PlayerId(1));
+        grid.set_cell(3, 3, PlayerId(KI_ID));
+        KiPlayer::new(KI_ID).make_turn(&mut grid);
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(1, 1));
+    }
+
+    #[test]
+    fn ki_maximises_the_points_when_every_line_scores() {
+        const KI_ID: u32 = 2;
+        let mut grid = Grid::new(3, 3, 3);
+        grid.scoring = true;
+        // (0, 2) completes a line for both players, (2, 2) only a diagonal for the ki
+        for &(row, col) in &[(0, 0), (0, 1), (1, 2), (2, 1)] {
+            grid.set_cell(row, col, PlayerId(KI_ID));
+        }
+        for &(row, col) in &[(1, 0), (1, 1), (2, 0)] {
+            grid.set_cell(row, col, PlayerId(1));
+        }
+        KiPlayer::new(KI_ID).make_turn(&mut grid);
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(0, 2));
+        grid.set_cell(2, 2, PlayerId(1));
+        assert_eq!(GameState::Scores(0, 1), grid_observer::check_winner(&grid));
+        assert_eq!(Some(GameEvaluation::Win), evaluate_game(&grid, PlayerId(KI_ID)));
+    }
+
+    #[test]
+    fn points_decide_a_finished_game() {
+        // ahead by one point is a win for the second player and a loss for the first
+        assert!(points_score(0, 1, PlayerId(2), 3) > WIN_SCORE / 2);
+        assert_eq!(-points_score(0, 1, PlayerId(2), 3), points_score(0, 1, PlayerId(1), 3));
+        assert!(points_score(3, 1, PlayerId(1), 3) > points_score(2, 1, PlayerId(1), 3));
+        assert!(points_score(9999, 0, PlayerId(1), 3) < WIN_SCORE);
+        assert_eq!(0, points_score(2, 2, PlayerId(1), 3));
+    }
+
+    #[test]
+    fn ki_swaps_into_a_won_position() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(2, 2, PlayerId(2));
+        grid.set_cell(0, 1, PlayerId(1));
+        grid.set_cell(1, 0, PlayerId(1));
+        let ki = KiPlayer::new(2);
+        let options = [OpeningChoice::Keep, OpeningChoice::Swap];
+        assert_eq!(OpeningChoice::Swap, ki.choose_opening(&grid, PlayerId(2), &options));
+        assert_eq!(OpeningChoice::Keep, ki.choose_opening(&grid, PlayerId(1), &options));
+    }
 }
@@ -1,8 +1,39 @@
 pub mod terminal;
 pub mod ki;
+pub mod tui;
+pub mod external;
+pub mod network;
 
 use ::game::grid::Grid;
+use ::game::PlayerId;
+use ::game::opening::OpeningChoice;
 
 pub trait Player {
     fn make_turn (&self, grid: &mut Grid);
+    // Makes the turn of the given side, which is not always the player's own. Opening
+    // protocols have one player place stones for both sides, and sides can be swapped.
+    fn make_turn_as (&self, grid: &mut Grid, side: PlayerId);
+    // Picks one of the options an opening protocol offers, while playing `side`
+    fn choose_opening (&self, grid: &Grid, side: PlayerId,
+                       options: &[OpeningChoice]) -> OpeningChoice;
+    // How good the player thought their last turn was, for the game record
+    fn evaluation (&self) -> Option<i32> {
+        None
+    }
+    // What kind of player this is, so that a saved game can be resumed with the same players
+    fn name (&self) -> &str {
+        "Player"
+    }
+    // A player may end make_turn without a stone to have the game saved first. This is the
+    // file to save it in, an empty one lets the game choose.
+    fn save_request (&self) -> Option<String> {
+        None
+    }
+    // Where the game was saved after a request, or why it couldn't be
+    fn saved (&self, _result: &Result<String, String>) {}
+    // Why the player lost by breaking a rule, like an engine that crashed or made an illegal
+    // turn. The game ends as soon as a player forfeits.
+    fn forfeit (&self) -> Option<String> {
+        None
+    }
 }
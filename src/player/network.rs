@@ -0,0 +1,446 @@
+use std::cell::{Cell, RefCell};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+use ::engine::parse_turn;
+use ::game::grid::Grid;
+use ::game::{CellState, PlayerId};
+use ::game::notation::{parse_notation, to_notation};
+use ::game::opening::OpeningChoice;
+use ::game::record::write_stone;
+use ::game::Move;
+use ::player::Player;
+use ::server::PROTOCOL_VERSION;
+
+// How often and how far apart a lost connection is tried again
+const RECONNECT_ATTEMPTS: u32 = 30;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// A seat in a game on a server, see docs/network-protocol.md. It holds every turn the server
+// confirmed, and after a lost connection it connects again with its token and gets them all
+// once more.
+pub struct Connection {
+    address: String,
+    game: String,
+    // the position a new game starts from
+    position: String,
+    token: RefCell<String>,
+    side: Cell<PlayerId>,
+    stream: RefCell<Option<(BufReader<TcpStream>, TcpStream)>>,
+    start: RefCell<Grid>,
+    // the turns of the server and how many of them the game here has
+    turns: RefCell<Vec<String>>,
+    applied: Cell<usize>,
+    // the server refused the last turn sent
+    refused: Cell<bool>,
+    // how often the seat was taken, which tells when the connection was made again
+    connections: Cell<u32>,
+    verbose: bool,
+}
+
+impl Connection {
+    // Takes a seat in the game on the server at `address`, which is started from `position`
+    // if there is none of that name yet. With `verbose` what happens on the server, like the
+    // opponent coming and going, is printed.
+    pub fn join(address: &str, game: &str, position: &Grid,
+                verbose: bool) -> Result<Connection, String> {
+        Connection::open(address, game, to_notation(position), String::new(), verbose)
+    }
+
+    // Takes back the seat of the token in the game
+    pub fn rejoin(address: &str, game: &str, token: &str,
+                  verbose: bool) -> Result<Connection, String> {
+        Connection::open(address, game, String::new(), token.to_string(), verbose)
+    }
+
+    fn open(address: &str, game: &str, position: String, token: String,
+            verbose: bool) -> Result<Connection, String> {
+        let connection = Connection {
+            address: address.to_string(),
+            game: game.to_string(),
+            position,
+            token: RefCell::new(token),
+            side: Cell::new(PlayerId(1)),
+            stream: RefCell::new(None),
+            start: RefCell::new(Grid::new(3, 3, 3)),
+            turns: RefCell::new(Vec::new()),
+            applied: Cell::new(0),
+            refused: Cell::new(false),
+            connections: Cell::new(0),
+            verbose,
+        };
+        connection.connect()?;
+        Ok(connection)
+    }
+
+    // The side this client plays
+    pub fn side(&self) -> PlayerId {
+        self.side.get()
+    }
+
+    // What gives the seat back after the program ended, with `rejoin`
+    pub fn token(&self) -> String {
+        self.token.borrow().clone()
+    }
+
+    // The position the game started from. The turns after it come as the turns of the players.
+    pub fn start(&self) -> Grid {
+        self.start.borrow().clone()
+    }
+
+    fn notice(&self, text: &str) {
+        if self.verbose {
+            println!("{}", text);
+        }
+    }
+
+    // Connects, takes the seat and reads the game up to the 'joined' that follows it
+    fn connect(&self) -> Result<(), String> {
+        let stream = TcpStream::connect(&self.address)
+            .map_err(|error| format!("Can't connect to {}: {}", self.address, error))?;
+        let writer = stream.try_clone().map_err(|error| error.to_string())?;
+        *self.stream.borrow_mut() = Some((BufReader::new(stream), writer));
+        let welcome = self.read()?;
+        if welcome != format!("welcome {}", PROTOCOL_VERSION) {
+            *self.stream.borrow_mut() = None;
+            return Err(format!("The server greets with '{}', not protocol version {}.",
+                               welcome, PROTOCOL_VERSION));
+        }
+        let token = self.token();
+        if token.is_empty() {
+            self.send(&format!("join {} {}", self.game, self.position))?;
+        } else {
+            self.send(&format!("rejoin {} {}", self.game, token))?;
+        }
+        loop {
+            let message = self.read()?;
+            if let Some(error) = message.strip_prefix("error ") {
+                *self.stream.borrow_mut() = None;
+                return Err(error.to_string());
+            }
+            if self.handle(&message)? {
+                self.connections.set(self.connections.get() + 1);
+                return Ok(());
+            }
+        }
+    }
+
+    // Connects again after the connection was lost, up to the number of attempts
+    fn reconnect(&self) -> Result<(), String> {
+        *self.stream.borrow_mut() = None;
+        self.notice("The connection to the server is lost, trying again.");
+        let mut result = Err("The connection to the server is lost.".to_string());
+        for _ in 0 .. RECONNECT_ATTEMPTS {
+            thread::sleep(RECONNECT_DELAY);
+            result = self.connect();
+            if result.is_ok() {
+                self.notice("Connected again.");
+                return result;
+            }
+        }
+        result
+    }
+
+    fn read(&self) -> Result<String, String> {
+        let mut stream = self.stream.borrow_mut();
+        let (ref mut reader, _) = *stream.as_mut().ok_or("There is no connection.")?;
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(read) if read > 0 => Ok(line.trim_end().to_string()),
+            _ => Err("The server closed the connection.".to_string()),
+        }
+    }
+
+    fn send(&self, message: &str) -> Result<(), String> {
+        let mut stream = self.stream.borrow_mut();
+        let (_, ref mut writer) = *stream.as_mut().ok_or("There is no connection.")?;
+        writeln!(writer, "{}", message)
+            .and_then(|_| writer.flush())
+            .map_err(|error| error.to_string())
+    }
+
+    // Takes in a message of the server. Tells whether it was the 'joined' that ends the game
+    // sent after joining.
+    fn handle(&self, message: &str) -> Result<bool, String> {
+        let (kind, text) = match message.find(' ') {
+            Some(index) => (&message[.. index], &message[index + 1 ..]),
+            None => (message, ""),
+        };
+        match kind {
+            "start" => {
+                *self.start.borrow_mut() = parse_notation(text)?;
+                self.turns.borrow_mut().clear();
+            },
+            "turn" => self.turns.borrow_mut().push(text.to_string()),
+            "joined" => {
+                let words: Vec<&str> = text.split_whitespace().collect();
+                match words[..] {
+                    [_, side, token] if side == "1" || side == "2" => {
+                        self.side.set(PlayerId(if side == "1" { 1 } else { 2 }));
+                        *self.token.borrow_mut() = token.to_string();
+                    },
+                    _ => {
+                        let expected = "not 'joined <game> <side> <token>'";
+                        return Err(format!("The server sent '{}', {}.", message, expected));
+                    },
+                }
+                return Ok(true);
+            },
+            "error" => {
+                self.refused.set(true);
+                self.notice(&format!("The server refused the turn: {}", text));
+            },
+            "result" => self.notice(&format!("The server has the result {}.", text)),
+            "opponent" if text == "joined" => self.notice("Your opponent is here."),
+            "opponent" if text == "left" => self.notice("Your opponent lost the connection."),
+            _ => {},
+        }
+        Ok(false)
+    }
+
+    // Waits for the next message, and connects again if the connection is lost
+    fn wait(&self) -> Result<(), String> {
+        match self.read() {
+            Ok(message) => self.handle(&message).map(|_| ()),
+            Err(_) => self.reconnect(),
+        }
+    }
+
+    // Puts the next turn of the server on the grid, if the grid doesn't have it yet
+    fn apply(&self, grid: &mut Grid) -> Result<bool, String> {
+        let turns = self.turns.borrow();
+        let applied = self.applied.get();
+        match turns.get(applied) {
+            Some(turn) => {
+                let stones: Vec<&str> = turn.split_whitespace().collect();
+                *grid = parse_turn(grid, &stones)
+                    .map_err(|error| {
+                        format!("The turn '{}' of the server doesn't fit: {}", turn, error)
+                    })?;
+                self.applied.set(applied + 1);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    // Sends the turn the local player made on their copy of the grid until the server takes it
+    fn play(&self, grid: &mut Grid, player: &dyn Player, side: PlayerId) -> Result<(), String> {
+        let mut pending: Option<String> = None;
+        loop {
+            if self.apply(grid)? {
+                return Ok(());
+            }
+            let turn = match pending.take() {
+                Some(turn) => turn,
+                None => match new_stones(grid, player, side) {
+                    Some(turn) => turn,
+                    None => continue,
+                },
+            };
+            self.refused.set(false);
+            if self.send(&format!("play {}", turn)).is_err() {
+                self.reconnect()?;
+                pending = Some(turn);
+                continue;
+            }
+            // the turn comes back from the server, or the error that it was refused
+            let (turns, connections) = (self.turns.borrow().len(), self.connections.get());
+            while self.turns.borrow().len() == turns && !self.refused.get()
+                && self.connections.get() == connections {
+                self.wait()?;
+            }
+            if self.turns.borrow().len() == turns && !self.refused.get() {
+                // the connection was lost on the way and the server doesn't have the turn
+                pending = Some(turn);
+            }
+        }
+    }
+
+    // Waits for the turn of the opponent
+    fn receive(&self, grid: &mut Grid) -> Result<(), String> {
+        while !self.apply(grid)? {
+            self.wait()?;
+        }
+        Ok(())
+    }
+}
+
+// The stones of a turn the player makes on a copy of the grid. Nothing if they made none,
+// because they asked to save, which the server takes care of.
+fn new_stones(grid: &Grid, player: &dyn Player, side: PlayerId) -> Option<String> {
+    let mut copy = grid.clone();
+    player.make_turn_as(&mut copy, side);
+    let stones: Vec<String> = (0 .. copy.cell_count())
+        .map(|index| copy.coord_of(index))
+        .filter(|coord| *grid.get_cell_at(coord) == CellState::Unset)
+        .filter_map(|coord| match *copy.get_cell_at(&coord) {
+            CellState::Set(symbol) => Some(write_stone(&Move::at(coord, side, symbol))),
+            CellState::Unset | CellState::Blocked => None,
+        })
+        .collect();
+    if stones.is_empty() {
+        if player.save_request().is_some() {
+            let kept = "The server keeps the game, come back to it with --rejoin.".to_string();
+            player.saved(&Err(kept));
+        }
+        None
+    } else {
+        Some(stones.join(" "))
+    }
+}
+
+// One side of a game on a server. The local side wraps the player at this end and sends
+// their turns, the other side waits for the turns of the opponent.
+pub struct NetworkPlayer<'a> {
+    connection: &'a Connection,
+    local: Option<Box<dyn Player + 'a>>,
+    forfeit: RefCell<Option<String>>,
+}
+
+impl<'a> NetworkPlayer<'a> {
+    pub fn local(connection: &'a Connection, player: Box<dyn Player + 'a>) -> NetworkPlayer<'a> {
+        NetworkPlayer {
+            connection,
+            local: Some(player),
+            forfeit: RefCell::new(None),
+        }
+    }
+
+    pub fn remote(connection: &'a Connection) -> NetworkPlayer<'a> {
+        NetworkPlayer {
+            connection,
+            local: None,
+            forfeit: RefCell::new(None),
+        }
+    }
+}
+
+impl<'a> Player for NetworkPlayer<'a> {
+    fn make_turn (&self, grid: &mut Grid) {
+        let side = grid.to_move();
+        self.make_turn_as(grid, side);
+    }
+
+    fn make_turn_as (&self, grid: &mut Grid, side: PlayerId) {
+        if self.forfeit.borrow().is_some() {
+            return;
+        }
+        let result = match self.local {
+            Some(ref player) => self.connection.play(grid, &**player, side),
+            None => self.connection.receive(grid),
+        };
+        if let Err(reason) = result {
+            *self.forfeit.borrow_mut() = Some(reason);
+        }
+    }
+
+    // Games on a server are played without an opening protocol
+    fn choose_opening (&self, _grid: &Grid, _side: PlayerId,
+                       _options: &[OpeningChoice]) -> OpeningChoice {
+        OpeningChoice::Keep
+    }
+
+    fn evaluation (&self) -> Option<i32> {
+        self.local.as_ref().and_then(|player| player.evaluation())
+    }
+
+    fn name (&self) -> &str {
+        match self.local {
+            Some(ref player) => player.name(),
+            None => "Remote",
+        }
+    }
+
+    fn forfeit (&self) -> Option<String> {
+        self.forfeit.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{Shutdown, TcpListener};
+    use ::game::GameState;
+    use ::game::opening::Opening;
+    use ::play::Game;
+    use ::player::ki::KiPlayer;
+    use ::server::serve;
+
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, Duration::from_secs(0)));
+        address
+    }
+
+    // Plays the game of this client to its end with the ki for the local side
+    fn play(connection: &Connection, cut: bool) -> (GameState, Grid) {
+        let side = connection.side();
+        let ki = KiPlayer::new(side.0);
+        let local = NetworkPlayer::local(connection, Box::new(ki));
+        let remote = NetworkPlayer::remote(connection);
+        let players: [&dyn Player; 2] = if side == PlayerId(1) {
+            [&local, &remote]
+        } else {
+            [&remote, &local]
+        };
+        let mut game = Game::new(connection.start(), players, Opening::Free);
+        if cut {
+            // the connection breaks before the first turn
+            connection.stream.borrow().as_ref().unwrap().1.shutdown(Shutdown::Both).unwrap();
+        }
+        let state = game.play().unwrap();
+        (state, game.grid.clone())
+    }
+
+    #[test]
+    fn test_two_clients_play_a_game() {
+        let address = start_server();
+        let first = Connection::join(&address, "game", &Grid::new(3, 3, 3), false).unwrap();
+        assert_eq!(PlayerId(1), first.side());
+        let other = address.clone();
+        let second = thread::spawn(move || {
+            let second = Connection::join(&other, "game", &Grid::new(4, 4, 4), false).unwrap();
+            assert_eq!(PlayerId(2), second.side());
+            // the first player started the game, so it is the one on 3x3
+            assert_eq!("3x3 3 standard 3/3/3 1", to_notation(&second.start()));
+            play(&second, false)
+        });
+        let (state, grid) = play(&first, false);
+        let (other_state, other_grid) = second.join().unwrap();
+        assert_eq!(GameState::Draw, state);
+        assert_eq!(state, other_state);
+        assert_eq!(to_notation(&grid), to_notation(&other_grid));
+    }
+
+    #[test]
+    fn test_reconnect_restores_the_game() {
+        let address = start_server();
+        let first = Connection::join(&address, "game", &Grid::new(3, 3, 3), false).unwrap();
+        let other = address.clone();
+        let second = thread::spawn(move || {
+            let second = Connection::join(&other, "game", &Grid::new(3, 3, 3), false).unwrap();
+            play(&second, true)
+        });
+        let (state, grid) = play(&first, false);
+        let (other_state, other_grid) = second.join().unwrap();
+        assert_eq!(state, other_state);
+        assert_eq!(to_notation(&grid), to_notation(&other_grid));
+    }
+
+    #[test]
+    fn test_rejoin_with_the_token() {
+        let address = start_server();
+        let first = Connection::join(&address, "game", &Grid::new(3, 3, 3), false).unwrap();
+        let token = first.token();
+        drop(first);
+        let again = Connection::rejoin(&address, "game", &token, false).unwrap();
+        assert_eq!(PlayerId(1), again.side());
+        match Connection::rejoin(&address, "game", "0000", false) {
+            Err(error) => assert_eq!("No player of 'game' has this token.", error),
+            Ok(_) => panic!("the token was taken"),
+        }
+    }
+}
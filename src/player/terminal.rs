@@ -1,56 +1,223 @@
+use std::cell::RefCell;
 use std::io;
+use std::process;
 use super::Player;
-use ::game::PlayerId;
+use ::game::{PlayerId, GameState, CellState, Move, Coord, X, O};
+use ::game::algebraic;
 use ::game::grid::Grid;
+use ::game::grid_observer::check_winner;
+use ::game::opening::OpeningChoice;
 
 pub struct TerminalPlayer {
     id: u32,
+    // the file the player asked to save the game in
+    save: RefCell<Option<String>>,
 }
 
 impl TerminalPlayer {
     pub fn new(id: u32) -> TerminalPlayer {
         println!("Welcome, Player {}!", id);
         println!("This is a simple implementation of the classical game 'Tic-Tac-Toe'.");
-        println!("If you are asked for input, you should enter it in the form 'row column'");
-        println!("Row and column numeration starts at 0.");
-        println!("Example: To set the cell at row 0 and column 2, enter '0 2'");
+        println!("If you are asked for input, enter the cell with a letter for the column and");
+        println!("the number of the row, counted from 1 at the top.");
+        println!("Example: To set the third cell of the first row, enter 'c1'");
+        println!("The form 'row column' counted from 0 works as well, 'c1' is then '0 2'.");
+        println!("If you may choose your symbol, add it after the cell: 'c1 X'");
+        println!("To stop and continue later, enter 'save' or 'save <file>' at the start of a \
+                  turn.");
 
         TerminalPlayer {
-            id: id,
+            id,
+            save: RefCell::new(None),
         }
     }
+
+    fn parse_move(&self, input: &str, grid: &Grid, side: PlayerId) -> Result<Move, String> {
+        let split: Vec<_> = input.split_whitespace().collect();
+        // a cell like 'c3' is one value, the numeric form has one for every dimension
+        let algebraic = split.first()
+            .is_some_and(|first| first.chars().any(|c| c.is_ascii_alphabetic()));
+        let dimensions = if algebraic { 1 } else { grid.dimensions().len() };
+        let expected = if grid.variant.has_symbol_choice() { dimensions + 1 } else { dimensions };
+        if split.len() != expected {
+            return Err(format!("Expected {} values, got {}.", expected, split.len()));
+        }
+
+        let coord = if algebraic {
+            algebraic::parse(split[0])?
+        } else {
+            let mut values = Vec::new();
+            for &value in &split[.. dimensions] {
+                values.push(value.parse()
+                    .map_err(|_| format!("'{}' is not a number.", value))?);
+            }
+            Coord(values)
+        };
+        if coord.0.len() != grid.dimensions().len()
+            || coord.0.iter().zip(grid.dimensions()).any(|(&value, &size)| value >= size) {
+            return Err(format!("{} is not on the board.", split[.. dimensions].join(" ")));
+        }
+        if *grid.get_cell_at(&coord) == CellState::Blocked {
+            return Err(format!("Cell {} is blocked.", algebraic::format(&coord)));
+        }
+
+        let symbol = if grid.variant.has_symbol_choice() {
+            match split[dimensions] {
+                "X" | "x" => X,
+                "O" | "o" => O,
+                other => return Err(format!("'{}' is not a symbol, choose X or O.", other)),
+            }
+        } else {
+            side
+        };
+        Ok(Move::at(coord, side, symbol))
+    }
 }
 
 impl Player for TerminalPlayer {
     fn make_turn (&self, grid: &mut Grid) {
+        self.make_turn_as(grid, PlayerId(self.id));
+    }
+
+    fn make_turn_as (&self, grid: &mut Grid, side: PlayerId) {
+        let turn = grid.turns_played();
+        let stones = grid.stones_left();
+        while grid.turns_played() == turn && check_winner(grid) == GameState::Mid {
+            if self.place_stone(grid, side, grid.stones_left() == stones) {
+                return;
+            }
+        }
+    }
+
+    fn choose_opening (&self, grid: &Grid, side: PlayerId,
+                       options: &[OpeningChoice]) -> OpeningChoice {
+        println!("\nCurrent state:");
+        grid.pretty_print();
+        println!("Player {}, you play side {}.", self.id, side.0);
+        println!("Enter 'keep' to stay with side {}, 'swap' to take side {} instead{}.",
+                 side.0, side.opponent().0,
+                 if options.contains(&OpeningChoice::PlaceTwo) {
+                     " or 'two' to place two more stones and let the other player choose"
+                 } else {
+                     ""
+                 });
+        loop {
+            let input = read_input();
+            let choice = match input.trim() {
+                "keep" => OpeningChoice::Keep,
+                "swap" => OpeningChoice::Swap,
+                "two" => OpeningChoice::PlaceTwo,
+                other => {
+                    println!("'{}' is not a choice. Try again!", other);
+                    continue;
+                }
+            };
+            if options.contains(&choice) {
+                return choice;
+            }
+            println!("You can't choose '{}' now. Try again!", input.trim());
+        }
+    }
+
+    fn name (&self) -> &str {
+        "Human"
+    }
+
+    fn save_request (&self) -> Option<String> {
+        self.save.borrow_mut().take()
+    }
+
+    fn saved (&self, result: &Result<String, String>) {
+        match *result {
+            Ok(ref path) => {
+                println!("The game is saved in {}. Continue it with --resume {}.", path, path);
+            },
+            Err(ref error) => println!("{}", error),
+        }
+    }
+}
+
+// Reads a line, and leaves the game if there is no more input
+fn read_input() -> String {
+    let mut input = String::new();
+    let read = io::stdin()
+        .read_line(&mut input)
+        .expect("failed to read line");
+    if read == 0 {
+        println!("No more input, leaving the game.");
+        process::exit(0);
+    }
+    input
+}
+
+impl TerminalPlayer {
+    // Places one stone, or asks for the game to be saved if that is possible and gives back
+    // true then
+    fn place_stone(&self, grid: &mut Grid, side: PlayerId, may_save: bool) -> bool {
         loop {
             println!("\nCurrent state:");
             grid.pretty_print();
+            if grid.stones_left() > 1 {
+                println!("Player {}, you have {} stones left in this turn.",
+                         self.id, grid.stones_left());
+            }
+            if grid.dimensions().len() > 2 {
+                println!("Enter the layers before the cell, like '2:c3'.");
+            }
+            if side != PlayerId(self.id) {
+                println!("Player {}, you place the stones of side {}.", self.id, side.0);
+            }
             println!("Player {}, what is your turn?", self.id);
-            let mut input = String::new();
-            io::stdin()
-                .read_line(&mut input)
-                .ok()
-                .expect("failed to read line");
+            let input = read_input();
+            let mut words = input.split_whitespace();
+            if words.next() == Some("save") {
+                if may_save {
+                    *self.save.borrow_mut() = Some(words.collect::<Vec<_>>().join(" "));
+                    return true;
+                }
+                println!("The game can only be saved before the first stone of a turn.");
+                continue;
+            }
 
-            let split: Vec<_> = input.split_whitespace().collect();
-            assert_eq!(2, split.len());
+            match self.parse_move(&input, grid, side) {
+                Err(message) => println!("{} Try again!", message),
+                Ok(mov) => {
+                    if !grid.make_move(mov.clone()) {
+                        println!("Cell {} is already set! Try again!",
+                                 algebraic::format(&mov.coord));
+                    } else {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+}
 
-            let row: usize = split[0]
-                .parse()
-                .ok()
-                .expect("failed to parse the input");
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::Variant;
 
-            let column: usize = split[1]
-                .parse()
-                .ok()
-                .expect("failed to parse the input");
+    #[test]
+    fn test_parse_move() {
+        let player = TerminalPlayer { id: 1, save: RefCell::new(None) };
+        let grid = Grid::new(3, 3, 3);
+        let expected = Move::at(Coord::new(0, 2), PlayerId(1), PlayerId(1));
+        assert_eq!(Ok(expected.clone()), player.parse_move("c1", &grid, PlayerId(1)));
+        assert_eq!(Ok(expected), player.parse_move("0 2", &grid, PlayerId(1)));
+        assert!(player.parse_move("d1", &grid, PlayerId(1)).is_err());
+        assert!(player.parse_move("0 3", &grid, PlayerId(1)).is_err());
+        assert!(player.parse_move("2:c1", &grid, PlayerId(1)).is_err());
+    }
 
-            if !grid.set_cell(row, column, PlayerId(self.id)) {
-                println!("Cell {} {} is already set! Try again!",row,column);
-            } else {
-                break;
-            }
-        }
+    #[test]
+    fn test_parse_move_with_layers_and_symbol() {
+        let player = TerminalPlayer { id: 1, save: RefCell::new(None) };
+        let grid = Grid::with_dimensions(vec![3, 3, 3], 3);
+        assert_eq!(Ok(Coord(vec![1, 2, 0])),
+                   player.parse_move("2:a3", &grid, PlayerId(1)).map(|mov| mov.coord));
+        let wild = Grid::with_variant(3, 3, 3, Variant::Wild);
+        assert_eq!(Ok(O), player.parse_move("b2 o", &wild, PlayerId(1)).map(|mov| mov.symbol));
     }
 }
@@ -0,0 +1,103 @@
+use std::cell::Cell;
+use std::process;
+use super::Player;
+use ::game::{PlayerId, GameState, CellState, Move};
+use ::game::algebraic;
+use ::game::grid::Grid;
+use ::game::grid_observer::check_winner;
+use ::game::opening::OpeningChoice;
+use ::tui::{Action, Screen};
+
+// A player at the keyboard of the full-screen view
+pub struct TuiPlayer<'a> {
+    id: u32,
+    screen: &'a Screen,
+    saving: Cell<bool>,
+}
+
+impl<'a> TuiPlayer<'a> {
+    pub fn new(id: u32, screen: &'a Screen) -> TuiPlayer<'a> {
+        TuiPlayer {
+            id,
+            screen,
+            saving: Cell::new(false),
+        }
+    }
+
+    // Gives the terminal back and ends the program
+    fn leave(&self) -> ! {
+        self.screen.close();
+        println!("Player {} left the game.", self.id);
+        process::exit(0);
+    }
+}
+
+impl<'a> Player for TuiPlayer<'a> {
+    fn make_turn (&self, grid: &mut Grid) {
+        self.make_turn_as(grid, PlayerId(self.id));
+    }
+
+    fn make_turn_as (&self, grid: &mut Grid, side: PlayerId) {
+        let turn = grid.turns_played();
+        let stones = grid.stones_left();
+        self.screen.set_status(&format!("Player {}, your turn with side {}.", self.id, side.0));
+        while grid.turns_played() == turn && check_winner(grid) == GameState::Mid {
+            match self.screen.select(grid, side) {
+                Ok(Action::Place(coord, symbol)) => {
+                    let status = match *grid.get_cell_at(&coord) {
+                        CellState::Blocked => {
+                            format!("Cell {} is blocked.", algebraic::format(&coord))
+                        },
+                        CellState::Set(_) => {
+                            format!("Cell {} is already set.", algebraic::format(&coord))
+                        },
+                        CellState::Unset => {
+                            grid.make_move(Move::at(coord, side, symbol));
+                            format!("Player {}, your turn with side {}.", self.id, side.0)
+                        },
+                    };
+                    self.screen.set_status(&status);
+                },
+                Ok(Action::Save) if grid.stones_left() == stones => {
+                    self.saving.set(true);
+                    return;
+                },
+                Ok(Action::Save) => {
+                    let refused = "The game can only be saved before the first stone of a turn.";
+                    self.screen.set_status(refused);
+                },
+                Ok(Action::Quit) | Err(_) => self.leave(),
+            }
+        }
+    }
+
+    fn choose_opening (&self, grid: &Grid, side: PlayerId,
+                       options: &[OpeningChoice]) -> OpeningChoice {
+        let two = options.contains(&OpeningChoice::PlaceTwo);
+        let place_two = if two { ", t places two more stones" } else { "" };
+        let question = format!("Player {}: k keeps side {}, s swaps to side {}{}.", self.id, side.0,
+                               side.opponent().0, place_two);
+        let answers: &[char] = if two { &['k', 's', 't'] } else { &['k', 's'] };
+        match self.screen.ask(grid, &question, answers) {
+            Ok(Some('k')) => OpeningChoice::Keep,
+            Ok(Some('s')) => OpeningChoice::Swap,
+            Ok(Some(_)) => OpeningChoice::PlaceTwo,
+            Ok(None) | Err(_) => self.leave(),
+        }
+    }
+
+    fn name (&self) -> &str {
+        "Human"
+    }
+
+    fn save_request (&self) -> Option<String> {
+        if self.saving.replace(false) { Some(String::new()) } else { None }
+    }
+
+    fn saved (&self, result: &Result<String, String>) {
+        match *result {
+            Ok(ref path) => self.screen.set_status(&format!("Saved in {}.", path)),
+            Err(ref error) => self.screen.set_status(error),
+        }
+    }
+}
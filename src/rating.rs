@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+use ::game::grid::Grid;
+use ::game::notation::to_notation;
+use ::game::record::Record;
+
+// Where the ratings are kept, if no other file is given
+pub const DEFAULT_PATH: &str = "games/ratings.txt";
+
+const INITIAL_RATING: f64 = 1500.0;
+// How far an Elo rating moves after a single game
+const ELO_K: f64 = 32.0;
+// The Glicko-2 deviation and volatility of a new player, and how much the volatility may change
+const INITIAL_DEVIATION: f64 = 350.0;
+const INITIAL_VOLATILITY: f64 = 0.06;
+const TAU: f64 = 0.5;
+// Glicko-2 works on its own scale, this converts it to the one of Elo
+const GLICKO_SCALE: f64 = 173.7178;
+
+// The ways players are rated. Both ratings are kept for every player, the system chooses the one
+// leaderboards are ordered by.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+pub enum System {
+    Elo,
+    // Glicko-2, where every game is a rating period of its own
+    Glicko2,
+}
+
+impl System {
+    pub fn parse(name: &str) -> Result<System, String> {
+        match name {
+            "elo" => Ok(System::Elo),
+            "glicko2" | "glicko-2" => Ok(System::Glicko2),
+            _ => Err(format!("'{}' is not a rating system, use elo or glicko2.", name)),
+        }
+    }
+}
+
+// How strong a player is, in both systems, and how their games went
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Rating {
+    pub elo: f64,
+    pub glicko: f64,
+    // the uncertainty of the Glicko-2 rating, the strength is likely within twice of it
+    pub deviation: f64,
+    pub volatility: f64,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Rating {
+        Rating {
+            elo: INITIAL_RATING,
+            glicko: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+            volatility: INITIAL_VOLATILITY,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+        }
+    }
+}
+
+impl Rating {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn value(&self, system: System) -> f64 {
+        match system {
+            System::Elo => self.elo,
+            System::Glicko2 => self.glicko,
+        }
+    }
+
+    // The rating after a game with `score` points against `opponent`, 1 for a win and 1/2 for
+    // a draw
+    fn after(&self, opponent: &Rating, score: f64) -> Rating {
+        let expected = 1.0 / (1.0 + 10f64.powf((opponent.elo - self.elo) / 400.0));
+        let (glicko, deviation, volatility) = glicko2(self, &[(opponent, score)]);
+        Rating {
+            elo: self.elo + ELO_K * (score - expected),
+            glicko,
+            deviation,
+            volatility,
+            wins: self.wins + (score == 1.0) as u32,
+            draws: self.draws + (score == 0.5) as u32,
+            losses: self.losses + (score == 0.0) as u32,
+        }
+    }
+}
+
+// The Glicko-2 rating, deviation and volatility after a rating period with these games, as
+// described in Mark Glickman's "Example of the Glicko-2 system"
+fn glicko2(rating: &Rating, games: &[(&Rating, f64)]) -> (f64, f64, f64) {
+    let mu = (rating.glicko - INITIAL_RATING) / GLICKO_SCALE;
+    let phi = rating.deviation / GLICKO_SCALE;
+    let sigma = rating.volatility;
+    let g = |phi: f64| 1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt();
+    let opponents: Vec<(f64, f64, f64)> = games.iter()
+        .map(|(opponent, score)| {
+            let phi = opponent.deviation / GLICKO_SCALE;
+            let mu_j = (opponent.glicko - INITIAL_RATING) / GLICKO_SCALE;
+            let expected = 1.0 / (1.0 + (-g(phi) * (mu - mu_j)).exp());
+            (g(phi), expected, *score)
+        })
+        .collect();
+    let v = 1.0 / opponents.iter()
+        .map(|(g, expected, _)| g * g * expected * (1.0 - expected))
+        .sum::<f64>();
+    let improvement: f64 = opponents.iter()
+        .map(|(g, expected, score)| g * (score - expected))
+        .sum();
+    let delta = v * improvement;
+
+    // the new volatility is where f is 0, found with the Illinois algorithm
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+            - (x - a) / (TAU * TAU)
+    };
+    let mut low = a;
+    let mut high = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+    let (mut f_low, mut f_high) = (f(low), f(high));
+    while (high - low).abs() > 0.000001 {
+        let middle = low + (low - high) * f_low / (f_high - f_low);
+        let f_middle = f(middle);
+        if f_middle * f_high <= 0.0 {
+            low = high;
+            f_low = f_high;
+        } else {
+            f_low /= 2.0;
+        }
+        high = middle;
+        f_high = f_middle;
+    }
+    let volatility = (low / 2.0).exp();
+
+    let phi_star = (phi * phi + volatility * volatility).sqrt();
+    let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let mu = mu + phi * phi * improvement;
+    (GLICKO_SCALE * mu + INITIAL_RATING, GLICKO_SCALE * phi, volatility)
+}
+
+// The pool of ratings a game counts for: games on other boards or with other rules test other
+// skills. It is the start of the notation, like '15x15 5 standard', with the rules.
+pub fn pool(grid: &Grid) -> String {
+    let notation = to_notation(grid);
+    let words: Vec<&str> = notation.split_whitespace().collect();
+    let mut pool = words[.. 3].join(" ");
+    // a handicap is part of the game, not of the rules
+    let rules: Vec<&str> = words.get(5).map_or(Vec::new(), |rules| rules.split(',').collect())
+        .into_iter()
+        .filter(|rule| !rule.starts_with("handicap="))
+        .collect();
+    if !rules.is_empty() {
+        pool += " ";
+        pool += &rules.join(",");
+    }
+    pool
+}
+
+// The points of the first side in a result of a record, or None if the game isn't over
+pub fn score(result: &str) -> Option<f64> {
+    match result {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => {
+            let (first, second) = result.split_once(':')?;
+            let (first, second): (u32, u32) = (first.parse().ok()?, second.parse().ok()?);
+            Some(if first > second { 1.0 } else if first < second { 0.0 } else { 0.5 })
+        },
+    }
+}
+
+// The ratings of all players by pool, kept in a file with a line for every player of a pool:
+//
+//     # pool  player  elo  glicko2  deviation  volatility  wins  draws  losses
+//     3x3 3 standard  ki:hard  1516.0  1662.3  290.3  0.060000  1  0  0
+//
+// The fields are separated by tabs, so that names can have spaces, like engine commands.
+#[derive(Default)]
+pub struct Ratings {
+    pub pools: BTreeMap<String, BTreeMap<String, Rating>>,
+}
+
+const HEADER: &str = "# pool\tplayer\telo\tglicko2\tdeviation\tvolatility\twins\tdraws\tlosses";
+
+impl Ratings {
+    // The ratings in the file, no ratings if there is no file yet
+    pub fn load(path: &str) -> Result<Ratings, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ratings::parse(&text)
+                .map_err(|error| format!("The ratings in {} are invalid: {}", path, error)),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(Ratings::default()),
+            Err(error) => Err(format!("Can't read the ratings in {}: {}", path, error)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Ratings, String> {
+        let mut ratings = Ratings::default();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let invalid = || {
+                format!("line {} isn't a pool, a player and seven numbers", number + 1)
+            };
+            if fields.len() != 9 {
+                return Err(invalid());
+            }
+            let numbers: Vec<f64> = fields[2 ..].iter()
+                .map(|field| {
+                    field.parse().ok().filter(|value: &f64| value.is_finite()).ok_or_else(invalid)
+                })
+                .collect::<Result<Vec<f64>, String>>()?;
+            let rating = Rating {
+                elo: numbers[0],
+                glicko: numbers[1],
+                deviation: numbers[2],
+                volatility: numbers[3],
+                wins: numbers[4] as u32,
+                draws: numbers[5] as u32,
+                losses: numbers[6] as u32,
+            };
+            ratings.pools.entry(fields[0].to_string()).or_default()
+                .insert(fields[1].to_string(), rating);
+        }
+        Ok(ratings)
+    }
+
+    pub fn write(&self) -> String {
+        let mut text = HEADER.to_string() + "\n";
+        for (pool, players) in &self.pools {
+            for (name, rating) in players {
+                text += &format!("{}\t{}\t{:.1}\t{:.1}\t{:.1}\t{:.6}\t{}\t{}\t{}\n", pool, name,
+                                 rating.elo, rating.glicko, rating.deviation, rating.volatility,
+                                 rating.wins, rating.draws, rating.losses);
+            }
+        }
+        text
+    }
+
+    // Writes the ratings to a file, and creates the directory it is in if needed
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        match Path::new(path).parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => fs::create_dir_all(directory)?,
+            _ => {},
+        }
+        fs::write(path, self.write())
+    }
+
+    // The rating of a player in a pool, the one of a new player if they haven't played there
+    pub fn get(&self, pool: &str, name: &str) -> Rating {
+        self.pools.get(pool).and_then(|players| players.get(name)).cloned().unwrap_or_default()
+    }
+
+    // Rates a game between two players in which the first got `score` points. A player can't
+    // learn anything about themselves by playing against themselves, such games are left out.
+    pub fn add_game(&mut self, pool: &str, names: [&str; 2], score: f64) {
+        if names[0] == names[1] {
+            return;
+        }
+        let (first, second) = (self.get(pool, names[0]), self.get(pool, names[1]));
+        let players = self.pools.entry(pool.to_string()).or_default();
+        players.insert(names[0].to_string(), first.after(&second, score));
+        players.insert(names[1].to_string(), second.after(&first, 1.0 - score));
+    }
+
+    // Rates the game of a record with its players, rules and result. Gives back the pool it
+    // counted for.
+    pub fn add_record(&mut self, record: &Record) -> Result<String, String> {
+        let names = [record.tag("Player1").unwrap_or("?"), record.tag("Player2").unwrap_or("?")];
+        let score = score(record.tag("Result").unwrap_or("*")).ok_or("The game isn't over.")?;
+        let pool = pool(&record.start()?);
+        self.add_game(&pool, names, score);
+        Ok(pool)
+    }
+
+    // The players of every pool from the best to the worst:
+    //
+    //     3x3 3 standard
+    //     #   Player   Elo   Glicko-2     W   D   L
+    //     1   ki:hard  1516  1662 ± 581   1   0   0
+    //     2   ki:easy  1484  1338 ± 581   0   0   1
+    pub fn leaderboard(&self, system: System) -> String {
+        let mut boards = Vec::new();
+        for (pool, players) in &self.pools {
+            let mut ranking: Vec<(&String, &Rating)> = players.iter().collect();
+            ranking.sort_by(|a, b| {
+                b.1.value(system).total_cmp(&a.1.value(system)).then(a.0.cmp(b.0))
+            });
+            let width = players.keys().map(|name| name.chars().count()).max().unwrap_or(0)
+                .max("Player".len());
+            let head = format!("{:<3} {:<width$}  {:<5} {:<12} {:<3} {:<3} {}", "#", "Player",
+                               "Elo", "Glicko-2", "W", "D", "L", width = width);
+            let mut lines = vec![pool.clone(), head];
+            for (place, (name, rating)) in ranking.iter().enumerate() {
+                let glicko = format!("{:.0} ± {:.0}", rating.glicko, 2.0 * rating.deviation);
+                lines.push(format!("{:<3} {:<width$}  {:<5.0} {:<12} {:<3} {:<3} {}", place + 1,
+                                   name, rating.elo, glicko, rating.wins, rating.draws,
+                                   rating.losses, width = width));
+            }
+            boards.push(lines.join("\n"));
+        }
+        boards.join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::notation::parse_notation;
+
+    fn rating(glicko: f64, deviation: f64) -> Rating {
+        Rating { glicko, deviation, ..Rating::default() }
+    }
+
+    #[test]
+    fn test_glicko2_example() {
+        // the example in Glickman's description
+        let player = rating(1500.0, 200.0);
+        let opponents = [rating(1400.0, 30.0), rating(1550.0, 100.0), rating(1700.0, 300.0)];
+        let results = [(&opponents[0], 1.0), (&opponents[1], 0.0), (&opponents[2], 0.0)];
+        let (glicko, deviation, volatility) = glicko2(&player, &results);
+        assert!((glicko - 1464.06).abs() < 0.01, "{}", glicko);
+        assert!((deviation - 151.52).abs() < 0.01, "{}", deviation);
+        assert!((volatility - 0.05999).abs() < 0.00001, "{}", volatility);
+    }
+
+    #[test]
+    fn test_games_change_the_ratings() {
+        let mut ratings = Ratings::default();
+        ratings.add_game("3x3 3 standard", ["Alice", "ki:easy"], 1.0);
+        let alice = ratings.get("3x3 3 standard", "Alice");
+        let ki = ratings.get("3x3 3 standard", "ki:easy");
+        assert_eq!((1516.0, 1484.0), (alice.elo, ki.elo));
+        assert!(alice.glicko > 1500.0 && ki.glicko < 1500.0 && alice.deviation < INITIAL_DEVIATION);
+        assert_eq!((1, 0, 1), (alice.wins, ki.wins, ki.losses));
+        // other boards have their own ratings
+        assert_eq!(Rating::default(), ratings.get("15x15 5 standard", "Alice"));
+        // a draw between equal players changes nothing in Elo
+        ratings.add_game("15x15 5 standard", ["Alice", "Bob"], 0.5);
+        assert_eq!(1500.0, ratings.get("15x15 5 standard", "Bob").elo);
+        ratings.add_game("15x15 5 standard", ["Bob", "Bob"], 1.0);
+        assert_eq!(1, ratings.get("15x15 5 standard", "Bob").games());
+    }
+
+    #[test]
+    fn test_ratings_from_records() {
+        let start = "15x15 5 standard 15/15/15/15/15/15/15/15/15/15/15/15/15/15/15 1 wrap";
+        let mut record = Record::new(&parse_notation(start).unwrap());
+        record.set_tag("Player1", "python3 engine.py");
+        record.set_tag("Player2", "ki:hard");
+        assert_eq!(Err("The game isn't over.".to_string()), Ratings::default().add_record(&record));
+        record.set_tag("Result", "0-1");
+        let mut ratings = Ratings::default();
+        assert_eq!(Ok("15x15 5 standard wrap".to_string()), ratings.add_record(&record));
+        assert_eq!(1, ratings.get("15x15 5 standard wrap", "ki:hard").wins);
+        assert_eq!(Some(0.0), score("2:5"));
+    }
+
+    #[test]
+    fn test_write_and_parse() {
+        let mut ratings = Ratings::default();
+        ratings.add_game("3x3 3 standard", ["python3 my engine.py", "ki"], 0.5);
+        let text = ratings.write();
+        assert!(text.starts_with(HEADER));
+        // the file has the ratings as precise as they are shown
+        assert_eq!(text, Ratings::parse(&text).unwrap().write());
+        assert_eq!(Some("line 1 isn't a pool, a player and seven numbers".to_string()),
+                   Ratings::parse("3x3 3 standard\tki\t1500").err());
+        let line = text.lines().nth(1).unwrap();
+        for number in ["NaN", "inf", "-inf"] {
+            // the Elo of the first player
+            let mut fields: Vec<&str> = line.split('\t').collect();
+            fields[2] = number;
+            assert_eq!(Some("line 1 isn't a pool, a player and seven numbers".to_string()),
+                       Ratings::parse(&fields.join("\t")).err());
+        }
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let mut ratings = Ratings::default();
+        ratings.add_game("3x3 3 standard", ["ki:easy", "ki:hard"], 0.0);
+        assert_eq!("3x3 3 standard\n\
+                    #   Player   Elo   Glicko-2     W   D   L\n\
+                    1   ki:hard  1516  1662 ± 581   1   0   0\n\
+                    2   ki:easy  1484  1338 ± 581   0   0   1",
+                   ratings.leaderboard(System::Elo));
+    }
+}
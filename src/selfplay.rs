@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use ::game::{GameState, PlayerId};
+use ::game::grid::Grid;
+use ::game::grid_observer::check_winner;
+use ::game::notation::to_notation;
+use ::game::opening::Opening;
+use ::game::record::{result_text, write_stone};
+use ::play::Game;
+use ::player::ki::possible_turns;
+use ::rating::score;
+use ::tournament::new_player;
+
+// Plays many games without anyone watching and writes every position of them as a line of
+// JSON, for training and testing players (wrapped here):
+//
+//     {"game":0,"ply":2,"position":"3x3 3 standard 1x1/3/3 2","player":"ki","side":2,
+//      "turn":["b2"],"evaluation":0,"result":"1/2-1/2","outcome":0}
+//
+// `position` is before `turn`, the stones `player` placed for `side` with the `evaluation` of
+// its search, if it tells one. `outcome` is the result for the side to move: 1 for a win, 0
+// for a draw and -1 for a loss. The lines come in the order of the games, whatever thread
+// played them, so the same seed gives the same data as long as the players don't depend on
+// the time they get.
+pub struct SelfPlay {
+    // the kinds of the players, as in a tournament. They take turns with the first side.
+    pub players: [String; 2],
+    pub games: usize,
+    pub threads: usize,
+    pub seed: u64,
+    // the turns played at random at the start of every game, so that the games differ
+    pub random_turns: usize,
+    pub start: Grid,
+    pub move_time: Duration,
+}
+
+// Random numbers that are the same for the same seed, from SplitMix64
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^ (value >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+impl SelfPlay {
+    // Plays all games and writes their positions to `output`. `progress` hears of the number of
+    // games written so far. Gives back the number of positions.
+    pub fn run(&self, output: &mut dyn Write,
+               progress: &mut dyn FnMut(usize)) -> Result<usize, String> {
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel::<(usize, Result<Vec<String>, String>)>();
+        thread::scope(|scope| {
+            for _ in 0 .. self.threads.max(1) {
+                let sender = sender.clone();
+                let (next, stop) = (&next, &stop);
+                scope.spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= self.games || sender.send((index, self.play(index))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            let written = self.write(receiver, output, progress);
+            // the other threads end after their current game
+            stop.store(true, Ordering::Relaxed);
+            written
+        })
+    }
+
+    // Writes the games in their order, holding back those that were done early
+    fn write(&self, games: mpsc::Receiver<(usize, Result<Vec<String>, String>)>,
+             output: &mut dyn Write, progress: &mut dyn FnMut(usize)) -> Result<usize, String> {
+        let mut waiting = BTreeMap::new();
+        let mut written = 0;
+        let mut positions = 0;
+        for (index, lines) in games {
+            waiting.insert(index, lines?);
+            while let Some(lines) = waiting.remove(&written) {
+                for line in &lines {
+                    writeln!(output, "{}", line)
+                        .map_err(|error| format!("Can't write the data: {}", error))?;
+                }
+                positions += lines.len();
+                written += 1;
+                progress(written);
+            }
+        }
+        output.flush().map_err(|error| format!("Can't write the data: {}", error))?;
+        Ok(positions)
+    }
+
+    // The lines of one game, which depends on nothing but its number and the seed
+    fn play(&self, index: usize) -> Result<Vec<String>, String> {
+        let mut random = Random(self.seed ^ (index as u64).wrapping_mul(0xd1b5_4a32_d192_ed03));
+        let start = self.random_start(&mut random);
+        // the players swap sides every game
+        let kinds = if index.is_multiple_of(2) {
+            [&self.players[0], &self.players[1]]
+        } else {
+            [&self.players[1], &self.players[0]]
+        };
+        let first = new_player(kinds[0], 1, self.move_time)?;
+        let second = new_player(kinds[1], 2, self.move_time)?;
+        let mut game = Game::new(start.clone(), [&*first, &*second], Opening::Free);
+        let state = game.play()?;
+        let result = result_text(&state);
+        let first_score = score(&result).unwrap_or(0.5);
+
+        let mut grid = start;
+        let mut lines = Vec::new();
+        for (ply, turn) in game.turns.iter().enumerate() {
+            let side = grid.to_move();
+            let stones: Vec<String> = turn.stones.iter()
+                .map(|stone| format!("\"{}\"", write_stone(stone)))
+                .collect();
+            let side_score = if side == PlayerId(1) { first_score } else { 1.0 - first_score };
+            let player = json_string(kinds[game.player_of(side)]);
+            let evaluation = turn.evaluation.map_or("null".to_string(), |score| score.to_string());
+            lines.push(format!("{{\"game\":{},\"ply\":{},\"position\":\"{}\",\"player\":{},\
+                                \"side\":{},\"turn\":[{}],\"evaluation\":{},\"result\":\"{}\",\
+                                \"outcome\":{}}}",
+                               index, ply, to_notation(&grid), player, side.0, stones.join(","),
+                               evaluation, result, (side_score * 2.0 - 1.0) as i32));
+            for stone in &turn.stones {
+                grid.make_move(stone.clone());
+            }
+        }
+        Ok(lines)
+    }
+
+    // The start with the random turns played. A game that ends during them is tried again.
+    fn random_start(&self, random: &mut Random) -> Grid {
+        for _ in 0 .. 100 {
+            let mut grid = self.start.clone();
+            for _ in 0 .. self.random_turns {
+                let turns = possible_turns(&grid, grid.to_move());
+                if turns.is_empty() || check_winner(&grid) != GameState::Mid {
+                    break;
+                }
+                for stone in turns[random.below(turns.len())].clone() {
+                    grid.make_move(stone);
+                }
+            }
+            if check_winner(&grid) == GameState::Mid {
+                return grid;
+            }
+        }
+        self.start.clone()
+    }
+}
+
+// Text as a JSON string, with quotes
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            character if (character as u32) < 0x20 => {
+                json += &format!("\\u{:04x}", character as u32);
+            },
+            character => json.push(character),
+        }
+    }
+    json + "\""
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn self_play(threads: usize, seed: u64) -> SelfPlay {
+        SelfPlay {
+            players: ["ki:easy".to_string(), "ki:depth=2".to_string()],
+            games: 6,
+            threads,
+            seed,
+            random_turns: 2,
+            start: Grid::new(4, 4, 3),
+            move_time: Duration::from_millis(100),
+        }
+    }
+
+    fn data(self_play: &SelfPlay) -> String {
+        let mut output = Vec::new();
+        let mut games = 0;
+        self_play.run(&mut output, &mut |written| games = written).unwrap();
+        assert_eq!(self_play.games, games);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_the_seed_decides_the_data() {
+        let generated = data(&self_play(1, 7));
+        assert_eq!(generated, data(&self_play(3, 7)));
+        assert_ne!(generated, data(&self_play(3, 8)));
+        let first = generated.lines().next().unwrap();
+        // the random turns aren't part of the data
+        assert!(first.starts_with("{\"game\":0,\"ply\":0,\"position\":\"4x4 3 standard "));
+        assert!(first.contains("\"player\":\"ki:easy\",\"side\":1,\"turn\":[\""));
+        let last = generated.lines().last().unwrap();
+        assert!(last.starts_with("{\"game\":5,") && last.contains("\"player\":\"ki:"));
+    }
+
+    #[test]
+    fn test_outcomes_are_for_the_side_to_move() {
+        let generated = data(&SelfPlay { random_turns: 0, games: 1, ..self_play(1, 1) });
+        assert!(generated.lines().count() >= 5);
+        for line in generated.lines() {
+            let first = match &line[line.find("\"result\"").unwrap() ..] {
+                result if result.starts_with("\"result\":\"1-0\"") => 1,
+                result if result.starts_with("\"result\":\"0-1\"") => -1,
+                _ => 0,
+            };
+            let outcome = if line.contains("\"side\":1,") { first } else { -first };
+            assert!(line.ends_with(&format!("\"outcome\":{}}}", outcome)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_json_strings() {
+        assert_eq!("\"python3 \\\"my engine\\\".py\\u0009\"",
+                   json_string("python3 \"my engine\".py\t"));
+    }
+}
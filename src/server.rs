@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use ::engine::parse_turn;
+use ::game::GameState;
+use ::game::grid::Grid;
+use ::game::grid_observer::check_winner;
+use ::game::notation::{parse_notation, to_notation};
+use ::game::record::{parse_stone, result_text, write_stone};
+use ::spectator::Relay;
+
+// The version of the protocol described in docs/network-protocol.md
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// How long a client may take to accept a line before its connection is closed
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// How long a game that isn't over stays after both players have left
+const ABANDONED_GAME_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+// The number of the next connection, so that a seat knows which connection holds it
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+
+// A game on the server, with the two seats players can take. A seat is kept when its player
+// loses the connection, the token gives it back to them.
+struct HostedGame {
+    start: Grid,
+    grid: Grid,
+    // every turn so far, the stones written like in a record
+    turns: Vec<String>,
+    tokens: [Option<String>; 2],
+    // the number and the outbox of the connection of each side while its player is there
+    clients: [Option<(u64, Sender<String>)>; 2],
+    // when the last player left a game that isn't over
+    abandoned: Option<Instant>,
+    // the game for its spectators
+    relay: Relay,
+}
+
+type Games = Arc<Mutex<HashMap<String, HostedGame>>>;
+
+impl HostedGame {
+    fn new(start: Grid, delay: Duration) -> HostedGame {
+        let relay = Relay::new(delay);
+        relay.publish(format!("start {}", to_notation(&start)));
+        HostedGame {
+            grid: start.clone(),
+            start,
+            turns: Vec::new(),
+            tokens: [None, None],
+            clients: [None, None],
+            abandoned: None,
+            relay,
+        }
+    }
+
+    // Sends a message to a side, if it is there. A connection that failed is dropped, its
+    // own thread notices that as well.
+    fn tell(&mut self, side: usize, message: &str) {
+        let sent = match self.clients[side] {
+            Some((_, ref client)) => client.send(message.to_string()).is_ok(),
+            None => true,
+        };
+        if !sent {
+            self.clients[side] = None;
+        }
+    }
+
+    fn tell_both(&mut self, message: &str) {
+        self.tell(0, message);
+        self.tell(1, message);
+    }
+
+    // Gives the seat to a connection and sends it the whole game
+    fn seat(&mut self, name: &str, side: usize, client: (u64, Sender<String>)) {
+        self.clients[side] = Some(client);
+        self.abandoned = None;
+        let start = format!("start {}", to_notation(&self.start));
+        self.tell(side, &start);
+        for turn in self.turns.clone() {
+            self.tell(side, &format!("turn {}", turn));
+        }
+        let token = self.tokens[side].clone().unwrap_or_default();
+        self.tell(side, &format!("joined {} {} {}", name, side + 1, token));
+        let state = check_winner(&self.grid);
+        if state != GameState::Mid {
+            self.tell(side, &format!("result {}", result_text(&state)));
+        }
+        self.tell(1 - side, "opponent joined");
+    }
+
+    // Plays a turn for the side, then every player gets it
+    fn play(&mut self, side: usize, stones: &[&str]) -> Result<(), String> {
+        if check_winner(&self.grid) != GameState::Mid {
+            return Err("The game is over.".to_string());
+        }
+        let to_move = self.grid.to_move();
+        if to_move.0 as usize != side + 1 {
+            return Err("It is not your turn.".to_string());
+        }
+        let grid = parse_turn(&self.grid, stones)?;
+        let written: Result<Vec<String>, String> = stones.iter()
+            .map(|stone| parse_stone(stone, to_move).map(|stone| write_stone(&stone)))
+            .collect();
+        let turn = written?.join(" ");
+        self.grid = grid;
+        self.turns.push(turn.clone());
+        self.tell_both(&format!("turn {}", turn));
+        self.relay.publish(format!("turn {}", turn));
+        let state = check_winner(&self.grid);
+        if state != GameState::Mid {
+            self.tell_both(&format!("result {}", result_text(&state)));
+            self.relay.publish(format!("result {}", result_text(&state)));
+        }
+        Ok(())
+    }
+}
+
+// Hosts games for the clients that connect to `listener`, each in a thread of its own,
+// until the listener fails. Spectators see every turn after the delay.
+pub fn serve(listener: TcpListener, delay: Duration) -> io::Result<()> {
+    let games: Games = Arc::new(Mutex::new(HashMap::new()));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let games = games.clone();
+        thread::spawn(move || {
+            let _ = handle(stream, games, delay);
+        });
+    }
+    Ok(())
+}
+
+// The lines waiting for a connection. A thread of its own writes them, so a client that doesn't
+// read only holds up itself, never whoever sends it something. The connection is closed when
+// a line can't be written in time.
+pub(crate) fn outbox(stream: &TcpStream) -> io::Result<Sender<String>> {
+    let mut stream = stream.try_clone()?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let (outbox, lines) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        for line in lines {
+            if send(&mut stream, &line).is_err() {
+                let _ = stream.shutdown(Shutdown::Both);
+                break;
+            }
+        }
+    });
+    Ok(outbox)
+}
+
+// Talks with one client until it leaves or the connection ends
+fn handle(stream: TcpStream, games: Games, delay: Duration) -> io::Result<()> {
+    let writer = outbox(&stream)?;
+    let client = (NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed), writer.clone());
+    let _ = writer.send(format!("welcome {}", PROTOCOL_VERSION));
+    // the game and the side this client plays
+    let mut seat: Option<(String, usize)> = None;
+    let mut watching = false;
+    let mut result = Ok(());
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                result = Err(error);
+                break;
+            },
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let answer = match (words.split_first(), &seat) {
+            (None, _) => continue,
+            (Some((&"leave", [])), _) => break,
+            (Some(_), &None) if watching => Err("Spectators can't play.".to_string()),
+            (Some((&"watch", &[name])), &None) => {
+                watch(&games, name, &writer).map(|_| watching = true)
+            },
+            (Some((&"join", arguments)), &None) if !arguments.is_empty() => {
+                join(&games, arguments[0], &arguments[1 ..], &client, delay).map(|side| {
+                    seat = Some((arguments[0].to_string(), side));
+                })
+            },
+            (Some((&"rejoin", &[name, token])), &None) => {
+                rejoin(&games, name, token, &client).map(|side| {
+                    seat = Some((name.to_string(), side));
+                })
+            },
+            (Some((&"join", _)), &Some(_)) | (Some((&"rejoin", _)), &Some(_))
+            | (Some((&"watch", _)), &Some(_)) => {
+                Err("You are in a game already.".to_string())
+            },
+            (Some((&"play", stones)), &Some((ref name, side))) if !stones.is_empty() => {
+                match games.lock().unwrap().get_mut(name) {
+                    Some(game) => game.play(side, stones),
+                    None => Err("The game is gone.".to_string()),
+                }
+            },
+            (Some((&"play", _)), &None) => Err("Join a game first.".to_string()),
+            (Some((&command, _)), _) => match command {
+                "join" | "rejoin" | "watch" | "play" | "leave" => {
+                    Err(format!("Wrong arguments for {}.", command))
+                },
+                _ => Err(format!("Unknown command '{}'.", command)),
+            },
+        };
+        if let Err(error) = answer {
+            let _ = writer.send(format!("error {}", error));
+        }
+    }
+    if let Some((name, side)) = seat {
+        let mut games = games.lock().unwrap();
+        let finished = match games.get_mut(&name) {
+            // the seat may have been taken back by a new connection in the meantime
+            Some(game) if game.clients[side].as_ref().is_some_and(|&(id, _)| id == client.0) => {
+                game.clients[side] = None;
+                game.tell(1 - side, "opponent left");
+                let empty = game.clients.iter().all(Option::is_none);
+                if empty {
+                    game.abandoned = Some(Instant::now());
+                }
+                empty && check_winner(&game.grid) != GameState::Mid
+            },
+            _ => false,
+        };
+        // a finished game stays until both players are gone
+        if finished {
+            games.remove(&name);
+        }
+    }
+    result
+}
+
+// Takes a free seat in the game, or starts it from the position if there is no game of
+// that name yet. The first player plays side 1.
+fn join(games: &Games, name: &str, position: &[&str], client: &(u64, Sender<String>),
+        delay: Duration) -> Result<usize, String> {
+    let mut games = games.lock().unwrap();
+    expire(&mut games, ABANDONED_GAME_TIME);
+    if !games.contains_key(name) {
+        if position.is_empty() {
+            return Err(format!("There is no game '{}', give a position to start it.", name));
+        }
+        let start = parse_notation(&position.join(" "))?;
+        games.insert(name.to_string(), HostedGame::new(start, delay));
+    }
+    let game = games.get_mut(name).unwrap();
+    let side = game.tokens.iter().position(Option::is_none)
+        .ok_or_else(|| format!("The game '{}' has two players, rejoin it with your token.", name))?;
+    game.tokens[side] = Some(new_token());
+    game.seat(name, side, client.clone());
+    Ok(side)
+}
+
+// Takes back the seat the token belongs to
+fn rejoin(games: &Games, name: &str, token: &str,
+          client: &(u64, Sender<String>)) -> Result<usize, String> {
+    let mut games = games.lock().unwrap();
+    let game = games.get_mut(name).ok_or_else(|| format!("There is no game '{}'.", name))?;
+    let side = game.tokens.iter().position(|seat| seat.as_ref().is_some_and(|seat| seat == token))
+        .ok_or_else(|| format!("No player of '{}' has this token.", name))?;
+    game.seat(name, side, client.clone());
+    Ok(side)
+}
+
+// Lets the client follow the game without a seat. It gets the game so far from the relay.
+fn watch(games: &Games, name: &str, client: &Sender<String>) -> Result<(), String> {
+    let games = games.lock().unwrap();
+    let game = games.get(name).ok_or_else(|| format!("There is no game '{}'.", name))?;
+    let _ = client.send(format!("watching {}", name));
+    game.relay.attach(client.clone());
+    Ok(())
+}
+
+// Removes the games nobody has played in for longer than `after`, the ones that are over
+// are gone already
+fn expire(games: &mut HashMap<String, HostedGame>, after: Duration) {
+    games.retain(|_, game| game.abandoned.is_none_or(|left| left.elapsed() < after));
+}
+
+// A token nobody can guess, from the random keys of the standard library's hash maps
+fn new_token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or(0);
+    hasher.write_u128(time);
+    format!("{:016x}", hasher.finish())
+}
+
+fn send(stream: &mut TcpStream, message: &str) -> io::Result<()> {
+    writeln!(stream, "{}", message)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::SocketAddr;
+
+    // A server on a free port of localhost
+    fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, Duration::from_secs(0)));
+        address
+    }
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(address: SocketAddr) -> Client {
+            let stream = TcpStream::connect(address).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let mut client = Client { reader, writer: stream };
+            assert_eq!("welcome 1", client.read());
+            client
+        }
+
+        fn send(&mut self, message: &str) {
+            send(&mut self.writer, message).unwrap();
+        }
+
+        fn read(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        }
+
+        // The token of the seat it got
+        fn join(&mut self, command: &str) -> String {
+            self.send(command);
+            let mut line = self.read();
+            while !line.starts_with("joined ") {
+                line = self.read();
+            }
+            line.rsplit(' ').next().unwrap().to_string()
+        }
+    }
+
+    #[test]
+    fn test_two_players_play_a_game() {
+        let address = start_server();
+        let mut first = Client::connect(address);
+        first.send("join game 3x3 3 standard 3/3/3 1");
+        assert_eq!("start 3x3 3 standard 3/3/3 1", first.read());
+        assert!(first.read().starts_with("joined game 1 "));
+        let mut second = Client::connect(address);
+        second.join("join game");
+        assert_eq!("opponent joined", first.read());
+        second.send("play b2");
+        assert_eq!("error It is not your turn.", second.read());
+        for (cell, mover) in [("b2", 0), ("a1", 1), ("b1", 0), ("a3", 1), ("b3", 0)] {
+            if mover == 0 {
+                first.send(&format!("play {}", cell));
+            } else {
+                second.send(&format!("play {}", cell));
+            }
+            assert_eq!(format!("turn {}", cell), first.read());
+            assert_eq!(format!("turn {}", cell), second.read());
+        }
+        assert_eq!("result 1-0", first.read());
+        assert_eq!("result 1-0", second.read());
+        second.send("play c1");
+        assert_eq!("error The game is over.", second.read());
+    }
+
+    #[test]
+    fn test_illegal_turns_are_refused() {
+        let address = start_server();
+        let mut client = Client::connect(address);
+        client.send("play b2");
+        assert_eq!("error Join a game first.", client.read());
+        client.send("join game");
+        assert_eq!("error There is no game 'game', give a position to start it.", client.read());
+        client.join("join game 3x3 3 standard 3/3/3 1");
+        client.send("play d4");
+        assert_eq!("error d4 is not on the board.", client.read());
+        client.send("play 1,1");
+        assert_eq!("turn b2", client.read());
+        client.send("fly");
+        assert_eq!("error Unknown command 'fly'.", client.read());
+    }
+
+    #[test]
+    fn test_rejoin_restores_the_game() {
+        let address = start_server();
+        let mut first = Client::connect(address);
+        let token = first.join("join game 3x3 3 standard 3/3/3 1");
+        let mut second = Client::connect(address);
+        second.join("join game");
+        first.read();
+        first.send("play b2");
+        first.read();
+        second.read();
+        drop(first);
+        assert_eq!("opponent left", second.read());
+        let mut third = Client::connect(address);
+        third.send("join game");
+        assert_eq!("error The game 'game' has two players, rejoin it with your token.",
+                   third.read());
+        third.send("rejoin game 0000");
+        assert_eq!("error No player of 'game' has this token.", third.read());
+        third.send(&format!("rejoin game {}", token));
+        assert_eq!("start 3x3 3 standard 3/3/3 1", third.read());
+        assert_eq!("turn b2", third.read());
+        assert_eq!(format!("joined game 1 {}", token), third.read());
+        assert_eq!("opponent joined", second.read());
+    }
+
+    #[test]
+    fn test_an_old_connection_leaves_a_taken_back_seat_alone() {
+        let address = start_server();
+        let mut first = Client::connect(address);
+        let token = first.join("join game 3x3 3 standard 3/3/3 1");
+        let mut second = Client::connect(address);
+        second.join("join game");
+        first.read();
+        let mut third = Client::connect(address);
+        third.join(&format!("rejoin game {}", token));
+        assert_eq!("opponent joined", second.read());
+        // the seat belongs to the third connection now, the first one ending changes nothing
+        drop(first);
+        thread::sleep(Duration::from_millis(100));
+        third.send("play b2");
+        assert_eq!("turn b2", second.read());
+        assert_eq!("turn b2", third.read());
+    }
+
+    #[test]
+    fn test_abandoned_games_expire() {
+        let mut games = HashMap::new();
+        let kept = HostedGame::new(Grid::new(3, 3, 3), Duration::from_secs(0));
+        games.insert("kept".to_string(), kept);
+        let mut abandoned = HostedGame::new(Grid::new(3, 3, 3), Duration::from_secs(0));
+        abandoned.abandoned = Some(Instant::now());
+        games.insert("abandoned".to_string(), abandoned);
+        expire(&mut games, Duration::from_secs(60));
+        assert_eq!(2, games.len());
+        thread::sleep(Duration::from_millis(20));
+        expire(&mut games, Duration::from_millis(10));
+        assert_eq!(vec!["kept"], games.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_spectators_watch_without_playing() {
+        let address = start_server();
+        let mut player = Client::connect(address);
+        player.join("join game 3x3 3 standard 3/3/3 1");
+        player.send("play b2");
+        player.read();
+        let mut spectator = Client::connect(address);
+        spectator.send("watch nothing");
+        assert_eq!("error There is no game 'nothing'.", spectator.read());
+        spectator.send("watch game");
+        assert_eq!("watching game", spectator.read());
+        // the turns so far, then the ones to come
+        assert_eq!("start 3x3 3 standard 3/3/3 1", spectator.read());
+        assert_eq!("turn b2", spectator.read());
+        spectator.send("play a1");
+        assert_eq!("error Spectators can't play.", spectator.read());
+        let mut second = Client::connect(address);
+        second.join("join game");
+        second.send("play a1");
+        assert_eq!("turn a1", spectator.read());
+    }
+}
@@ -0,0 +1,318 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use ::engine::parse_turn;
+use ::game::{GameState, PlayerId};
+use ::game::grid::Grid;
+use ::game::notation::{parse_notation, to_notation};
+use ::game::record::{result_text, write_stone, RecordedTurn};
+use ::server::{outbox, PROTOCOL_VERSION};
+
+// Someone who follows a game without ever playing in it. A spectator that comes late gets the
+// start and every turn so far first.
+pub trait Spectator {
+    // The position the game starts from, before any turn
+    fn start (&self, grid: &Grid);
+    // A turn of `side` as it was played, with the position after it
+    fn turn (&self, turn: &RecordedTurn, side: PlayerId, grid: &Grid);
+    fn end (&self, _state: &GameState) {}
+}
+
+// The messages of a game for the spectators who watch it over the network, in the lines of
+// docs/network-protocol.md. Every message is held back for the delay, so that watching can't
+// help a player. Spectators who attach get every message released so far. The messages go
+// to the outboxes of the spectators, so a slow one holds up nobody.
+pub struct Relay {
+    released: Arc<Mutex<Released>>,
+    queue: Sender<(Instant, String)>,
+    delay: Duration,
+}
+
+struct Released {
+    history: Vec<String>,
+    watchers: Vec<Sender<String>>,
+    // the messages published, released or not
+    published: usize,
+}
+
+impl Relay {
+    pub fn new(delay: Duration) -> Relay {
+        let released = Released { history: Vec::new(), watchers: Vec::new(), published: 0 };
+        let released = Arc::new(Mutex::new(released));
+        let (queue, messages) = mpsc::channel::<(Instant, String)>();
+        let shared = released.clone();
+        // all messages have the same delay, so they are due in the order they come
+        thread::spawn(move || {
+            for (due, message) in messages {
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep(due - now);
+                }
+                let mut released = shared.lock().unwrap();
+                released.watchers.retain(|watcher| watcher.send(message.clone()).is_ok());
+                released.history.push(message);
+            }
+        });
+        Relay { released, queue, delay }
+    }
+
+    pub fn publish(&self, message: String) {
+        self.released.lock().unwrap().published += 1;
+        let _ = self.queue.send((Instant::now() + self.delay, message));
+    }
+
+    // Waits until every message published so far is released
+    pub fn flush(&self) {
+        loop {
+            {
+                let released = self.released.lock().unwrap();
+                if released.history.len() >= released.published {
+                    return;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // Sends the messages released so far to the watcher, then every one after them
+    pub fn attach(&self, watcher: Sender<String>) {
+        let mut released = self.released.lock().unwrap();
+        let sent = released.history.iter().try_for_each(|message| watcher.send(message.clone()));
+        if sent.is_ok() {
+            released.watchers.push(watcher);
+        }
+    }
+}
+
+// A game on this machine that spectators can watch over the network with 'watch', like a
+// game on the server
+pub struct Broadcast {
+    relay: Arc<Relay>,
+}
+
+impl Broadcast {
+    // Lets spectators connect to `listener`, the game is called `name` for them
+    pub fn start(listener: TcpListener, name: &str, delay: Duration) -> Broadcast {
+        let relay = Arc::new(Relay::new(delay));
+        let shared = relay.clone();
+        let name = name.to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let relay = shared.clone();
+                let name = name.clone();
+                thread::spawn(move || {
+                    let _ = welcome(stream, &name, &relay);
+                });
+            }
+        });
+        Broadcast { relay }
+    }
+
+    // Waits until the spectators have seen the game so far, as the delay holds it back
+    pub fn flush(&self) {
+        self.relay.flush();
+    }
+}
+
+// Greets a spectator and lets them watch once they ask to. Nothing else is possible here.
+fn welcome(stream: TcpStream, name: &str, relay: &Relay) -> io::Result<()> {
+    let writer = outbox(&stream)?;
+    let _ = writer.send(format!("welcome {}", PROTOCOL_VERSION));
+    let mut watching = false;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let answer = match words[..] {
+            [] => continue,
+            ["leave"] => break,
+            ["watch", _] if !watching => {
+                watching = true;
+                let _ = writer.send(format!("watching {}", name));
+                relay.attach(writer.clone());
+                continue;
+            },
+            _ if watching => "error Spectators can't play.",
+            _ => "error This game can only be watched, with 'watch <game>'.",
+        };
+        let _ = writer.send(answer.to_string());
+    }
+    Ok(())
+}
+
+impl Spectator for Broadcast {
+    fn start (&self, grid: &Grid) {
+        self.relay.publish(format!("start {}", to_notation(grid)));
+    }
+
+    fn turn (&self, turn: &RecordedTurn, _side: PlayerId, _grid: &Grid) {
+        let stones: Vec<String> = turn.stones.iter().map(write_stone).collect();
+        self.relay.publish(format!("turn {}", stones.join(" ")));
+    }
+
+    fn end (&self, state: &GameState) {
+        self.relay.publish(format!("result {}", result_text(state)));
+    }
+}
+
+// What a spectator sees of a game over the network
+pub enum WatchEvent {
+    Start(Grid),
+    // the side that played, its stones and the position after them
+    Turn(PlayerId, Vec<String>, Grid),
+    Result(String),
+}
+
+// Watches a game on a server, or one that is broadcast
+pub struct Watcher {
+    reader: BufReader<TcpStream>,
+    grid: Option<Grid>,
+}
+
+impl Watcher {
+    pub fn connect(address: &str, game: &str) -> Result<Watcher, String> {
+        let stream = TcpStream::connect(address)
+            .map_err(|error| format!("Can't connect to {}: {}", address, error))?;
+        let mut writer = stream.try_clone().map_err(|error| error.to_string())?;
+        let mut watcher = Watcher { reader: BufReader::new(stream), grid: None };
+        let welcome = watcher.read()?.unwrap_or_default();
+        if welcome != format!("welcome {}", PROTOCOL_VERSION) {
+            return Err(format!("The server greets with '{}', not protocol version {}.", welcome,
+                               PROTOCOL_VERSION));
+        }
+        send(&mut writer, &format!("watch {}", game)).map_err(|error| error.to_string())?;
+        match watcher.read()? {
+            Some(ref line) if line.starts_with("watching ") => Ok(watcher),
+            Some(line) => Err(line.trim_start_matches("error ").to_string()),
+            None => Err("The server closed the connection.".to_string()),
+        }
+    }
+
+    fn read(&mut self) -> Result<Option<String>, String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line.trim_end().to_string())),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    // The next thing that happens in the game, or nothing once the game can't be watched any
+    // more. A spectator who comes late gets the start and every turn so far first.
+    pub fn next_event(&mut self) -> Result<Option<WatchEvent>, String> {
+        while let Some(line) = self.read()? {
+            let (kind, text) = match line.find(' ') {
+                Some(index) => (&line[.. index], &line[index + 1 ..]),
+                None => (&line[..], ""),
+            };
+            match kind {
+                "start" => {
+                    let grid = parse_notation(text)?;
+                    self.grid = Some(grid.clone());
+                    return Ok(Some(WatchEvent::Start(grid)));
+                },
+                "turn" => {
+                    let grid = self.grid.as_ref()
+                        .ok_or("A turn came before the start of the game.")?;
+                    let stones: Vec<&str> = text.split_whitespace().collect();
+                    let side = grid.to_move();
+                    let after = parse_turn(grid, &stones)
+                        .map_err(|error| format!("The turn '{}' doesn't fit: {}", text, error))?;
+                    self.grid = Some(after.clone());
+                    let stones = stones.iter().map(|stone| stone.to_string()).collect();
+                    return Ok(Some(WatchEvent::Turn(side, stones, after)));
+                },
+                "result" => return Ok(Some(WatchEvent::Result(text.to_string()))),
+                _ => {},
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn send(stream: &mut TcpStream, message: &str) -> io::Result<()> {
+    writeln!(stream, "{}", message)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use ::game::opening::Opening;
+    use ::play::Game;
+    use ::player::ki::KiPlayer;
+
+    // Writes down everything it sees
+    struct Notes {
+        seen: RefCell<Vec<String>>,
+    }
+
+    impl Spectator for Notes {
+        fn start (&self, grid: &Grid) {
+            self.seen.borrow_mut().push(to_notation(grid));
+        }
+
+        fn turn (&self, turn: &RecordedTurn, side: PlayerId, _grid: &Grid) {
+            let stones: Vec<String> = turn.stones.iter().map(write_stone).collect();
+            self.seen.borrow_mut().push(format!("{}: {}", side.0, stones.join(" ")));
+        }
+
+        fn end (&self, state: &GameState) {
+            self.seen.borrow_mut().push(result_text(state));
+        }
+    }
+
+    #[test]
+    fn test_late_spectators_get_the_history() {
+        let (first, second) = (KiPlayer::new(1), KiPlayer::new(2));
+        let notes = Notes { seen: RefCell::new(Vec::new()) };
+        let mut game = Game::new(Grid::new(3, 3, 3), [&first, &second], Opening::Free);
+        game.play_turn();
+        game.play_turn();
+        game.watch(&notes);
+        game.play().unwrap();
+        let seen = notes.seen.borrow();
+        // the start, nine turns and the draw
+        assert_eq!(11, seen.len());
+        assert_eq!("3x3 3 standard 3/3/3 1", seen[0]);
+        assert!(seen[1].starts_with("1: ") && seen[2].starts_with("2: "));
+        assert!(seen[9].starts_with("1: "));
+        assert_eq!("1/2-1/2", seen[10]);
+    }
+
+    #[test]
+    fn test_broadcast_with_delay() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let broadcast = Broadcast::start(listener, "local", Duration::from_millis(300));
+        let (first, second) = (KiPlayer::new(1), KiPlayer::new(2));
+        let grid = parse_notation("3x3 3 standard xx1/oo1/3 1").unwrap();
+        let mut game = Game::new(grid, [&first, &second], Opening::Free);
+        game.watch(&broadcast);
+        let mut watcher = Watcher::connect(&address, "local").unwrap();
+        let started = Instant::now();
+        game.play().unwrap();
+        match watcher.next_event().unwrap() {
+            Some(WatchEvent::Start(grid)) => {
+                assert_eq!("3x3 3 standard xx1/oo1/3 1", to_notation(&grid));
+            },
+            _ => panic!("the start was expected"),
+        }
+        match watcher.next_event().unwrap() {
+            Some(WatchEvent::Turn(side, stones, _)) => {
+                assert_eq!((PlayerId(1), vec!["c1".to_string()]), (side, stones));
+            },
+            _ => panic!("a turn was expected"),
+        }
+        // the turn was played at once, but the spectator only sees it after the delay
+        assert!(started.elapsed() >= Duration::from_millis(250));
+        match watcher.next_event().unwrap() {
+            Some(WatchEvent::Result(result)) => assert_eq!("1-0", result),
+            _ => panic!("the result was expected"),
+        }
+        broadcast.flush();
+    }
+}
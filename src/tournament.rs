@@ -0,0 +1,545 @@
+use std::time::Duration;
+use ::game::{GameState, PlayerId};
+use ::game::grid::Grid;
+use ::game::opening::Opening;
+use ::game::record::{result_text, Record};
+use ::play::Game;
+use ::player::Player;
+use ::player::external::{ExternalPlayer, DEFAULT_GRACE};
+use ::player::ki::KiPlayer;
+
+// Who meets whom. In a round robin every entrant plays every other one, in a Swiss tournament
+// entrants with about the same points meet for the given number of rounds.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Pairings {
+    RoundRobin,
+    Swiss(usize),
+}
+
+impl Pairings {
+    pub fn parse(name: &str, rounds: Option<usize>) -> Result<Pairings, String> {
+        match name {
+            // every entrant meets every other once
+            "round-robin" if rounds.is_some() => {
+                Err("A round-robin has no rounds to choose.".to_string())
+            },
+            "round-robin" => Ok(Pairings::RoundRobin),
+            "swiss" => Ok(Pairings::Swiss(rounds.unwrap_or(0))),
+            _ => Err(format!("'{}' is not a tournament, use round-robin or swiss.", name)),
+        }
+    }
+}
+
+// A player of the tournament: the name it has in the tables and records, and the kind of
+// player it is, see `new_player`
+pub struct Entrant {
+    pub name: String,
+    pub kind: String,
+}
+
+impl Entrant {
+    // Entrants of these kinds, named after them. Entrants of the same kind are numbered.
+    pub fn all(kinds: &[String]) -> Vec<Entrant> {
+        kinds.iter().enumerate()
+            .map(|(index, kind)| {
+                let same = kinds.iter().filter(|other| *other == kind).count();
+                let before = kinds[.. index].iter().filter(|other| *other == kind).count();
+                let name = if same > 1 {
+                    format!("{} #{}", kind, before + 1)
+                } else {
+                    kind.clone()
+                };
+                Entrant { name, kind: kind.clone() }
+            })
+            .collect()
+    }
+}
+
+// A player for side `id`:
+//
+// * 'ki', the minimax search as in a normal game
+// * 'ki:easy', 'ki:medium' and 'ki:hard', the difficulties of the HTTP API
+// * 'ki:depth=N' to look N turns ahead, 'ki:movetime=MS' to search for MS milliseconds a turn
+// * 'engine:COMMAND', the engine that COMMAND starts, which gets `move_time` for a turn
+pub fn new_player(kind: &str, id: u32, move_time: Duration) -> Result<Box<dyn Player>, String> {
+    match kind.strip_prefix("engine:") {
+        Some(command) => {
+            Ok(Box::new(ExternalPlayer::start(id, command, move_time, DEFAULT_GRACE)?))
+        },
+        None => Ok(Box::new(ki_player(kind, id)?)),
+    }
+}
+
+// The ki of a kind, an error if the kind is no ki
+fn ki_player(kind: &str, id: u32) -> Result<KiPlayer, String> {
+    let setting = match kind.strip_prefix("ki") {
+        Some(setting) if setting.is_empty() || setting.starts_with(':') => setting,
+        _ => return Err(format!("'{}' is no player, use ki, ki:SETTING or engine:COMMAND.", kind)),
+    };
+    match setting {
+        "" => Ok(KiPlayer::new(id)),
+        ":easy" => Ok(KiPlayer::with_depth(id, 1)),
+        ":medium" => Ok(KiPlayer::with_depth(id, 2)),
+        ":hard" => Ok(KiPlayer::with_move_time(id, Duration::from_millis(1000))),
+        setting if setting.starts_with(":depth=") => {
+            let text = &setting[":depth=".len() ..];
+            text.parse::<u32>().ok().filter(|&depth| depth > 0)
+                .map(|depth| KiPlayer::with_depth(id, depth))
+                .ok_or_else(|| format!("'{}' in '{}' is not a number above 0.", text, kind))
+        },
+        setting if setting.starts_with(":movetime=") => {
+            let text = &setting[":movetime=".len() ..];
+            text.parse::<u64>()
+                .map(|time| KiPlayer::with_move_time(id, Duration::from_millis(time)))
+                .map_err(|_| format!("'{}' in '{}' is not a number.", text, kind))
+        },
+        _ => {
+            Err(format!("'{}' is not a setting of the ki, use easy, medium, hard, depth=N or \
+                         movetime=MS.", &setting[1 ..]))
+        },
+    }
+}
+
+pub struct Tournament {
+    pub entrants: Vec<Entrant>,
+    pub pairings: Pairings,
+    // the games of each pairing, the entrants take turns with the first side
+    pub games: usize,
+    pub start: Grid,
+    pub opening: Opening,
+    // the time of an engine for a turn
+    pub move_time: Duration,
+}
+
+// A game of the tournament, with the entrants by the side they started on
+pub struct Played {
+    pub round: usize,
+    pub entrants: [usize; 2],
+    // the points each of them got, 1 for a win and 1/2 for a draw
+    pub points: [f64; 2],
+    pub record: Record,
+}
+
+// Everything that happened in a tournament
+pub struct Results {
+    pub names: Vec<String>,
+    pub games: Vec<Played>,
+    // the entrant that sat out in a round of a Swiss tournament, for each round
+    pub byes: Vec<(usize, usize)>,
+    // what a bye is worth, the points of winning every game of a pairing
+    bye_points: f64,
+}
+
+impl Tournament {
+    pub fn rounds(&self) -> usize {
+        let count = self.entrants.len();
+        match self.pairings {
+            Pairings::RoundRobin => 1,
+            // enough rounds for one entrant to stay ahead of all others, if none are given
+            Pairings::Swiss(0) => {
+                (usize::BITS - count.saturating_sub(1).leading_zeros()).max(1) as usize
+            },
+            Pairings::Swiss(rounds) => rounds,
+        }
+    }
+
+    // Plays all games, `report` hears of each one as soon as it is over
+    pub fn run(&self, report: &mut dyn FnMut(&Results, &Played)) -> Result<Results, String> {
+        if self.entrants.len() < 2 {
+            return Err("A tournament needs two entrants at least.".to_string());
+        }
+        // engines are only started for their games, but a ki that can't be made is a mistake
+        // in the entrants
+        for entrant in &self.entrants {
+            if !entrant.kind.starts_with("engine:") {
+                ki_player(&entrant.kind, 1)?;
+            }
+        }
+        let mut results = Results {
+            names: self.entrants.iter().map(|entrant| entrant.name.clone()).collect(),
+            games: Vec::new(),
+            byes: Vec::new(),
+            bye_points: self.games as f64,
+        };
+        for round in 1 ..= self.rounds() {
+            let pairs = match self.pairings {
+                Pairings::RoundRobin => round_robin(self.entrants.len()),
+                Pairings::Swiss(_) => {
+                    let (pairs, bye) = swiss(&results);
+                    if let Some(bye) = bye {
+                        results.byes.push((round, bye));
+                    }
+                    pairs
+                },
+            };
+            for pair in pairs {
+                // whoever started fewer games so far starts the first game of the pairing
+                let (a, b) = if results.started(pair.1) < results.started(pair.0) {
+                    (pair.1, pair.0)
+                } else {
+                    pair
+                };
+                for game in 0 .. self.games {
+                    let entrants = if game % 2 == 0 { [a, b] } else { [b, a] };
+                    let played = self.play(round, entrants)?;
+                    results.games.push(played);
+                    report(&results, results.games.last().unwrap());
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn play(&self, round: usize, entrants: [usize; 2]) -> Result<Played, String> {
+        let names = [self.entrants[entrants[0]].name.as_str(),
+                     self.entrants[entrants[1]].name.as_str()];
+        let first = new_player(&self.entrants[entrants[0]].kind, 1, self.move_time);
+        let second = new_player(&self.entrants[entrants[1]].kind, 2, self.move_time);
+        let (first, second) = match (first, second) {
+            (Ok(first), Ok(second)) => (first, second),
+            (Err(error), _) => {
+                return Ok(self.forfeited(round, entrants, names, PlayerId(1), &error));
+            },
+            (_, Err(error)) => {
+                return Ok(self.forfeited(round, entrants, names, PlayerId(2), &error));
+            },
+        };
+        let mut game = Game::new(self.start.clone(), [&*first, &*second], self.opening);
+        let state = game.play()?;
+        let mut points = [0.0; 2];
+        match state {
+            GameState::Win(side) => points[game.player_of(side)] = 1.0,
+            GameState::Scores(first, second) if first != second => {
+                let winner = if first > second { PlayerId(1) } else { PlayerId(2) };
+                points[game.player_of(winner)] = 1.0;
+            },
+            _ => points = [0.5, 0.5],
+        }
+        let mut record = game.record(names);
+        record.set_tag("Round", &round.to_string());
+        Ok(Played { round, entrants, points, record })
+    }
+
+    // A game lost before it began, by an engine that doesn't start
+    fn forfeited(&self, round: usize, entrants: [usize; 2], names: [&str; 2], side: PlayerId,
+                 reason: &str) -> Played {
+        let mut record = Record::new(&self.start);
+        record.set_tag("Player1", names[0]);
+        record.set_tag("Player2", names[1]);
+        record.set_tag("Result", &result_text(&GameState::Win(side.opponent())));
+        record.set_tag("Termination", &format!("Side {} forfeits: {}", side.0, reason));
+        record.set_tag("Round", &round.to_string());
+        let points = if side == PlayerId(1) { [0.0, 1.0] } else { [1.0, 0.0] };
+        Played { round, entrants, points, record }
+    }
+}
+
+// Every entrant against every other one
+fn round_robin(count: usize) -> Vec<(usize, usize)> {
+    (0 .. count).flat_map(|a| (a + 1 .. count).map(move |b| (a, b))).collect()
+}
+
+// The pairs of the next round of a Swiss tournament. Entrants meet someone close to them in the
+// standings they haven't met yet, and only meet again if there is no other way. With an odd
+// number of entrants, the lowest one that hadn't had a bye yet sits out.
+fn swiss(results: &Results) -> (Vec<(usize, usize)>, Option<usize>) {
+    let mut ranking = results.ranking();
+    let bye = if ranking.len() % 2 == 1 {
+        let index = ranking.iter()
+            .rposition(|entrant| !results.byes.iter().any(|bye| bye.1 == *entrant))
+            .unwrap_or(ranking.len() - 1);
+        Some(ranking.remove(index))
+    } else {
+        None
+    };
+    let pairs = pair(results, &ranking, false)
+        .or_else(|| pair(results, &ranking, true))
+        .unwrap_or_default();
+    (pairs, bye)
+}
+
+// Pairs the first entrant left with the next one it may meet, going back if the others can't
+// all be paired then
+fn pair(results: &Results, left: &[usize], again: bool) -> Option<Vec<(usize, usize)>> {
+    let (first, rest) = match left.split_first() {
+        Some(split) => split,
+        None => return Some(Vec::new()),
+    };
+    for (index, second) in rest.iter().enumerate() {
+        if !again && results.met(*first, *second) {
+            continue;
+        }
+        let mut others = rest.to_vec();
+        others.remove(index);
+        if let Some(mut pairs) = pair(results, &others, again) {
+            pairs.insert(0, (*first, *second));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+impl Results {
+    pub fn points(&self, entrant: usize) -> f64 {
+        let played: f64 = self.games.iter()
+            .filter_map(|game| {
+                let side = game.entrants.iter().position(|other| *other == entrant)?;
+                Some(game.points[side])
+            })
+            .sum();
+        let byes = self.byes.iter().filter(|bye| bye.1 == entrant).count();
+        played + byes as f64 * self.bye_points
+    }
+
+    // The points of `entrant` in the games against `opponent`, if they met
+    pub fn points_against(&self, entrant: usize, opponent: usize) -> Option<f64> {
+        let mut met = false;
+        let mut points = 0.0;
+        for game in &self.games {
+            if game.entrants == [entrant, opponent] {
+                points += game.points[0];
+                met = true;
+            } else if game.entrants == [opponent, entrant] {
+                points += game.points[1];
+                met = true;
+            }
+        }
+        if met { Some(points) } else { None }
+    }
+
+    fn met(&self, entrant: usize, opponent: usize) -> bool {
+        self.points_against(entrant, opponent).is_some()
+    }
+
+    fn started(&self, entrant: usize) -> usize {
+        self.games.iter().filter(|game| game.entrants[0] == entrant).count()
+    }
+
+    // The Sonneborn-Berger score: the points of every opponent, weighted by the points
+    // scored against them. It ranks entrants with the same points.
+    pub fn tiebreak(&self, entrant: usize) -> f64 {
+        (0 .. self.names.len())
+            .filter_map(|opponent| {
+                let points = self.points_against(entrant, opponent)?;
+                Some(points * self.points(opponent))
+            })
+            .sum()
+    }
+
+    // The entrants from the first place to the last
+    pub fn ranking(&self) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0 .. self.names.len()).collect();
+        ranking.sort_by(|a, b| {
+            (self.points(*b), self.tiebreak(*b)).partial_cmp(&(self.points(*a), self.tiebreak(*a)))
+                .unwrap()
+                .then(a.cmp(b))
+        });
+        ranking
+    }
+
+    // The wins, draws and losses of the first entrant against the second
+    pub fn score(&self, entrant: usize, opponent: usize) -> (usize, usize, usize) {
+        let mut score = (0, 0, 0);
+        for game in &self.games {
+            let side = if game.entrants == [entrant, opponent] {
+                0
+            } else if game.entrants == [opponent, entrant] {
+                1
+            } else {
+                continue;
+            };
+            match game.points[side] {
+                1.0 => score.0 += 1,
+                0.5 => score.1 += 1,
+                _ => score.2 += 1,
+            }
+        }
+        score
+    }
+
+    // The standings, with the points of every entrant against every other one:
+    //
+    //     #  Entrant   Points  SB     1    2    3
+    //     1  ki        3.0     2.50   -    1.5  1.5
+    //     2  ki:easy   1.5     0.75   0.5  -    1.0
+    pub fn crosstable(&self) -> String {
+        let ranking = self.ranking();
+        let width = self.names.iter().map(|name| name.chars().count()).max().unwrap_or(0)
+            .max("Entrant".len());
+        let mut table = format!("{:<3} {:<width$}  {:<6}  {:<6}", "#", "Entrant", "Points", "SB",
+                                width = width);
+        for place in 1 ..= ranking.len() {
+            table += &format!("  {:<4}", place);
+        }
+        for (place, entrant) in ranking.iter().enumerate() {
+            table += &format!("\n{:<3} {:<width$}  {:<6.1}  {:<6.2}", place + 1,
+                              self.names[*entrant], self.points(*entrant), self.tiebreak(*entrant),
+                              width = width);
+            for opponent in &ranking {
+                let cell = match self.points_against(*entrant, *opponent) {
+                    _ if opponent == entrant => "-".to_string(),
+                    Some(points) => format!("{:.1}", points),
+                    None => String::new(),
+                };
+                table += &format!("  {:<4}", cell);
+            }
+        }
+        table.lines().map(str::trim_end).collect::<Vec<&str>>().join("\n")
+    }
+
+    // The wins, draws and losses of every pairing, like 'ki - ki:easy: 3 wins, 1 draw, 0 losses'
+    pub fn pairings(&self) -> String {
+        let mut lines = Vec::new();
+        for game in &self.games {
+            let [a, b] = game.entrants;
+            let (first, second) = if a < b { (a, b) } else { (b, a) };
+            let line = {
+                let (wins, draws, losses) = self.score(first, second);
+                format!("{} - {}: {} {}, {} {}, {} {}", self.names[first], self.names[second],
+                        wins, if wins == 1 { "win" } else { "wins" },
+                        draws, if draws == 1 { "draw" } else { "draws" },
+                        losses, if losses == 1 { "loss" } else { "losses" })
+            };
+            if !lines.contains(&line) {
+                lines.push(line);
+            }
+        }
+        for (round, entrant) in &self.byes {
+            lines.push(format!("{} has a bye in round {}", self.names[*entrant], round));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A tournament of the kinds on a 3x3 board with two games a pairing
+    fn tournament(kinds: &[&str], pairings: Pairings) -> Tournament {
+        Tournament {
+            entrants: Entrant::all(&kinds.iter().map(|kind| kind.to_string()).collect::<Vec<_>>()),
+            pairings,
+            games: 2,
+            start: Grid::new(3, 3, 3),
+            opening: Opening::Free,
+            move_time: Duration::from_millis(100),
+        }
+    }
+
+    fn results(count: usize, games: &[(usize, usize, f64)]) -> Results {
+        Results {
+            names: (0 .. count).map(|entrant| format!("e{}", entrant)).collect(),
+            games: games.iter()
+                .map(|(a, b, points)| Played {
+                    round: 1,
+                    entrants: [*a, *b],
+                    points: [*points, 1.0 - *points],
+                    record: Record::new(&Grid::new(3, 3, 3)),
+                })
+                .collect(),
+            byes: Vec::new(),
+            bye_points: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_round_robin_with_alternating_sides() {
+        let tournament = tournament(&["ki:easy", "ki:depth=1", "ki:easy"], Pairings::RoundRobin);
+        let names: Vec<&str> = tournament.entrants.iter()
+            .map(|entrant| entrant.name.as_str())
+            .collect();
+        assert_eq!(vec!["ki:easy #1", "ki:depth=1", "ki:easy #2"], names);
+        let mut reported = 0;
+        let results = tournament.run(&mut |_, _| reported += 1).unwrap();
+        // three pairings of two games
+        assert_eq!(6, reported);
+        assert_eq!(6, results.games.len());
+        for pair in results.games.chunks(2) {
+            assert_eq!([pair[0].entrants[1], pair[0].entrants[0]], pair[1].entrants);
+        }
+        let total: f64 = (0 .. 3).map(|entrant| results.points(entrant)).sum();
+        assert_eq!(6.0, total);
+        assert_eq!(Some("ki:easy #1"), results.games[0].record.tag("Player1"));
+        assert_eq!(Some("1"), results.games[0].record.tag("Round"));
+        assert_eq!(3, results.pairings().lines().count());
+    }
+
+    #[test]
+    fn test_swiss_pairs_by_points_without_rematches() {
+        // e0 and e2 won the first round
+        let mut results = results(4, &[(0, 1, 1.0), (2, 3, 1.0)]);
+        let (pairs, bye) = swiss(&results);
+        assert_eq!((vec![(0, 2), (1, 3)], None), (pairs, bye));
+        // with five entrants the last one sits out, but only once
+        results.names.push("e4".to_string());
+        let (pairs, bye) = swiss(&results);
+        assert_eq!((vec![(0, 2), (1, 3)], Some(4)), (pairs, bye));
+        results.byes.push((1, 4));
+        let (pairs, bye) = swiss(&results);
+        assert_eq!((vec![(0, 2), (4, 1)], Some(3)), (pairs, bye));
+        assert_eq!(1.0, results.points(4));
+        assert_eq!(4, tournament(&["ki"; 9], Pairings::Swiss(0)).rounds());
+        assert_eq!(Ok(Pairings::Swiss(3)), Pairings::parse("swiss", Some(3)));
+        assert!(Pairings::parse("round-robin", Some(3)).is_err());
+    }
+
+    #[test]
+    fn test_crosstable() {
+        let results = results(3, &[(0, 1, 1.0), (1, 0, 0.5), (2, 0, 0.0), (1, 2, 0.5)]);
+        assert_eq!(vec![0, 1, 2], results.ranking());
+        assert_eq!((1, 1, 0), results.score(0, 1));
+        assert_eq!("#   Entrant  Points  SB      1     2     3\n\
+                    1   e0       2.5     2.00    -     1.5   1.0\n\
+                    2   e1       1.0     1.50    0.5   -     0.5\n\
+                    3   e2       0.5     0.50    0.0   0.5   -",
+                   results.crosstable());
+        assert_eq!("e0 - e1: 1 win, 1 draw, 0 losses
+e0 - e2: 1 win, 0 draws, 0 losses
+e1 - e2: 0 wins, 1 draw, 0 losses",
+                   results.pairings());
+    }
+
+    #[test]
+    fn test_unknown_settings() {
+        match new_player("ki:godlike", 1, Duration::from_millis(100)) {
+            Err(error) => assert_eq!("'godlike' is not a setting of the ki, use easy, medium, \
+                                      hard, depth=N or movetime=MS.", error),
+            Ok(_) => panic!("the setting should be refused"),
+        }
+        assert!(new_player("ki:depth=many", 1, Duration::from_millis(100)).is_err());
+        match new_player("ki:depth=0", 1, Duration::from_millis(100)) {
+            Err(error) => assert_eq!("'0' in 'ki:depth=0' is not a number above 0.", error),
+            Ok(_) => panic!("a depth of 0 should be refused"),
+        }
+        assert!(new_player("ki:depth=4294967296", 1, Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn test_unknown_kinds() {
+        for kind in &["kii", "k:easy", "python3 engine.py", ""] {
+            match new_player(kind, 1, Duration::from_millis(100)) {
+                Err(error) => {
+                    assert_eq!(format!("'{}' is no player, use ki, ki:SETTING or \
+                                        engine:COMMAND.", kind), error);
+                },
+                Ok(_) => panic!("'{}' should be refused", kind),
+            }
+        }
+        let tournament = tournament(&["ki:easy", "kii"], Pairings::RoundRobin);
+        assert!(tournament.run(&mut |_, _| {}).is_err());
+    }
+
+    #[test]
+    fn test_engines_that_dont_start_forfeit() {
+        let tournament = tournament(&["ki:easy", "engine:/nonexistent/engine"],
+                                    Pairings::RoundRobin);
+        let results = tournament.run(&mut |_, _| {}).unwrap();
+        assert_eq!((2.0, 0.0), (results.points(0), results.points(1)));
+        assert_eq!(Some("0-1"), results.games[1].record.tag("Result"));
+        let termination = results.games[1].record.tag("Termination").unwrap();
+        assert!(termination.starts_with("Side 1 forfeits: "));
+    }
+}
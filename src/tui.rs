@@ -0,0 +1,391 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use std::time::Duration;
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, SetAttribute, SetBackgroundColor,
+                       SetForegroundColor};
+use ::game::{CellState, Coord, PlayerId, X, O};
+use ::game::algebraic;
+use ::game::grid::Grid;
+use ::game::grid_observer;
+use ::game::record::RecordedTurn;
+use ::game::topology::Topology;
+use ::play::Game;
+
+// The width of the panel next to the board
+const PANEL_WIDTH: u16 = 26;
+// The lines above the board: the title and the layer
+const TOP_LINES: u16 = 2;
+
+// A full-screen view of the game: the board, on which a cursor picks the cells, and a panel
+// next to it with the turns so far and the last evaluation of the computer player. The
+// terminal is restored when the screen is closed or dropped.
+pub struct Screen {
+    view: RefCell<View>,
+    open: Cell<bool>,
+}
+
+// What is shown besides the board
+struct View {
+    cursor: Option<Coord>,
+    history: Vec<String>,
+    last_turn: Vec<Coord>,
+    // the number of the turn with the last evaluation and the evaluation
+    evaluation: Option<(usize, i32)>,
+    // the time each player took so far
+    clocks: [Duration; 2],
+    status: String,
+}
+
+// What the player did on the board
+pub enum Action {
+    // a symbol placed on a cell
+    Place(Coord, PlayerId),
+    Save,
+    Quit,
+}
+
+impl Screen {
+    pub fn open() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen {
+            view: RefCell::new(View {
+                cursor: None,
+                history: Vec::new(),
+                last_turn: Vec::new(),
+                evaluation: None,
+                clocks: [Duration::from_secs(0); 2],
+                status: String::new(),
+            }),
+            open: Cell::new(true),
+        })
+    }
+
+    // Gives the terminal back in the state it was before
+    pub fn close(&self) {
+        if self.open.replace(false) {
+            let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+
+    // Takes over the turns of the game and shows it with `status` below the board
+    pub fn show(&self, game: &Game, status: &str) -> io::Result<()> {
+        {
+            let mut view = self.view.borrow_mut();
+            view.history = history(&game.turns);
+            view.last_turn = game.turns.last().map_or(Vec::new(), |turn| {
+                turn.stones.iter().map(|stone| stone.coord.clone()).collect()
+            });
+            view.evaluation = game.turns.iter().enumerate().rev()
+                .find_map(|(number, turn)| turn.evaluation.map(|value| (number + 1, value)));
+            view.clocks = [game.clock(0), game.clock(1)];
+            view.status = status.to_string();
+        }
+        self.draw(&game.grid, false)
+    }
+
+    pub fn set_status(&self, status: &str) {
+        self.view.borrow_mut().status = status.to_string();
+    }
+
+    // Lets the player move the cursor until a cell is chosen. With the symbol choice of the
+    // wild variant the cell is taken with 'x' or 'o', otherwise with Enter or space.
+    pub fn select(&self, grid: &Grid, side: PlayerId) -> io::Result<Action> {
+        let layers = grid.layers();
+        loop {
+            let cursor = {
+                let mut view = self.view.borrow_mut();
+                let cursor = match view.cursor.take() {
+                    Some(ref cursor) if cursor.0.len() == grid.dimensions().len() => cursor.clone(),
+                    _ => centre(grid),
+                };
+                view.cursor = Some(cursor.clone());
+                cursor
+            };
+            self.draw(grid, true)?;
+            let code = match read_key()? {
+                Some(code) => code,
+                None => continue,
+            };
+            let layer = layers.iter()
+                .position(|layer| layer[..] == cursor.layer()[..])
+                .unwrap_or(0);
+            let moved = match code {
+                KeyCode::Up | KeyCode::Char('k') => step(grid, &cursor, -1, 0),
+                KeyCode::Down | KeyCode::Char('j') => step(grid, &cursor, 1, 0),
+                KeyCode::Left | KeyCode::Char('h') => step(grid, &cursor, 0, -1),
+                KeyCode::Right | KeyCode::Char('l') => step(grid, &cursor, 0, 1),
+                KeyCode::PageUp | KeyCode::Char('<') if layer > 0 =>
+                    Coord::in_layer(&layers[layer - 1], cursor.row(), cursor.column()),
+                KeyCode::PageDown | KeyCode::Char('>') if layer + 1 < layers.len() =>
+                    Coord::in_layer(&layers[layer + 1], cursor.row(), cursor.column()),
+                KeyCode::Enter | KeyCode::Char(' ') if !grid.variant.has_symbol_choice() =>
+                    return Ok(Action::Place(cursor, side)),
+                KeyCode::Char('x') if grid.variant.has_symbol_choice() =>
+                    return Ok(Action::Place(cursor, X)),
+                KeyCode::Char('o') if grid.variant.has_symbol_choice() =>
+                    return Ok(Action::Place(cursor, O)),
+                KeyCode::Char('s') => return Ok(Action::Save),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(Action::Quit),
+                _ => cursor,
+            };
+            self.view.borrow_mut().cursor = Some(moved);
+        }
+    }
+
+    // Shows `question` below the board and waits for one of the `answers`, or 'q' to leave
+    pub fn ask(&self, grid: &Grid, question: &str, answers: &[char]) -> io::Result<Option<char>> {
+        self.set_status(question);
+        loop {
+            self.draw(grid, false)?;
+            match read_key()? {
+                Some(KeyCode::Char('q')) | Some(KeyCode::Esc) => return Ok(None),
+                Some(KeyCode::Char(answer)) if answers.contains(&answer) => return Ok(Some(answer)),
+                _ => {},
+            }
+        }
+    }
+
+    // Shows the game one last time until a key is pressed
+    pub fn finish(&self, game: &Game, status: &str) -> io::Result<()> {
+        self.show(game, &format!("{} Press any key.", status))?;
+        while read_key()?.is_none() {
+            self.draw(&game.grid, false)?;
+        }
+        Ok(())
+    }
+
+    fn draw(&self, grid: &Grid, with_cursor: bool) -> io::Result<()> {
+        let view = self.view.borrow();
+        let mut out = io::stdout();
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        let (width, height) = terminal::size()?;
+        let (needed_width, needed_height) = needed_size(grid);
+        if width < needed_width || height < needed_height {
+            let message = format!("The terminal is too small, it needs {}x{}.",
+                                  needed_width, needed_height);
+            queue!(out, cursor::MoveTo(0, 0), Print(message))?;
+            return out.flush();
+        }
+
+        let layer = view.cursor.as_ref().map_or(Vec::new(), |cursor| cursor.layer().to_vec());
+        let layer = if layer.len() + 2 == grid.dimensions().len() {
+            layer
+        } else {
+            grid.layers().remove(0)
+        };
+        let winning: Vec<Coord> = grid_observer::winning_lines(grid).into_iter()
+            .flat_map(|line| line.cells)
+            .collect();
+        queue!(out, cursor::MoveTo(0, 0), SetAttribute(Attribute::Bold),
+               Print(format!("Tic Tac Toe: {} in a row", grid.to_win)),
+               SetAttribute(Attribute::Reset))?;
+        if !layer.is_empty() {
+            queue!(out, cursor::MoveTo(0, 1),
+                   Print(format!("Layer {} (PageUp and PageDown change it)",
+                                 Grid::layer_name(&layer))))?;
+        }
+
+        let width_of_label = label_width(grid);
+        let header = header_lines(grid);
+        for column in 0 .. grid.column_count {
+            let name = algebraic::column_name(column);
+            for (line, letter) in name.chars().enumerate() {
+                let x = width_of_label + 1 + 2 * column as u16;
+                queue!(out, cursor::MoveTo(x, TOP_LINES + line as u16), Print(letter))?;
+            }
+        }
+        for row in 0 .. grid.row_count {
+            let y = TOP_LINES + header + row as u16;
+            queue!(out, cursor::MoveTo(0, y),
+                   Print(format!("{:>1$}", row + 1, width_of_label as usize)))?;
+            for column in 0 .. grid.column_count {
+                let coord = Coord::in_layer(&layer, row, column);
+                let (x, _) = cell_position(grid, row, column);
+                let (text, colour) = match *grid.get_cell_at(&coord) {
+                    CellState::Unset => (".".to_string(), Color::DarkGrey),
+                    CellState::Blocked => ("#".to_string(), Color::DarkGrey),
+                    CellState::Set(symbol) => (grid.symbol_name(symbol), colour_of(symbol)),
+                };
+                queue!(out, cursor::MoveTo(x, y), SetForegroundColor(colour))?;
+                if view.last_turn.contains(&coord) {
+                    queue!(out, SetAttribute(Attribute::Bold),
+                           SetAttribute(Attribute::Underlined))?;
+                }
+                if winning.contains(&coord) {
+                    queue!(out, SetAttribute(Attribute::Reverse))?;
+                }
+                if with_cursor && view.cursor.as_ref() == Some(&coord) {
+                    queue!(out, SetBackgroundColor(Color::White))?;
+                }
+                queue!(out, Print(text), SetAttribute(Attribute::Reset))?;
+            }
+        }
+
+        let panel_x = board_width(grid) + 3;
+        let mut panel = vec![
+            match grid_observer::check_winner(grid) {
+                ::game::GameState::Mid => format!("Side {} to move", grid.to_move().0),
+                _ => "The game is over".to_string(),
+            },
+        ];
+        if grid.stones_left() > 1 {
+            panel.push(format!("{} stones left this turn", grid.stones_left()));
+        }
+        for (player, clock) in view.clocks.iter().enumerate() {
+            let seconds = clock.as_secs();
+            panel.push(format!("Player {} time: {}:{:02}", player + 1, seconds / 60, seconds % 60));
+        }
+        panel.push(match view.evaluation {
+            Some((turn, value)) => format!("Evaluation: {:+} (turn {})", value, turn),
+            None => "Evaluation: none yet".to_string(),
+        });
+        panel.push(String::new());
+        panel.push("Turns:".to_string());
+        let room = (height - 1) as usize - panel.len();
+        let skipped = view.history.len().saturating_sub(room);
+        panel.extend(view.history[skipped ..].iter().cloned());
+        for (line, text) in panel.iter().enumerate() {
+            let text: String = text.chars().take(PANEL_WIDTH as usize).collect();
+            queue!(out, cursor::MoveTo(panel_x, line as u16), Print(text))?;
+        }
+
+        let help = if !with_cursor {
+            ""
+        } else if grid.variant.has_symbol_choice() {
+            " Arrows move, x or o places, s saves, q leaves."
+        } else {
+            " Arrows move, Enter places, s saves, q leaves."
+        };
+        let status: String = format!("{}{}", view.status, help).chars()
+            .take(width as usize)
+            .collect();
+        queue!(out, cursor::MoveTo(0, height - 1), Print(status))?;
+        out.flush()
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// The next key pressed, or None if something else happened, like the terminal being resized
+fn read_key() -> io::Result<Option<KeyCode>> {
+    match event::read()? {
+        Event::Key(key) if key.kind == KeyEventKind::Press => {
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                Ok(Some(KeyCode::Char('q')))
+            } else {
+                Ok(Some(key.code))
+            }
+        },
+        _ => Ok(None),
+    }
+}
+
+fn colour_of(symbol: PlayerId) -> Color {
+    if symbol == X { Color::Cyan } else { Color::Red }
+}
+
+// The middle of the first layer
+fn centre(grid: &Grid) -> Coord {
+    Coord::in_layer(&grid.layers()[0], grid.row_count / 2, grid.column_count / 2)
+}
+
+// The cursor moved by the given rows and columns, staying on the board
+fn step(grid: &Grid, cursor: &Coord, rows: isize, columns: isize) -> Coord {
+    let clamp = |value: usize, by: isize, size: usize| {
+        (value as isize + by).max(0).min(size as isize - 1) as usize
+    };
+    Coord::in_layer(cursor.layer(), clamp(cursor.row(), rows, grid.row_count),
+                    clamp(cursor.column(), columns, grid.column_count))
+}
+
+// One line for every turn, like '3. b2 c3 (-12)'
+fn history(turns: &[RecordedTurn]) -> Vec<String> {
+    turns.iter().enumerate()
+        .map(|(number, turn)| {
+            let mut line = format!("{}.", number + 1);
+            for stone in &turn.stones {
+                line += " ";
+                line += &algebraic::format(&stone.coord);
+            }
+            if let Some(evaluation) = turn.evaluation {
+                line += &format!(" ({:+})", evaluation);
+            }
+            line
+        })
+        .collect()
+}
+
+fn label_width(grid: &Grid) -> u16 {
+    grid.row_count.to_string().len() as u16
+}
+
+// Longer column names are written downwards, one letter per line
+fn header_lines(grid: &Grid) -> u16 {
+    algebraic::column_name(grid.column_count.saturating_sub(1)).len() as u16
+}
+
+// Where a cell is drawn. Cells are two characters wide, the rows of a hex board are shifted
+// by one more character each.
+fn cell_position(grid: &Grid, row: usize, column: usize) -> (u16, u16) {
+    let shift = if grid.topology() == Topology::Hex { row } else { 0 };
+    ((label_width(grid) as usize + 1 + shift + 2 * column) as u16,
+     TOP_LINES + header_lines(grid) + row as u16)
+}
+
+fn board_width(grid: &Grid) -> u16 {
+    let shift = if grid.topology() == Topology::Hex { grid.row_count } else { 0 };
+    label_width(grid) + 1 + (2 * grid.column_count + shift) as u16
+}
+
+// The smallest terminal the board and the panel fit into
+fn needed_size(grid: &Grid) -> (u16, u16) {
+    let board_height = TOP_LINES + header_lines(grid) + grid.row_count as u16;
+    (board_width(grid) + 3 + PANEL_WIDTH, board_height.max(10) + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::Move;
+
+    #[test]
+    fn test_step_stays_on_the_board() {
+        let grid = Grid::new(3, 4, 3);
+        assert_eq!(Coord::new(0, 1), step(&grid, &Coord::new(0, 0), -1, 1));
+        assert_eq!(Coord::new(2, 3), step(&grid, &Coord::new(2, 3), 1, 1));
+        let cube = Grid::with_dimensions(vec![2, 3, 3], 3);
+        assert_eq!(Coord(vec![1, 1, 0]), step(&cube, &Coord(vec![1, 1, 1]), 0, -1));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut turns = vec![
+            RecordedTurn::new(vec![Move::new(1, 1, PlayerId(1))]),
+            RecordedTurn::new(vec![Move::new(0, 0, PlayerId(2)), Move::new(0, 2, PlayerId(2))]),
+        ];
+        turns[1].evaluation = Some(-12);
+        assert_eq!(vec!["1. b2".to_string(), "2. a1 c1 (-12)".to_string()], history(&turns));
+    }
+
+    #[test]
+    fn test_a_big_board_fits_in_a_normal_terminal() {
+        let grid = Grid::new(19, 19, 6);
+        assert_eq!((3, 3), cell_position(&grid, 0, 0));
+        let (width, height) = needed_size(&grid);
+        assert!(width <= 80 && height <= 24, "{}x{}", width, height);
+    }
+
+    #[test]
+    fn test_hex_rows_are_shifted() {
+        let grid = Grid::hexagon(3, 3);
+        assert_eq!(cell_position(&grid, 0, 1).0 + 1, cell_position(&grid, 1, 1).0);
+    }
+}
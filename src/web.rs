@@ -0,0 +1,637 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::{Message, WebSocket};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use ::engine::{empty_board, parse_turn};
+use ::game::{CellState, GameState, X};
+use ::game::algebraic;
+use ::game::grid::Grid;
+use ::game::grid_observer::check_winner_lines;
+use ::game::notation::{parse_notation, to_notation, variant_name};
+use ::game::record::{parse_stone, result_text, write_stone};
+use ::player::ki::{KiPlayer, Limits};
+
+// Games are kept in memory until they are deleted, up to this many
+const MAX_GAMES: usize = 10_000;
+// The biggest request body read, a game or a turn is much smaller
+const MAX_BODY: u64 = 64 * 1024;
+// How long the ki may think at the difficulty 'hard', if no time is given
+const HARD_MOVE_TIME_MS: u64 = 1000;
+// The most time and depth a request can give the ki. A search that is only given a depth gets
+// this time as well, and makes the best turn it found by then.
+const MAX_MOVE_TIME_MS: u64 = 10_000;
+const MAX_DEPTH: u32 = 9;
+// The biggest games hosted, so that the ki keeps to its time and no request holds up the others
+// for long
+const MAX_BOARD_CELLS: usize = 100 * 100;
+const MAX_TO_WIN: u32 = 10;
+
+// The body of POST /games: a position in the notation, or the parts of an empty board
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct NewGame {
+    position: Option<String>,
+    size: Option<Vec<usize>>,
+    to_win: Option<u32>,
+    variant: Option<String>,
+    options: Option<String>,
+}
+
+// The body of POST /games/<id>/moves
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewTurn {
+    stones: Vec<String>,
+}
+
+// The body of POST /games/<id>/ai-move, every field may be left out
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct AiRequest {
+    difficulty: Option<String>,
+    depth: Option<u32>,
+    movetime: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Stone {
+    cell: String,
+    symbol: &'static str,
+}
+
+// A game as the API shows it
+#[derive(Serialize)]
+struct GameView {
+    id: u64,
+    position: String,
+    dimensions: Vec<usize>,
+    to_win: u32,
+    variant: &'static str,
+    stones: Vec<Stone>,
+    blocked: Vec<String>,
+    to_move: u32,
+    stones_left: u32,
+    // the stones of every turn so far
+    turns: Vec<Vec<String>>,
+    result: String,
+    winner: Option<u32>,
+    winning_cells: Vec<String>,
+    // the score of the last ki turn, from the side that made it
+    evaluation: Option<i32>,
+}
+
+// What the WebSocket feed of a game sends: the whole game when the feed opens, then every turn
+// with the side that made it
+#[derive(Serialize)]
+struct FeedMessage<'a> {
+    event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    side: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stones: Vec<String>,
+    game: &'a GameView,
+}
+
+#[derive(Serialize)]
+struct ErrorView<'a> {
+    error: &'a str,
+}
+
+// The messages waiting for a WebSocket. The thread of the feed sends them, so a client that
+// doesn't read only holds up its own feed.
+type Feed = Sender<String>;
+
+struct HostedGame {
+    grid: Grid,
+    turns: Vec<Vec<String>>,
+    evaluation: Option<i32>,
+    feeds: Vec<Feed>,
+}
+
+impl HostedGame {
+    fn snapshot(&self, id: u64) -> Snapshot {
+        Snapshot {
+            id,
+            grid: self.grid.clone(),
+            turns: self.turns.clone(),
+            evaluation: self.evaluation,
+        }
+    }
+
+    // Queues the message for every feed, the ones that were closed are dropped
+    fn broadcast(&mut self, text: &str) {
+        self.feeds.retain(|feed| feed.send(text.to_string()).is_ok());
+    }
+}
+
+// A copy of a game. Looking for lines goes over the whole board, so it is done on the copy,
+// without keeping the other games waiting.
+struct Snapshot {
+    id: u64,
+    grid: Grid,
+    turns: Vec<Vec<String>>,
+    evaluation: Option<i32>,
+}
+
+impl Snapshot {
+    fn view(&self) -> GameView {
+        let grid = &self.grid;
+        let (state, lines) = check_winner_lines(grid);
+        let mut stones = Vec::new();
+        let mut blocked = Vec::new();
+        for index in 0 .. grid.cell_count() {
+            let coord = grid.coord_of(index);
+            match *grid.get_cell_at(&coord) {
+                CellState::Set(symbol) => stones.push(Stone {
+                    cell: algebraic::format(&coord),
+                    symbol: if symbol == X { "X" } else { "O" },
+                }),
+                CellState::Blocked => blocked.push(algebraic::format(&coord)),
+                CellState::Unset => {},
+            }
+        }
+        let winner = match state {
+            GameState::Win(side) => Some(side.0),
+            GameState::Scores(first, second) if first != second => {
+                Some(if first > second { 1 } else { 2 })
+            },
+            _ => None,
+        };
+        // lines that cross share cells
+        let mut winning_cells = Vec::new();
+        if let GameState::Win(_) = state {
+            for cell in lines.iter().flat_map(|line| line.cells.iter().map(algebraic::format)) {
+                if !winning_cells.contains(&cell) {
+                    winning_cells.push(cell);
+                }
+            }
+        }
+        GameView {
+            id: self.id,
+            position: to_notation(grid),
+            dimensions: grid.dimensions().to_vec(),
+            to_win: grid.to_win,
+            variant: variant_name(grid.variant),
+            stones,
+            blocked,
+            to_move: grid.to_move().0,
+            stones_left: grid.stones_left(),
+            turns: self.turns.clone(),
+            result: result_text(&state),
+            winner,
+            winning_cells,
+            evaluation: self.evaluation,
+        }
+    }
+
+    fn over(&self) -> bool {
+        check_winner_lines(&self.grid).0 != GameState::Mid
+    }
+
+    // The game after a whole turn given in cells, with the stones as they are written down
+    fn play(&self, stones: &[String]) -> Result<(Snapshot, Vec<String>), (u16, String)> {
+        if self.over() {
+            return Err((409, "The game is over.".to_string()));
+        }
+        let side = self.grid.to_move();
+        let words: Vec<&str> = stones.iter().map(String::as_str).collect();
+        let grid = parse_turn(&self.grid, &words).map_err(|error| (400, error))?;
+        let written: Vec<String> = words.iter()
+            .map(|stone| parse_stone(stone, side).map(|stone| write_stone(&stone)))
+            .collect::<Result<_, _>>()
+            .map_err(|error| (400, error))?;
+        let mut turns = self.turns.clone();
+        turns.push(written.clone());
+        Ok((Snapshot { id: self.id, grid, turns, evaluation: self.evaluation }, written))
+    }
+}
+
+// Many games at once, each under its number
+struct Service {
+    games: Mutex<HashMap<u64, HostedGame>>,
+    next_id: Mutex<u64>,
+}
+
+impl Service {
+    fn new() -> Service {
+        Service {
+            games: Mutex::new(HashMap::new()),
+            next_id: Mutex::new(1),
+        }
+    }
+
+    // Answers a request of the API with a status and a JSON body
+    fn route(&self, method: &Method, url: &str, body: &str) -> (u16, String) {
+        let path = url.split('?').next().unwrap_or("");
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        let result = match (method, &parts[..]) {
+            (&Method::Get, ["games"]) => Ok((200, self.list())),
+            (&Method::Post, ["games"]) if body.trim().is_empty() => self.create(NewGame::default()),
+            (&Method::Post, ["games"]) => parse(body).and_then(|request| self.create(request)),
+            (method, ["games", id, rest @ ..]) => match id.parse() {
+                Ok(id) => self.route_game(method, id, rest, body),
+                Err(_) => Err((404, format!("There is no game '{}'.", id))),
+            },
+            _ => Err((404, format!("There is nothing at '{}'.", path))),
+        };
+        match result {
+            Ok((status, body)) => (status, body),
+            Err((status, error)) => (status, json(&ErrorView { error: &error })),
+        }
+    }
+
+    fn route_game(&self, method: &Method, id: u64, rest: &[&str],
+                  body: &str) -> Result<(u16, String), (u16, String)> {
+        match (method, rest) {
+            (&Method::Get, []) => Ok((200, json(&self.snapshot(id)?.view()))),
+            (&Method::Delete, []) => {
+                // the feeds close once their queues are gone with the game
+                self.games.lock().unwrap().remove(&id)
+                    .ok_or_else(|| (404, format!("There is no game {}.", id)))?;
+                Ok((204, String::new()))
+            },
+            (&Method::Post, ["moves"]) => {
+                let turn: NewTurn = parse(body)?;
+                let game = self.snapshot(id)?;
+                let evaluation = game.evaluation;
+                self.play(&game, &turn.stones, evaluation)
+            },
+            (&Method::Post, ["ai-move"]) => {
+                let request = if body.trim().is_empty() {
+                    AiRequest::default()
+                } else {
+                    parse(body)?
+                };
+                self.ai_move(id, request)
+            },
+            (_, []) | (_, ["moves"]) | (_, ["ai-move"]) => {
+                Err((405, "This method isn't allowed here.".to_string()))
+            },
+            _ => Err((404, format!("There is nothing at '/games/{}/{}'.", id, rest.join("/")))),
+        }
+    }
+
+    // A copy of the game, the games are only held while it is made
+    fn snapshot(&self, id: u64) -> Result<Snapshot, (u16, String)> {
+        let games = self.games.lock().unwrap();
+        let game = games.get(&id).ok_or_else(|| (404, format!("There is no game {}.", id)))?;
+        Ok(game.snapshot(id))
+    }
+
+    // Plays a turn on the copy of a game and puts the result in its place, unless another turn
+    // was played in the meantime. Then the feeds hear of the turn.
+    fn play(&self, before: &Snapshot, stones: &[String],
+            evaluation: Option<i32>) -> Result<(u16, String), (u16, String)> {
+        let side = before.grid.to_move();
+        let (mut after, written) = before.play(stones)?;
+        after.evaluation = evaluation;
+        let view = after.view();
+        let body = json(&view);
+        let turn = FeedMessage { event: "turn", side: Some(side.0), stones: written, game: &view };
+        let turn = json(&turn);
+        let mut games = self.games.lock().unwrap();
+        let game = games.get_mut(&before.id)
+            .ok_or_else(|| (404, format!("There is no game {}.", before.id)))?;
+        if game.turns.len() != before.turns.len() {
+            return Err((409, "Another turn was played in the meantime.".to_string()));
+        }
+        game.grid = after.grid;
+        game.turns = after.turns;
+        game.evaluation = after.evaluation;
+        game.broadcast(&turn);
+        Ok((200, body))
+    }
+
+    fn list(&self) -> String {
+        let games = self.games.lock().unwrap();
+        let mut ids: Vec<u64> = games.keys().cloned().collect();
+        ids.sort_unstable();
+        json(&ids)
+    }
+
+    fn create(&self, request: NewGame) -> Result<(u16, String), (u16, String)> {
+        let grid = match request.position {
+            Some(ref position) => parse_notation(position),
+            None => empty_board(request.size.as_deref().unwrap_or(&[3, 3]),
+                                request.to_win.unwrap_or(3),
+                                request.variant.as_deref().unwrap_or("standard"),
+                                request.options.as_deref().unwrap_or("")),
+        }.map_err(|error| (400, error))?;
+        if !grid.patterns.is_empty() {
+            return Err((400, "Patterns can't be described in the notation.".to_string()));
+        }
+        if grid.cell_count() > MAX_BOARD_CELLS {
+            return Err((400, format!("A board here has {} cells at most.", MAX_BOARD_CELLS)));
+        }
+        if grid.to_win > MAX_TO_WIN {
+            return Err((400, format!("A line here has {} cells at most.", MAX_TO_WIN)));
+        }
+        let game = HostedGame {
+            grid: grid.clone(),
+            turns: Vec::new(),
+            evaluation: None,
+            feeds: Vec::new(),
+        };
+        let id = {
+            let mut games = self.games.lock().unwrap();
+            if games.len() >= MAX_GAMES {
+                return Err((503, "There are too many games, delete some first.".to_string()));
+            }
+            let mut next_id = self.next_id.lock().unwrap();
+            let id = *next_id;
+            *next_id += 1;
+            games.insert(id, game);
+            id
+        };
+        let created = Snapshot { id, grid, turns: Vec::new(), evaluation: None };
+        Ok((201, json(&created.view())))
+    }
+
+    // Lets the ki make the turn of the side to move. The search runs on a copy of the game, so
+    // a turn played in the meantime makes it fail.
+    fn ai_move(&self, id: u64, request: AiRequest) -> Result<(u16, String), (u16, String)> {
+        let limits = search_limits(&request)?;
+        let game = self.snapshot(id)?;
+        if game.over() {
+            return Err((409, "The game is over.".to_string()));
+        }
+        let side = game.grid.to_move();
+        let ki = KiPlayer::new(side.0);
+        let turn = ki.find_turn_within(&game.grid, side, &limits)
+            .ok_or_else(|| (409, "There is no turn to make.".to_string()))?;
+        let stones: Vec<String> = turn.iter().map(write_stone).collect();
+        self.play(&game, &stones, ::player::Player::evaluation(&ki))
+    }
+
+    // Opens a feed of the game, which starts with the game as it is. Gives back the messages
+    // for the WebSocket, or None if there is no such game.
+    fn subscribe(&self, id: u64) -> Option<Receiver<String>> {
+        loop {
+            let game = self.snapshot(id).ok()?;
+            let view = game.view();
+            let state = FeedMessage { event: "state", side: None, stones: Vec::new(), game: &view };
+            let state = json(&state);
+            let mut games = self.games.lock().unwrap();
+            let hosted = games.get_mut(&id)?;
+            // a turn played while the view was made would be missing from the feed
+            if hosted.turns.len() == game.turns.len() {
+                let (feed, messages) = mpsc::channel();
+                feed.send(state).ok()?;
+                hosted.feeds.push(feed);
+                return Some(messages);
+            }
+        }
+    }
+}
+
+// The limits of the search the request asks for, within the ones of the service
+fn search_limits(request: &AiRequest) -> Result<Limits, (u16, String)> {
+    let mut limits = match request.difficulty.as_deref() {
+        Some("easy") => Limits { depth: Some(1), ..Limits::default() },
+        Some("medium") => Limits { depth: Some(2), ..Limits::default() },
+        Some("hard") | None => Limits {
+            deadline: Some(Instant::now() + Duration::from_millis(HARD_MOVE_TIME_MS)),
+            ..Limits::default()
+        },
+        Some(other) => {
+            let error = format!("'{}' is not a difficulty, use easy, medium or hard.", other);
+            return Err((400, error));
+        },
+    };
+    if let Some(depth) = request.depth {
+        limits.depth = Some(depth.clamp(1, MAX_DEPTH));
+    }
+    if let Some(movetime) = request.movetime {
+        let movetime = Duration::from_millis(movetime.min(MAX_MOVE_TIME_MS));
+        limits.deadline = Some(Instant::now() + movetime);
+    }
+    if limits.deadline.is_none() {
+        limits.deadline = Some(Instant::now() + Duration::from_millis(MAX_MOVE_TIME_MS));
+    }
+    Ok(limits)
+}
+
+fn json<T: ::serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn parse<'a, T: ::serde::Deserialize<'a>>(body: &'a str) -> Result<T, (u16, String)> {
+    serde_json::from_str(body).map_err(|error| (400, format!("The request is invalid: {}", error)))
+}
+
+// Serves the API on the address, like '0.0.0.0:8080', until the program ends. Every request
+// gets a thread of its own, so a long search doesn't hold up the other games.
+pub fn serve(address: &str) -> Result<(), String> {
+    let server = Server::http(address)
+        .map_err(|error| format!("Can't serve on {}: {}", address, error))?;
+    run(server);
+    Ok(())
+}
+
+fn run(server: Server) {
+    let service = Arc::new(Service::new());
+    for request in server.incoming_requests() {
+        let service = service.clone();
+        thread::spawn(move || handle(&service, request));
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("a valid header")
+}
+
+fn handle(service: &Service, mut request: Request) {
+    let value = |name: &'static str| request.headers().iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_string());
+    let upgrade = value("Upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+    let key = value("Sec-WebSocket-Key");
+    let parts: Vec<String> = request.url().split('?').next().unwrap_or("").split('/')
+        .filter(|part| !part.is_empty()).map(String::from).collect();
+    // GET /games/<id>/feed with an upgrade opens the WebSocket feed
+    if let (true, Some(key), [games, id, feed]) = (upgrade, key, &parts[..]) {
+        if games == "games" && feed == "feed" && *request.method() == Method::Get {
+            if let Ok(id) = id.parse() {
+                let accept = derive_accept_key(key.as_bytes());
+                let response = Response::empty(101)
+                    .with_header(header("Upgrade", "websocket"))
+                    .with_header(header("Connection", "Upgrade"))
+                    .with_header(header("Sec-WebSocket-Accept", &accept));
+                let stream = request.upgrade("websocket", response);
+                let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+                // this thread sends the messages of the feed until the game is deleted or the
+                // client is gone
+                for message in service.subscribe(id).into_iter().flatten() {
+                    if socket.send(Message::Text(message)).is_err() {
+                        break;
+                    }
+                }
+                let _ = socket.close(None);
+                let _ = socket.flush();
+                return;
+            }
+        }
+    }
+    let cors = header("Access-Control-Allow-Origin", "*");
+    if *request.method() == Method::Options {
+        let _ = request.respond(Response::empty(204)
+            .with_header(cors)
+            .with_header(header("Access-Control-Allow-Methods", "GET, POST, DELETE, OPTIONS"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type")));
+        return;
+    }
+    let mut body = String::new();
+    let (status, answer) = match request.as_reader().take(MAX_BODY).read_to_string(&mut body) {
+        Ok(_) => service.route(request.method(), request.url(), &body),
+        Err(_) => (400, json(&ErrorView { error: "The body is not UTF-8." })),
+    };
+    let _ = request.respond(Response::from_string(answer)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(cors));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpStream;
+    use serde_json::Value;
+
+    fn call(service: &Service, method: Method, url: &str, body: &str) -> (u16, Value) {
+        let (status, body) = service.route(&method, url, body);
+        (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+    }
+
+    fn post(service: &Service, url: &str, body: &str) -> (u16, Value) {
+        call(service, Method::Post, url, body)
+    }
+
+    // The status with the message of an error
+    fn error(answer: &(u16, Value)) -> (u16, &str) {
+        (answer.0, answer.1["error"].as_str().unwrap())
+    }
+
+    #[test]
+    fn test_create_and_play() {
+        let service = Service::new();
+        let (status, game) = post(&service, "/games", r#"{"size": [3, 3], "to_win": 3}"#);
+        assert_eq!(201, status);
+        assert_eq!(1, game["id"]);
+        assert_eq!("3x3 3 standard 3/3/3 1", game["position"]);
+        for cell in ["b2", "a1", "b1", "a3"] {
+            let body = format!(r#"{{"stones": ["{}"]}}"#, cell);
+            let (status, _) = post(&service, "/games/1/moves", &body);
+            assert_eq!(200, status);
+        }
+        let (status, game) = post(&service, "/games/1/moves", r#"{"stones": ["b3"]}"#);
+        assert_eq!(200, status);
+        assert_eq!("1-0", game["result"]);
+        assert_eq!(1, game["winner"]);
+        assert_eq!(serde_json::json!(["b1", "b2", "b3"]), game["winning_cells"]);
+        assert_eq!(serde_json::json!([["b2"], ["a1"], ["b1"], ["a3"], ["b3"]]), game["turns"]);
+        let answer = post(&service, "/games/1/moves", r#"{"stones": ["c1"]}"#);
+        assert_eq!((409, "The game is over."), error(&answer));
+        assert_eq!(serde_json::json!([1]), call(&service, Method::Get, "/games", "").1);
+    }
+
+    #[test]
+    fn test_errors() {
+        let service = Service::new();
+        post(&service, "/games", r#"{"position": "3x3 3 standard x2/3/3 2"}"#);
+        let answer = post(&service, "/games/1/moves", r#"{"stones": ["a1"]}"#);
+        assert_eq!((400, "a1 is not free."), error(&answer));
+        assert_eq!(400, post(&service, "/games/1/moves", r#"{"cells": ["a1"]}"#).0);
+        assert_eq!(400, post(&service, "/games", r#"{"size": [0, 3]}"#).0);
+        let answer = post(&service, "/games", r#"{"size": [100000, 100000]}"#);
+        assert_eq!((400, "A board can have 1048576 cells at most."), error(&answer));
+        let answer = post(&service, "/games", r#"{"size": [1024, 1024]}"#);
+        assert_eq!((400, "A board here has 10000 cells at most."), error(&answer));
+        let answer = post(&service, "/games", r#"{"size": [19, 19], "to_win": 11}"#);
+        assert_eq!((400, "A line here has 10 cells at most."), error(&answer));
+        assert_eq!(404, call(&service, Method::Get, "/games/7", "").0);
+        assert_eq!(404, call(&service, Method::Get, "/players", "").0);
+        assert_eq!(405, call(&service, Method::Put, "/games/1", "").0);
+        let answer = post(&service, "/games/1/ai-move", r#"{"difficulty": "godlike"}"#);
+        let message = "'godlike' is not a difficulty, use easy, medium or hard.";
+        assert_eq!((400, message), error(&answer));
+        assert_eq!(204, service.route(&Method::Delete, "/games/1", "").0);
+        assert_eq!(404, call(&service, Method::Get, "/games/1", "").0);
+    }
+
+    #[test]
+    fn test_ai_move() {
+        let service = Service::new();
+        post(&service, "/games", r#"{"position": "3x3 3 standard xx1/oo1/3 1"}"#);
+        let (status, game) = post(&service, "/games/1/ai-move", r#"{"difficulty": "easy"}"#);
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!([["c1"]]), game["turns"]);
+        assert_eq!("1-0", game["result"]);
+        assert!(game["evaluation"].as_i64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_search_limits() {
+        let latest = || Instant::now() + Duration::from_millis(MAX_MOVE_TIME_MS);
+        let request = AiRequest { difficulty: Some("easy".to_string()), ..AiRequest::default() };
+        let limits = search_limits(&request).unwrap();
+        assert_eq!(Some(1), limits.depth);
+        // even a search to a depth has to end
+        assert!(limits.deadline.unwrap() <= latest());
+        let request = AiRequest {
+            depth: Some(1000),
+            movetime: Some(u64::MAX),
+            ..AiRequest::default()
+        };
+        let limits = search_limits(&request).unwrap();
+        assert_eq!(Some(MAX_DEPTH), limits.depth);
+        assert!(limits.deadline.unwrap() <= latest());
+        let request = AiRequest { depth: Some(0), movetime: Some(5000), ..AiRequest::default() };
+        let limits = search_limits(&request).unwrap();
+        assert_eq!(Some(1), limits.depth);
+        let hard = Duration::from_millis(HARD_MOVE_TIME_MS);
+        assert!(limits.deadline.unwrap() > Instant::now() + hard);
+    }
+
+    // Sends a request over HTTP and gives back the status line
+    fn http(address: &str, method: &str, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n", method, path,
+               body.len()).unwrap();
+        write!(stream, "Connection: close\r\n\r\n{}", body).unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+
+    #[test]
+    fn test_feed_gets_every_turn() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let address = server.server_addr().to_ip().unwrap().to_string();
+        thread::spawn(move || run(server));
+        assert_eq!("HTTP/1.1 201 Created", http(&address, "POST", "/games", r#"{"size": [3, 3]}"#));
+        let stream = TcpStream::connect(&address).unwrap();
+        let url = format!("ws://{}/games/1/feed", address);
+        let (mut feed, _) = tungstenite::client(url, stream).unwrap();
+        let mut next = || -> Value {
+            serde_json::from_str(&feed.read().unwrap().into_text().unwrap()).unwrap()
+        };
+        assert_eq!("state", next()["event"]);
+        http(&address, "POST", "/games/1/moves", r#"{"stones": ["b2"]}"#);
+        let turn = next();
+        assert_eq!("turn", turn["event"]);
+        assert_eq!(1, turn["side"]);
+        assert_eq!(serde_json::json!(["b2"]), turn["stones"]);
+        assert_eq!("3x3 3 standard 3/1x1/3 2", turn["game"]["position"]);
+        http(&address, "DELETE", "/games/1", "");
+        // the feed of a deleted game is closed
+        assert!(matches!(feed.read(), Ok(Message::Close(_)) | Err(_)));
+    }
+}